@@ -0,0 +1,696 @@
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, fmt::Display, str::FromStr};
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Serialize, Deserialize, Ord, PartialOrd)]
+pub struct Username(String);
+
+impl From<String> for Username {
+    fn from(s: String) -> Self {
+        Username(s)
+    }
+}
+
+impl From<Username> for String {
+    fn from(u: Username) -> Self {
+        u.0
+    }
+}
+
+impl Display for Username {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, Serialize, Deserialize)]
+pub struct Coord(pub u16, pub u16);
+
+impl PartialOrd for Coord {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        if self.0 < other.0 && self.1 < other.1 {
+            Some(Ordering::Less)
+        } else if self.0 == other.0 && self.1 == other.1 {
+            Some(Ordering::Equal)
+        } else {
+            Some(Ordering::Greater)
+        }
+    }
+}
+
+impl Coord {
+    pub fn within(&self, a: &Coord, b: &Coord) -> bool {
+        self > a.min(b) && self < a.max(b)
+    }
+}
+
+impl From<(i16, i16)> for Coord {
+    fn from(x: (i16, i16)) -> Self {
+        Coord(x.0 as u16, x.1 as u16)
+    }
+}
+impl From<Coord> for (i16, i16) {
+    fn from(c: Coord) -> Self {
+        (c.0 as i16, c.1 as i16)
+    }
+}
+
+/// the biggest brush size a line can be drawn with
+pub const MAX_BRUSH_SIZE: u16 = 9;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Brush {
+    Paint(CanvasColor),
+    Erase,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Line {
+    pub start: Coord,
+    pub end: Coord,
+    pub brush: Brush,
+    /// width and height of the square brush the line is drawn with
+    pub size: u16,
+}
+
+impl Line {
+    pub fn new(start: Coord, end: Coord, color: CanvasColor) -> Self {
+        Line {
+            start,
+            end,
+            brush: Brush::Paint(color),
+            size: 1,
+        }
+    }
+
+    pub fn erase(start: Coord, end: Coord, size: u16) -> Self {
+        Line {
+            start,
+            end,
+            brush: Brush::Erase,
+            size,
+        }
+    }
+
+    /// the part of the line inside the given canvas dimensions, going in the same direction,
+    /// with the brush size limited. `None` if the line is outside of the canvas entirely.
+    pub fn clipped(&self, (width, height): (usize, usize)) -> Option<Line> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let (x, y) = (self.start.0 as f64, self.start.1 as f64);
+        let (dx, dy) = (self.end.0 as f64 - x, self.end.1 as f64 - y);
+        // the points start + t * (dx, dy) with t between t_start and t_end are on the canvas.
+        // Coordinates can't be negative, so only the right and bottom edge cut the line off.
+        let (mut t_start, mut t_end) = (0.0, 1.0);
+        let edges = [(x, dx, (width - 1) as f64), (y, dy, (height - 1) as f64)];
+        for &(from, delta, max) in &edges {
+            if delta > 0.0 {
+                t_end = f64::min(t_end, (max - from) / delta);
+            } else if delta < 0.0 {
+                t_start = f64::max(t_start, (max - from) / delta);
+            } else if from > max {
+                return None;
+            }
+        }
+        if t_start > t_end {
+            return None;
+        }
+        let point_at = |t: f64| Coord((x + t * dx).round() as u16, (y + t * dy).round() as u16);
+        Some(Line {
+            start: point_at(t_start),
+            end: point_at(t_end),
+            size: self.size.clamp(1, MAX_BRUSH_SIZE),
+            ..*self
+        })
+    }
+
+    /// how many points long the line is, not counting the brush size
+    pub fn point_count(&self) -> usize {
+        let dx = (self.start.0 as i32 - self.end.0 as i32).abs();
+        let dy = (self.start.1 as i32 - self.end.1 as i32).abs();
+        dx.max(dy) as usize + 1
+    }
+
+    pub fn coords_in(&self) -> Vec<Coord> {
+        let points = line_drawing::Bresenham::new(self.start.into(), self.end.into());
+        if self.size <= 1 {
+            return points.map(Coord::from).collect();
+        }
+        let size = self.size as i16;
+        let mut coords = points
+            .flat_map(|(x, y)| {
+                (0..size).flat_map(move |dx| (0..size).map(move |dy| (x + dx, y + dy)))
+            })
+            .map(|(x, y)| (x - size / 2, y - size / 2))
+            .filter(|(x, y)| *x >= 0 && *y >= 0)
+            .map(Coord::from)
+            .collect::<Vec<_>>();
+        // Coord's PartialOrd is not a total order, so sort by an explicit key
+        coords.sort_unstable_by_key(|c| (c.1, c.0));
+        coords.dedup();
+        coords
+    }
+}
+
+/// the longest chat message the server accepts, in characters
+pub const MAX_MESSAGE_LENGTH: usize = 200;
+
+/// the longest username the server accepts, in characters
+pub const MAX_USERNAME_LENGTH: usize = 20;
+
+/// names that would make a player look like the server
+const RESERVED_USERNAMES: [&str; 3] = ["system", "server", "admin"];
+
+/// strip characters that can't be seen or mess up the terminal from a username,
+/// returning the reason if the name can't be used at all.
+/// The client does the same, so both agree on the name.
+pub fn sanitize_username(name: &str) -> Result<Username, &'static str> {
+    let name = name
+        .chars()
+        .filter(|c| !c.is_control() && !is_zero_width(*c))
+        .collect::<String>();
+    let name = name.trim();
+    if name.is_empty() {
+        Err("Your name can't be empty")
+    } else if name.chars().count() > MAX_USERNAME_LENGTH {
+        Err("Your name can't be longer than 20 characters")
+    } else if RESERVED_USERNAMES
+        .iter()
+        .any(|reserved| name.eq_ignore_ascii_case(reserved))
+    {
+        Err("That name is reserved")
+    } else {
+        Ok(Username(name.to_string()))
+    }
+}
+
+fn is_zero_width(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}'..='\u{200F}' | '\u{2060}'..='\u{2064}' | '\u{FEFF}' | '\u{00AD}'
+    )
+}
+
+/// a checksum of the canvas, used to find out if a client missed some lines.
+/// This is FNV-1a over the fields of the lines, so it's the same for every build.
+pub fn canvas_checksum(lines: &[Line]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for line in lines {
+        let brush = match line.brush {
+            Brush::Paint(color) => color as u16,
+            Brush::Erase => u16::MAX,
+        };
+        let fields = [
+            line.start.0,
+            line.start.1,
+            line.end.0,
+            line.end.1,
+            brush,
+            line.size,
+        ];
+        for byte in fields.iter().flat_map(|field| field.to_le_bytes().to_vec()) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// the color of every cell of a canvas, stored row by row.
+/// It is serialized as runs of equally colored cells, as most of a drawing is empty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "CompactGrid", from = "CompactGrid")]
+pub struct CanvasGrid {
+    width: usize,
+    height: usize,
+    cells: Vec<Option<CanvasColor>>,
+}
+
+impl CanvasGrid {
+    pub fn new((width, height): (usize, usize)) -> Self {
+        CanvasGrid {
+            width,
+            height,
+            cells: vec![None; width * height],
+        }
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn index(&self, Coord(x, y): Coord) -> Option<usize> {
+        let (x, y) = (x as usize, y as usize);
+        if x < self.width && y < self.height {
+            Some(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, coord: Coord) -> Option<CanvasColor> {
+        self.index(coord).and_then(|idx| self.cells[idx])
+    }
+
+    /// paint a cell, ignoring cells outside of the canvas
+    pub fn insert(&mut self, coord: Coord, color: CanvasColor) {
+        if let Some(idx) = self.index(coord) {
+            self.cells[idx] = Some(color);
+        }
+    }
+
+    pub fn remove(&mut self, coord: Coord) {
+        if let Some(idx) = self.index(coord) {
+            self.cells[idx] = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.iter_mut().for_each(|cell| *cell = None);
+    }
+
+    /// paint or erase the cells a line covers
+    pub fn draw_line(&mut self, line: &Line) {
+        for coord in line.coords_in() {
+            match line.brush {
+                Brush::Paint(color) => self.insert(coord, color),
+                Brush::Erase => self.remove(coord),
+            }
+        }
+    }
+
+    /// a smaller version of the grid that fits into `max_size`, for previews of drawings.
+    /// Every cell of it gets the most common color of the cells it covers, if at least a quarter of them is painted.
+    pub fn downsample(&self, (max_width, max_height): (usize, usize)) -> CanvasGrid {
+        let factor = usize::max(
+            1,
+            usize::max(
+                self.width.div_ceil(max_width.max(1)),
+                self.height.div_ceil(max_height.max(1)),
+            ),
+        );
+        let mut small =
+            CanvasGrid::new((self.width.div_ceil(factor), self.height.div_ceil(factor)));
+        for y in 0..small.height {
+            for x in 0..small.width {
+                let mut counts: Vec<(CanvasColor, usize)> = Vec::new();
+                for (dx, dy) in (0..factor).flat_map(|dx| (0..factor).map(move |dy| (dx, dy))) {
+                    let coord = Coord((x * factor + dx) as u16, (y * factor + dy) as u16);
+                    if let Some(color) = self.get(coord) {
+                        match counts.iter_mut().find(|(c, _)| *c == color) {
+                            Some((_, count)) => *count += 1,
+                            None => counts.push((color, 1)),
+                        }
+                    }
+                }
+                let painted: usize = counts.iter().map(|(_, count)| count).sum();
+                let most_common = counts.iter().max_by_key(|(_, count)| *count);
+                if let (Some((color, _)), true) = (most_common, painted * 4 >= factor * factor) {
+                    small.insert(Coord(x as u16, y as u16), *color);
+                }
+            }
+        }
+        small
+    }
+
+    /// all painted cells, row by row
+    pub fn painted_cells(&self) -> impl Iterator<Item = (Coord, CanvasColor)> + '_ {
+        let width = self.width;
+        self.cells
+            .iter()
+            .enumerate()
+            .filter_map(move |(idx, cell)| {
+                cell.map(|color| (Coord((idx % width) as u16, (idx / width) as u16), color))
+            })
+    }
+}
+
+/// how a `CanvasGrid` gets serialized: the dimensions, and the cells as (length, color) runs
+#[derive(Serialize, Deserialize)]
+struct CompactGrid {
+    width: usize,
+    height: usize,
+    runs: Vec<(usize, Option<CanvasColor>)>,
+}
+
+impl From<CanvasGrid> for CompactGrid {
+    fn from(grid: CanvasGrid) -> Self {
+        let mut runs: Vec<(usize, Option<CanvasColor>)> = Vec::new();
+        for cell in grid.cells {
+            match runs.last_mut() {
+                Some((len, color)) if *color == cell => *len += 1,
+                _ => runs.push((1, cell)),
+            }
+        }
+        CompactGrid {
+            width: grid.width,
+            height: grid.height,
+            runs,
+        }
+    }
+}
+
+impl From<CompactGrid> for CanvasGrid {
+    fn from(compact: CompactGrid) -> Self {
+        let size = compact.width * compact.height;
+        let mut cells = compact
+            .runs
+            .into_iter()
+            .flat_map(|(len, color)| vec![color; len.min(size)])
+            .take(size)
+            .collect::<Vec<_>>();
+        // don't trust the runs to add up to the right size
+        cells.resize(size, None);
+        CanvasGrid {
+            width: compact.width,
+            height: compact.height,
+            cells,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Message {
+    SystemMsg(String),
+    UserMsg(Username, String),
+    /// something that happened in the game. Clients that didn't ask for events get
+    /// them as a `SystemMsg` with the events text instead.
+    Event(GameEvent),
+}
+
+/// how close a guess was to the word, without giving away the word itself
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Closeness {
+    /// one typo away from the word
+    VeryClose,
+    /// two typos away from the word
+    Close,
+    /// the word is part of the guess
+    ContainsWord,
+}
+
+/// the game events clients get told about in the chat
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameEvent {
+    Joined(Username),
+    Left(Username),
+    /// the player guessed the word
+    Guessed(Username),
+    /// only sent to the player whose guess was close
+    CloseGuess(Closeness),
+    /// the word at the end of a turn, with everyone who guessed it and how many seconds they took
+    WordRevealed {
+        word: String,
+        definition: Option<String>,
+        solve_times: Vec<(Username, u32)>,
+    },
+    /// the drawing player left, so their turn was skipped
+    TurnSkipped(Username),
+    NewLeader(Username),
+}
+
+impl Message {
+    /// the text a player or the server wrote. Events don't have one, use `to_string` for those.
+    pub fn text(&self) -> &str {
+        match self {
+            Message::SystemMsg(msg) => &msg,
+            Message::UserMsg(_, msg) => &msg,
+            Message::Event(_) => "",
+        }
+    }
+
+    pub fn is_system(&self) -> bool {
+        matches!(self, Message::SystemMsg(_) | Message::Event(_))
+    }
+
+    pub fn username(&self) -> Option<&Username> {
+        match self {
+            Message::UserMsg(username, _) => Some(username),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Message::SystemMsg(msg) => write!(f, "{}", msg),
+            Message::UserMsg(user, msg) => write!(f, "{}: {}", user, msg),
+            Message::Event(event) => write!(f, "{}", event),
+        }
+    }
+}
+
+impl Display for Closeness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Closeness::VeryClose => write!(f, "You're very close!"),
+            Closeness::Close => write!(f, "You're close!"),
+            Closeness::ContainsWord => write!(f, "The word is part of your guess!"),
+        }
+    }
+}
+
+/// how the event reads as text in the chat
+impl Display for GameEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameEvent::Joined(user) => write!(f, "{} joined", user),
+            GameEvent::Left(user) => write!(f, "{} left", user),
+            GameEvent::Guessed(user) => write!(f, "{} guessed it!", user),
+            GameEvent::CloseGuess(closeness) => write!(f, "{}", closeness),
+            GameEvent::WordRevealed {
+                word,
+                definition,
+                solve_times,
+            } => {
+                write!(f, "The word was: \"{}\"", word)?;
+                for (idx, (user, secs)) in solve_times.iter().enumerate() {
+                    if idx == 0 {
+                        write!(f, " ({} guessed it in {}s", user, secs)?;
+                    } else {
+                        write!(f, ", {} in {}s", user, secs)?;
+                    }
+                }
+                if !solve_times.is_empty() {
+                    write!(f, ")")?;
+                }
+                if let Some(definition) = definition {
+                    write!(f, " - {}", definition)?;
+                }
+                Ok(())
+            }
+            GameEvent::TurnSkipped(user) => write!(f, "{} left, skipping their turn", user),
+            GameEvent::NewLeader(user) => write!(f, "{} is now the leader", user),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CanvasColor {
+    White,
+    Gray,
+    DarkGray,
+    Black,
+    Red,
+    LightRed,
+    Green,
+    LightGreen,
+    Blue,
+    LightBlue,
+    Yellow,
+    LightYellow,
+    Cyan,
+    LightCyan,
+    Magenta,
+    LightMagenta,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    /// percentage of the normal score players get for guessing or drawing a word
+    pub fn score_percentage(self) -> u32 {
+        match self {
+            Difficulty::Easy => 75,
+            Difficulty::Medium => 100,
+            Difficulty::Hard => 150,
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("Unknown difficulty {}", s)),
+        }
+    }
+}
+
+impl Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difficulty::Easy => write!(f, "easy"),
+            Difficulty::Medium => write!(f, "medium"),
+            Difficulty::Hard => write!(f, "hard"),
+        }
+    }
+}
+
+/// how the next drawing player is chosen
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TurnRotation {
+    /// every player draws once per round, in a fixed order
+    InOrder,
+    /// a random player other than the current one draws next
+    Random,
+    /// the first player to guess the word draws next
+    Winner,
+}
+
+impl FromStr for TurnRotation {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "order" => Ok(TurnRotation::InOrder),
+            "random" => Ok(TurnRotation::Random),
+            "winner" => Ok(TurnRotation::Winner),
+            _ => Err(format!("Unknown turn rotation {}", s)),
+        }
+    }
+}
+
+impl Display for TurnRotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TurnRotation::InOrder => write!(f, "order"),
+            TurnRotation::Random => write!(f, "random"),
+            TurnRotation::Winner => write!(f, "winner"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CommandMsg {
+    KickPlayer(Username),
+    SetDimensions((usize, usize)),
+    /// choose which difficulties the words of new games are picked from
+    SetDifficulties(Vec<Difficulty>),
+    /// choose the language of the words of new games, or `None` for all languages
+    SetLanguage(Option<String>),
+    /// choose the least and the most characters the words of new games have
+    SetWordLengths((usize, usize)),
+    /// choose how the drawing player is picked in new games
+    SetTurnRotation(TurnRotation),
+    /// choose whether players vote for the best drawing when a game ends
+    SetDrawingVote(bool),
+    /// choose how many seconds the turns of new games last
+    SetRoundDuration(u64),
+    /// choose whether the other players see where the drawing player's pen is
+    SetPenSharing(bool),
+    /// clear the canvas, e.g. because something inappropriate was drawn,
+    /// and with `skip_turn`, go on with the next turn without any points for the drawing player
+    ModerateCanvas {
+        skip_turn: bool,
+    },
+    /// end the running game
+    EndGame,
+    /// add up the scores of this many games to find the winner of a series,
+    /// or `None` to cancel the running series
+    SetSeries(Option<u32>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_serialization_roundtrip() {
+        let mut grid = CanvasGrid::new((10, 4));
+        grid.draw_line(&Line {
+            start: Coord(1, 1),
+            end: Coord(6, 1),
+            brush: Brush::Paint(CanvasColor::Blue),
+            size: 1,
+        });
+        grid.insert(Coord(9, 3), CanvasColor::Red);
+        // outside of the canvas, so ignored
+        grid.insert(Coord(10, 0), CanvasColor::Red);
+
+        let json = serde_json::to_string(&grid).unwrap();
+        let parsed: CanvasGrid = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, grid);
+        assert_eq!(parsed.get(Coord(3, 1)), Some(CanvasColor::Blue));
+        assert_eq!(parsed.get(Coord(3, 2)), None);
+        assert_eq!(parsed.painted_cells().count(), 7);
+    }
+
+    #[test]
+    fn downsampled_grid_fits() {
+        let mut grid = CanvasGrid::new((100, 50));
+        grid.draw_line(&Line {
+            start: Coord(0, 0),
+            end: Coord(99, 0),
+            brush: Brush::Paint(CanvasColor::Red),
+            size: 2,
+        });
+        let small = grid.downsample((40, 20));
+        assert_eq!(small.dimensions(), (34, 17));
+        assert_eq!(small.get(Coord(10, 0)), Some(CanvasColor::Red));
+        assert_eq!(small.get(Coord(10, 1)), None);
+    }
+
+    #[test]
+    fn usernames_are_sanitized() {
+        assert_eq!(
+            sanitize_username(" al\u{200B}ice\u{7}\n"),
+            Ok(Username::from("alice".to_string()))
+        );
+        assert!(sanitize_username("\u{200B}\u{FEFF}").is_err());
+        assert!(sanitize_username("System").is_err());
+        assert!(sanitize_username(&"a".repeat(MAX_USERNAME_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn lines_are_clipped_to_the_canvas() {
+        let inside = Line::new(Coord(1, 2), Coord(8, 3), CanvasColor::Red);
+        assert_eq!(inside.clipped((10, 10)), Some(inside));
+
+        // the slope stays the same, instead of both ends being moved onto the canvas
+        let leaving = Line::new(Coord(0, 0), Coord(20, 10), CanvasColor::Red);
+        let clipped = leaving.clipped((11, 11)).unwrap();
+        assert_eq!((clipped.start, clipped.end), (Coord(0, 0), Coord(10, 5)));
+        let entering = Line::new(Coord(20, 10), Coord(0, 0), CanvasColor::Red);
+        let clipped = entering.clipped((11, 11)).unwrap();
+        assert_eq!((clipped.start, clipped.end), (Coord(10, 5), Coord(0, 0)));
+
+        let outside = Line::new(Coord(12, 0), Coord(20, 10), CanvasColor::Red);
+        assert_eq!(outside.clipped((11, 11)), None);
+        let passing_by = Line::new(Coord(8, 20), Coord(20, 8), CanvasColor::Red);
+        assert_eq!(passing_by.clipped((11, 11)), None);
+    }
+
+    #[test]
+    fn wide_lines_cover_every_cell_once() {
+        let line = Line {
+            start: Coord(10, 10),
+            end: Coord(80, 40),
+            brush: Brush::Paint(CanvasColor::Red),
+            size: MAX_BRUSH_SIZE,
+        };
+        let coords = line.coords_in();
+        let unique = coords.iter().collect::<std::collections::HashSet<_>>();
+        assert_eq!(unique.len(), coords.len());
+    }
+}
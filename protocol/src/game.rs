@@ -0,0 +1,215 @@
+//! the state of a running game, as the server shares it with the players
+
+use crate::data::{CanvasGrid, Difficulty, Line, TurnRotation, Username};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// the largest size of the previews of finished drawings shown when the game is over
+pub const THUMBNAIL_SIZE: (usize, usize) = (40, 20);
+
+/// what was drawn in a turn, kept for the gallery shown when the game is over
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Drawing {
+    pub drawer: Username,
+    pub word: String,
+    /// the canvas at the end of the turn, shrunk to `THUMBNAIL_SIZE`
+    pub thumbnail: CanvasGrid,
+}
+
+impl Drawing {
+    pub fn new(drawer: Username, word: String, lines: &[Line], dimensions: (usize, usize)) -> Self {
+        let mut canvas = CanvasGrid::new(dimensions);
+        for line in lines {
+            canvas.draw_line(line);
+        }
+        Drawing {
+            drawer,
+            word,
+            thumbnail: canvas.downsample(THUMBNAIL_SIZE),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SkribblState {
+    /// the word that is being drawn. Players that don't know it yet only get the
+    /// characters that were revealed, with `?` in place of the others.
+    pub current_word: String,
+    /// difficulty of the current word, which scales the score for it
+    pub difficulty: Difficulty,
+    pub revealed_characters: Vec<usize>,
+
+    /// the currently drawing user
+    pub drawing_user: Username,
+
+    /// players which didn't draw yet in the current round.
+    pub remaining_users: Vec<Username>,
+
+    /// how the next drawing player is chosen
+    pub rotation: TurnRotation,
+
+    /// states of all the players
+    pub player_states: HashMap<Username, PlayerState>,
+
+    /// players that guessed the word in the current turn, in the order they got it,
+    /// together with how many seconds into the turn that was
+    pub solve_times: Vec<(Username, u32)>,
+
+    /// players that joined during a round, and only start playing in the next one.
+    /// Until then, they can watch and chat, but not guess.
+    pub waiting_players: Vec<Username>,
+}
+
+impl SkribblState {
+    pub fn current_word(&self) -> &str {
+        &self.current_word
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    pub fn revealed_characters(&self) -> &[usize] {
+        self.revealed_characters.as_ref()
+    }
+
+    /// returns the placeholder chars for the current word, with the revealed characters revealed.
+    /// Spaces and punctuation stay in place, so the length of every word of a phrase can be seen.
+    pub fn hinted_current_word(&self) -> String {
+        self.current_word
+            .chars()
+            .enumerate()
+            .map(|(idx, c)| {
+                if self.revealed_characters.contains(&idx) || !c.is_alphanumeric() {
+                    c
+                } else {
+                    '?'
+                }
+            })
+            .collect()
+    }
+
+    /// whether a player may see the current word, because they draw it or already guessed it
+    pub fn knows_word(&self, username: &Username) -> bool {
+        self.is_drawing(username) || self.has_solved(username)
+    }
+
+    /// a copy of the state for players that don't know the word,
+    /// where the word only contains the characters that were revealed
+    pub fn redacted(&self) -> SkribblState {
+        SkribblState {
+            current_word: self.hinted_current_word(),
+            ..self.clone()
+        }
+    }
+
+    /// how many seconds into the turn a player guessed the word, if they did
+    pub fn solve_time(&self, username: &Username) -> Option<u32> {
+        self.solve_times
+            .iter()
+            .find(|(name, _)| name == username)
+            .map(|(_, secs)| *secs)
+    }
+
+    pub fn has_solved(&self, username: &Username) -> bool {
+        self.player_states.get(username).map(|x| x.has_solved) == Some(true)
+    }
+
+    pub fn is_waiting(&self, username: &Username) -> bool {
+        self.waiting_players.contains(username)
+    }
+
+    pub fn is_drawing(&self, username: &Username) -> bool {
+        self.drawing_user == *username
+    }
+    pub fn can_guess(&self, username: &Username) -> bool {
+        !self.is_drawing(username)
+            && matches!(self.player_states.get(username), Some(player) if !player.has_solved)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PlayerState {
+    pub score: u32,
+    pub has_solved: bool,
+    #[serde(default)]
+    pub stats: PlayerStats,
+}
+
+impl Default for PlayerState {
+    fn default() -> Self {
+        PlayerState {
+            score: 0,
+            has_solved: false,
+            stats: PlayerStats::default(),
+        }
+    }
+}
+
+/// what a player did during a game, shown to everyone once it's over
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct PlayerStats {
+    /// chat messages sent while the player could guess the word
+    pub guesses: u32,
+    pub words_guessed: u32,
+    /// how many seconds it took the player to guess all the words they guessed
+    pub total_solve_secs: u32,
+    /// points on the canvas the player drew lines through, while it was their turn
+    pub pixels_drawn: u64,
+    /// turns the player drew in until the end
+    pub words_drawn: u32,
+}
+
+impl PlayerStats {
+    pub fn average_solve_secs(&self) -> Option<u32> {
+        self.total_solve_secs.checked_div(self.words_guessed)
+    }
+}
+
+impl std::fmt::Display for PlayerStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} guesses, ", self.guesses)?;
+        match self.average_solve_secs() {
+            Some(secs) => write!(
+                f,
+                "guessed {} words in {}s on average, ",
+                self.words_guessed, secs
+            )?,
+            None => write!(f, "guessed no words, ")?,
+        }
+        write!(
+            f,
+            "drew {} words ({} pixels)",
+            self.words_drawn, self.pixels_drawn
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn players_that_do_not_know_the_word_only_see_the_hint() {
+        let alice = Username::from("alice".to_string());
+        let bob = Username::from("bob".to_string());
+        let mut player_states = HashMap::new();
+        player_states.insert(alice.clone(), PlayerState::default());
+        player_states.insert(bob.clone(), PlayerState::default());
+        let state = SkribblState {
+            current_word: "ice cream-cone".to_string(),
+            difficulty: Difficulty::Medium,
+            revealed_characters: vec![1],
+            drawing_user: alice.clone(),
+            remaining_users: vec![bob.clone()],
+            rotation: TurnRotation::InOrder,
+            player_states,
+            solve_times: Vec::new(),
+            waiting_players: Vec::new(),
+        };
+        assert!(state.knows_word(&alice));
+        assert!(!state.knows_word(&bob));
+        assert_eq!(state.redacted().current_word(), "?c? ?????-????");
+    }
+}
@@ -0,0 +1,14 @@
+//! the messages Termibbl clients and servers exchange over a websocket, for building clients
+//! other than the terminal one.
+//!
+//! Clients connect with `message::connect_request`, which tells the server the
+//! `message::PROTOCOL_VERSION` they speak, and send their username as the first text message.
+//! The server answers with `message::ToClientMsg::InitialState`, or with
+//! `message::ToClientMsg::UsernameRejected`. Every message after that is JSON wrapped in a
+//! `message::Envelope`, which `message::decode` unwraps, skipping messages this version doesn't know.
+//!
+//! Whenever `message::PROTOCOL_VERSION` is raised, so is the major version of this crate.
+
+pub mod data;
+pub mod game;
+pub mod message;
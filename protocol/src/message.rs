@@ -0,0 +1,289 @@
+use crate::{
+    data,
+    game::{Drawing, SkribblState},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashMap, time::Duration};
+use tungstenite::{client::IntoClientRequest, handshake::client::Request};
+
+/// the version of the protocol clients speak, sent in the `PROTOCOL_HEADER` when connecting.
+/// Only raised when clients of older versions can't take part anymore; messages that are
+/// merely new get skipped by older versions thanks to envelopes.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// the header of the websocket handshake clients send their `PROTOCOL_VERSION` in.
+/// Clients from before it was introduced don't send it.
+pub const PROTOCOL_HEADER: &str = "Termibbl-Protocol";
+
+/// the handshake request for connecting to the server at `addr`, telling it our `PROTOCOL_VERSION`
+pub fn connect_request(addr: &str) -> tungstenite::Result<Request> {
+    let mut request = addr.into_client_request()?;
+    request
+        .headers_mut()
+        .insert(PROTOCOL_HEADER, PROTOCOL_VERSION.into());
+    Ok(request)
+}
+
+/// what goes over the wire: a message, together with extensions.
+/// Peers skip messages they don't know, e.g. because they were added in a newer version,
+/// instead of failing the whole connection, see `decode`.
+/// Older versions only understand bare messages: servers only send envelopes to clients
+/// that pass the `PROTOCOL_VERSION` check, and clients only send them to servers that
+/// announce them in `InitialState::envelopes`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub msg: T,
+    /// additional data newer versions can attach to a message without breaking older ones
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub ext: HashMap<String, serde_json::Value>,
+}
+
+/// serialize a bare message, which every version understands
+pub fn encode<T: Serialize>(msg: &T) -> String {
+    serde_json::to_string(msg).expect("Could not serialize msg")
+}
+
+/// serialize a message, wrapped in an envelope without extensions
+pub fn encode_in_envelope<T: Serialize>(msg: &T) -> String {
+    let envelope = Envelope {
+        msg,
+        ext: HashMap::new(),
+    };
+    serde_json::to_string(&envelope).expect("Could not serialize msg")
+}
+
+/// parse a message sent by a peer. Returns `Ok(None)` for a valid envelope with a message
+/// this version doesn't know, which should be skipped.
+/// Bare messages without an envelope, as sent by older versions, are understood too.
+pub fn decode<T: DeserializeOwned>(text: &str) -> serde_json::Result<Option<T>> {
+    match serde_json::from_str(text)? {
+        serde_json::Value::Object(mut fields) if fields.contains_key("msg") => {
+            let msg = fields.remove("msg").unwrap_or_default();
+            Ok(serde_json::from_value(msg).ok())
+        }
+        bare => serde_json::from_value(bare).map(Some),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ToClientMsg {
+    NewMessage(data::Message),
+    /// a line was drawn, together with its position in the list of lines on the canvas
+    NewLine(usize, data::Line),
+    /// a line this client sent was drawn, like `NewLine`. Every line a client sends
+    /// gets answered with either `LineAccepted` or `LineRejected`, in the order they were sent.
+    LineAccepted(usize, data::Line),
+    /// a line this client sent was not drawn, e.g. because the turn ended before it arrived
+    LineRejected,
+    InitialState(InitialState),
+    /// the username sent when connecting can't be used, for the given reason.
+    /// The connection is closed right after this.
+    UsernameRejected(String),
+    SkribblStateChanged(SkribblState),
+    /// the final state of the game, and what was drawn in every turn of it
+    GameOver(SkribblState, Vec<Drawing>),
+    /// the game ended, and players can vote for the best of these drawings
+    DrawingVoteStarted(Vec<Drawing>),
+    /// the finished games the server kept, newest first, answering `ListReplays`
+    ReplayList(Vec<ReplayInfo>),
+    /// the drawings of a finished game, answering `FetchReplay`
+    Replay(u32, Vec<Drawing>),
+    ClearCanvas,
+    LeaderChanged(data::Username),
+    /// the canvas got resized, which also clears it.
+    DimensionsChanged((usize, usize)),
+    /// remaining seconds of the current turn, relative to when the message was sent.
+    /// This is a duration rather than a timestamp, so wrong client clocks don't matter.
+    TimeChanged(u32),
+    /// seconds until the game starts, sent every tick while waiting for more players,
+    /// or `None` if the game didn't start because there were not enough players
+    GameStarting(Option<u32>),
+    /// sent every few seconds: how many lines are on the canvas, and their `canvas_checksum`.
+    /// Clients that have a different canvas should ask for a `CanvasSync`.
+    CanvasChecksum(usize, u64),
+    /// all lines on the canvas, sent when a client asked for it
+    CanvasSync(Vec<data::Line>),
+    /// where the pen of the drawing player is, or `None` once it was lifted.
+    /// Relayed to everyone but the drawing player, unless the leader turned it off.
+    PenMoved(Option<data::Coord>),
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ToServerMsg {
+    NewMessage(data::Message),
+    CommandMsg(data::CommandMsg),
+    NewLine(data::Line),
+    ClearCanvas,
+    /// the size of the clients terminal, sent after joining and whenever it changes
+    TerminalSize((u16, u16)),
+    /// ask for all lines on the canvas, after noticing that some are missing
+    RequestCanvasSync,
+    /// vote to give the drawing player more time in the current turn
+    VoteExtendTime,
+    /// vote for the best drawing of the game that just ended, by its index
+    VoteDrawing(usize),
+    /// sent after joining by clients from before every client understood `Message::Event`.
+    /// Ignored, only clients that understand events get in.
+    EnableEvents,
+    /// sent every `Heartbeat::interval` to show the client is still there
+    Heartbeat,
+    /// sent after joining by clients from before envelopes were sent to every client.
+    /// Ignored, only clients that understand envelopes get in.
+    EnableEnvelopes,
+    /// ask which finished games the server kept
+    ListReplays,
+    /// ask for the drawings of a finished game, by its id
+    FetchReplay(u32),
+    /// where the pen is while drawing, or `None` once it was lifted.
+    /// Sent at most every `PEN_INTERVAL`.
+    PenMoved(Option<data::Coord>),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InitialState {
+    pub leader: Option<data::Username>,
+    pub lines: Vec<data::Line>,
+    pub dimensions: (usize, usize),
+    pub skribbl_state: Option<SkribblState>,
+    #[serde(default)]
+    pub heartbeat: Heartbeat,
+    /// whether the server understands envelopes, so the client can wrap its own messages in them
+    #[serde(default)]
+    pub envelopes: bool,
+}
+
+/// a finished game the server kept, so its drawings can be looked at later
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayInfo {
+    pub id: u32,
+    /// seconds since the unix epoch
+    pub finished_at: u64,
+    pub players: Vec<data::Username>,
+    pub drawings: usize,
+}
+
+/// how often clients send a heartbeat, and how long the server waits for any message
+/// from a client before dropping it. Every message counts, not just heartbeats.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Heartbeat {
+            interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// how much the time between heartbeats varies, as factors of `Heartbeat::interval`,
+/// so clients that connected at the same time don't all send their heartbeats at once
+pub const HEARTBEAT_JITTER: (f64, f64) = (0.8, 1.2);
+
+/// how often the drawing player shares where their pen is, at most
+pub const PEN_INTERVAL: Duration = Duration::from_millis(200);
+
+/// UDP port servers started with `--lan` answer discovery requests on
+pub const DISCOVERY_PORT: u16 = 27_182;
+
+/// what clients broadcast in the local network to find servers
+pub const DISCOVERY_REQUEST: &[u8] = b"termibbl-discover";
+
+/// a server's answer to a discovery request
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscoveryReply {
+    /// the port the server accepts websocket connections on
+    pub port: u16,
+    pub players: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_survive_the_envelope() {
+        let msg = ToServerMsg::VoteDrawing(2);
+        assert!(matches!(
+            decode(&encode_in_envelope(&msg)),
+            Ok(Some(ToServerMsg::VoteDrawing(2)))
+        ));
+    }
+
+    #[test]
+    fn bare_messages_are_understood_by_older_versions() {
+        let msg = ToClientMsg::TimeChanged(42);
+        assert!(matches!(
+            serde_json::from_str::<PreviousToClientMsg>(&encode(&msg)),
+            Ok(PreviousToClientMsg::TimeChanged(42))
+        ));
+    }
+
+    /// `ToClientMsg` as it was when envelopes were introduced, before replays and the pen
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize)]
+    enum PreviousToClientMsg {
+        NewMessage(data::Message),
+        NewLine(usize, data::Line),
+        LineAccepted(usize, data::Line),
+        LineRejected,
+        InitialState(InitialState),
+        UsernameRejected(String),
+        SkribblStateChanged(SkribblState),
+        GameOver(SkribblState, Vec<Drawing>),
+        DrawingVoteStarted(Vec<Drawing>),
+        ClearCanvas,
+        LeaderChanged(data::Username),
+        DimensionsChanged((usize, usize)),
+        TimeChanged(u32),
+        GameStarting(Option<u32>),
+        CanvasChecksum(usize, u64),
+        CanvasSync(Vec<data::Line>),
+    }
+
+    #[test]
+    fn previous_versions_skip_what_they_dont_know() {
+        let pen = encode_in_envelope(&ToClientMsg::PenMoved(Some(data::Coord(3, 4))));
+        assert!(matches!(decode::<PreviousToClientMsg>(&pen), Ok(None)));
+        let replay = encode_in_envelope(&ToClientMsg::Replay(1, Vec::new()));
+        assert!(matches!(decode::<PreviousToClientMsg>(&replay), Ok(None)));
+
+        let time = encode_in_envelope(&ToClientMsg::TimeChanged(42));
+        assert!(matches!(
+            decode::<PreviousToClientMsg>(&time),
+            Ok(Some(PreviousToClientMsg::TimeChanged(42)))
+        ));
+    }
+
+    #[test]
+    fn messages_of_newer_versions_are_skipped() {
+        let newer = r#"{"msg":{"ReactionSent":["alice","👍"]},"ext":{"animated":true}}"#;
+        assert!(matches!(decode::<ToClientMsg>(newer), Ok(None)));
+        let newer_unit = r#"{"msg":"ServerShuttingDown"}"#;
+        assert!(matches!(decode::<ToClientMsg>(newer_unit), Ok(None)));
+    }
+
+    #[test]
+    fn extensions_of_newer_versions_are_ignored() {
+        let newer = r#"{"msg":"ClearCanvas","ext":{"reason":"leader"}}"#;
+        assert!(matches!(
+            decode::<ToClientMsg>(newer),
+            Ok(Some(ToClientMsg::ClearCanvas))
+        ));
+    }
+
+    #[test]
+    fn bare_messages_of_older_versions_are_understood() {
+        assert!(matches!(
+            decode::<Option<ToServerMsg>>(r#""Heartbeat""#),
+            Ok(Some(Some(ToServerMsg::Heartbeat)))
+        ));
+        assert!(matches!(
+            decode::<Option<ToServerMsg>>("null"),
+            Ok(Some(None))
+        ));
+        assert!(decode::<ToClientMsg>("not json").is_err());
+    }
+}
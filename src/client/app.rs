@@ -1,20 +1,33 @@
 use crate::{
-    client::error::Result,
+    client::config::Config,
+    client::error::{Error, Result},
+    client::input::{self, LineInput},
+    client::log::{NetStats, RateCounter},
+    client::proxy::Proxy,
     client::ui,
-    data::{self, CanvasColor, Coord, Line, Message},
-    message::{InitialState, ToClientMsg, ToServerMsg},
-    server::skribbl::{PlayerState, SkribblState},
+    data::{
+        self, CanvasColor, CanvasGrid, Coord, GameEvent, Line, Message, MAX_BRUSH_SIZE,
+        MAX_MESSAGE_LENGTH,
+    },
+    game::{Drawing, PlayerState, SkribblState},
+    log_debug,
+    message::{self, InitialState, ToClientMsg, ToServerMsg, PEN_INTERVAL},
+    server::words::MAX_WORD_LENGTH,
     ClientEvent,
 };
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent};
 use futures_util::sink::SinkExt;
 use futures_util::stream::StreamExt;
+use rand::Rng;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::Arc;
 
-use data::{CommandMsg, Username};
+use data::{CommandMsg, Difficulty, Username};
 use tokio_tungstenite::WebSocketStream;
 use tui::{backend::Backend, Terminal};
 
-const PALETTE: [CanvasColor; 16] = [
+pub const PALETTE: [CanvasColor; 16] = [
     CanvasColor::White,
     CanvasColor::Gray,
     CanvasColor::DarkGray,
@@ -33,32 +46,345 @@ const PALETTE: [CanvasColor; 16] = [
     CanvasColor::LightMagenta,
 ];
 
+/// the highest amount of canvas cells that can be shown in a single terminal cell in each direction
+pub const MAX_ZOOM: u16 = 4;
+
+/// the least amount of time between two redraws, limiting the client to about 30 frames per second
+const FRAME_INTERVAL: std::time::Duration = std::time::Duration::from_millis(33);
+
+/// the least amount of time between two redraws in the low bandwidth mode
+const LOW_BANDWIDTH_FRAME_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// how often the stats overlay is updated while nothing else causes a redraw
+const STATS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// how long connecting to a server may take, until the initial state arrived
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// how long the word and its definition are shown on the canvas after a turn ended
+const REVEAL_DURATION: std::time::Duration = std::time::Duration::from_secs(6);
+
+/// how long the drawing of a turn that just ended is replayed for, before its word is shown
+const TIMELAPSE_DURATION: std::time::Duration = std::time::Duration::from_millis(2500);
+
+/// how long the lines other players drew may be held back, to spread out lines that arrive all at once
+pub const STROKE_SMOOTHING: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// the time between showing two lines that arrived all at once
+pub const STROKE_SPACING: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// the scheme of invite links, like `termibbl://example.com:8888`
+pub const INVITE_SCHEME: &str = "termibbl://";
+
+/// the websocket address an invite link points to, or `None` if `link` isn't an invite link
+/// or doesn't name a host. Anything after the host and port is ignored, so links can carry more in the future.
+pub fn address_of_invite(link: &str) -> Option<String> {
+    let rest = link.trim().strip_prefix(INVITE_SCHEME)?;
+    match rest.split('/').next() {
+        Some(host) if !host.trim().is_empty() => Some(format!("ws://{}", host)),
+        _ => None,
+    }
+}
+
+/// the word of the turn that just ended, shown on top of the canvas for a moment
+#[derive(Debug, Clone)]
+pub struct RevealedWord {
+    pub word: String,
+    pub definition: Option<String>,
+    pub shown_until: std::time::Instant,
+}
+
+/// the drawing of the turn that just ended, replayed stroke by stroke when its word is revealed
+#[derive(Debug, Clone)]
+pub struct Timelapse {
+    lines: Vec<Line>,
+    started_at: std::time::Instant,
+}
+
+impl Timelapse {
+    pub fn new(lines: Vec<Line>) -> Self {
+        Timelapse {
+            lines,
+            started_at: std::time::Instant::now(),
+        }
+    }
+
+    /// the lines that are drawn by now, or `None` once the timelapse is over
+    pub fn visible_lines(&self) -> Option<&[Line]> {
+        let elapsed = self.started_at.elapsed();
+        if elapsed >= TIMELAPSE_DURATION {
+            return None;
+        }
+        let progress = elapsed.as_secs_f64() / TIMELAPSE_DURATION.as_secs_f64();
+        let count = (self.lines.len() as f64 * progress).ceil() as usize;
+        Some(&self.lines[..count.min(self.lines.len())])
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AppCanvas {
     pub palette: Vec<CanvasColor>,
-    pub lines: Vec<data::Line>,
+    /// the lines on the canvas, in the order the server sent them
+    lines: Vec<data::Line>,
+    /// the color of every cell of `lines`, updated whenever a line is added,
+    /// so only the cells a new line touches get computed again rather than every line each frame
+    cells: CanvasGrid,
+    /// lines this player drew that the server didn't accept or reject yet, shown optimistically
+    pub pending_lines: Vec<data::Line>,
+    /// whether all lines were requested from the server, because some were missing
+    pub sync_requested: bool,
     pub dimensions: (usize, usize),
+    /// the canvas coordinate shown in the top-left corner, for canvases larger than the terminal
+    pub offset: (u16, u16),
+    pub terminal_size: (u16, u16),
+    /// how the screen is split up, which decides how much room the canvas gets
+    pub layout: ui::ScreenLayout,
+    /// how many columns the sidebar keeps next to the canvas, if the canvas doesn't get too small
+    pub sidebar_width: u16,
+    /// how many canvas cells get aggregated into one terminal cell in each direction
+    pub zoom: u16,
+    /// render the canvas using braille characters, showing 2x4 canvas cells per terminal cell
+    pub braille: bool,
+    /// position and size of the eraser, shown while erasing
+    pub eraser_cursor: Option<(Coord, u16)>,
+    /// where the pen of the drawing player is, while someone else draws
+    pub pen_cursor: Option<Coord>,
+    /// lines of other players that are part of `lines`, but only get shown at the given time,
+    /// so lines that arrive in bursts over a slow connection are drawn one after another
+    incoming: VecDeque<(std::time::Instant, Line)>,
 }
 
 impl AppCanvas {
     fn new(dimensions: (usize, usize), lines: Vec<data::Line>) -> Self {
-        AppCanvas {
-            lines,
+        let mut canvas = AppCanvas {
+            lines: Vec::new(),
+            cells: CanvasGrid::new(dimensions),
+            pending_lines: Vec::new(),
+            sync_requested: false,
             dimensions,
             palette: PALETTE.to_vec(),
-        }
+            offset: (0, 0),
+            terminal_size: (dimensions.0 as u16, dimensions.1 as u16),
+            layout: ui::ScreenLayout::Full,
+            sidebar_width: ui::MIN_SIDEBAR_WIDTH,
+            zoom: 1,
+            braille: false,
+            eraser_cursor: None,
+            pen_cursor: None,
+            incoming: VecDeque::new(),
+        };
+        canvas.set_lines(lines);
+        canvas
     }
 }
 
 impl AppCanvas {
-    pub fn draw_line(&mut self, line: Line) {
-        self.lines.push(line);
+    /// add a line the server sent, which is the `index`th line on the canvas.
+    /// Returns false if some lines before it are missing.
+    pub fn add_line(&mut self, index: usize, line: Line) -> bool {
+        self.insert_line(index, line, None)
+    }
+
+    /// like `add_line`, but the line is shown a bit later, after the lines that arrived right before it.
+    /// Lines are never held back for longer than `STROKE_SMOOTHING`.
+    pub fn add_line_smoothly(&mut self, index: usize, line: Line, now: std::time::Instant) -> bool {
+        let last = self.incoming.back().map_or(now, |(at, _)| *at).max(now);
+        let show_at = (last + STROKE_SPACING).min(now + STROKE_SMOOTHING);
+        self.insert_line(index, line, Some(show_at))
+    }
+
+    fn insert_line(
+        &mut self,
+        index: usize,
+        line: Line,
+        show_at: Option<std::time::Instant>,
+    ) -> bool {
+        if index > self.lines.len() {
+            return false;
+        }
+        // lines with a lower index are already on the canvas, because it was synced since
+        if index == self.lines.len() {
+            self.lines.push(line);
+            // lines are shown in order, so a line has to wait for the ones before it
+            match show_at.or_else(|| self.incoming.back().map(|(at, _)| *at)) {
+                Some(at) => self.incoming.push_back((at, line)),
+                None => self.cells.draw_line(&line),
+            }
+        }
+        true
+    }
+
+    /// show the held back lines that are due
+    pub fn show_incoming(&mut self, now: std::time::Instant) {
+        while let Some(&(at, line)) = self.incoming.front() {
+            if at > now {
+                break;
+            }
+            self.cells.draw_line(&line);
+            self.incoming.pop_front();
+        }
+    }
+
+    /// whether some lines are held back, so the canvas changes by itself
+    pub fn has_incoming(&self) -> bool {
+        !self.incoming.is_empty()
+    }
+
+    /// the lines on the canvas, in the order the server sent them
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// replace all lines on the canvas, e.g. after syncing it with the server
+    pub fn set_lines(&mut self, lines: Vec<Line>) {
+        self.incoming.clear();
+        self.cells.clear();
+        for line in &lines {
+            self.cells.draw_line(line);
+        }
+        self.lines = lines;
+    }
+
+    /// a canvas with the same view settings showing only the given lines, e.g. for a timelapse
+    pub fn with_lines(&self, lines: Vec<Line>) -> AppCanvas {
+        let mut canvas = AppCanvas {
+            pending_lines: Vec::new(),
+            eraser_cursor: None,
+            pen_cursor: None,
+            ..self.clone()
+        };
+        canvas.set_lines(lines);
+        canvas
+    }
+
+    /// the painted cells of all confirmed lines. Pending lines have to be drawn on top of them.
+    pub fn cells(&self) -> impl Iterator<Item = (Coord, CanvasColor)> + '_ {
+        self.cells.painted_cells()
+    }
+
+    /// the server answered the oldest line this player drew, which either got drawn or rolled back
+    pub fn resolve_pending_line(&mut self) {
+        if !self.pending_lines.is_empty() {
+            self.pending_lines.remove(0);
+        }
+    }
+
+    /// remove all lines. Pending lines are kept, as the server will draw them after clearing.
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.incoming.clear();
+        self.cells.clear();
+    }
+
+    pub fn resize(&mut self, dimensions: (usize, usize)) {
+        self.dimensions = dimensions;
+        self.lines.clear();
+        self.incoming.clear();
+        self.cells = CanvasGrid::new(dimensions);
+        self.offset = (0, 0);
+    }
+
+    /// how many canvas cells are shown by a single terminal cell, horizontally and vertically
+    pub fn scale(&self) -> (u16, u16) {
+        if self.braille {
+            (2 * self.zoom, 4 * self.zoom)
+        } else {
+            (self.zoom, self.zoom)
+        }
+    }
+
+    /// the size of the part of the canvas that fits into the terminal, in terminal cells
+    pub fn visible_size(&self) -> (u16, u16) {
+        let (scale_x, scale_y) = self.scale();
+        let (width, height) = self.terminal_size;
+        let available = match self.layout {
+            ui::ScreenLayout::Full => {
+                let sidebar_width = self
+                    .sidebar_width
+                    .min(width.saturating_sub(ui::MIN_CANVAS_WIDTH))
+                    .max(ui::MIN_SIDEBAR_WIDTH);
+                (width.saturating_sub(sidebar_width), height)
+            }
+            // one row is left for the status line
+            ui::ScreenLayout::CanvasOnly => (width, height.saturating_sub(1)),
+            ui::ScreenLayout::ChatOnly | ui::ScreenLayout::TooSmall => (0, 0),
+        };
+        (
+            u16::min((self.dimensions.0 as u16).div_ceil(scale_x), available.0),
+            u16::min((self.dimensions.1 as u16).div_ceil(scale_y), available.1),
+        )
+    }
+
+    /// scroll the visible part of the canvas by the given amount of terminal cells,
+    /// staying within the canvas bounds
+    pub fn scroll_by(&mut self, dx: i32, dy: i32) {
+        let (scale_x, scale_y) = self.scale();
+        let visible_size = self.visible_size();
+        let max_x = self.dimensions.0 as i32 - (visible_size.0 * scale_x) as i32;
+        let max_y = self.dimensions.1 as i32 - (visible_size.1 * scale_y) as i32;
+        self.offset = (
+            (self.offset.0 as i32 + dx * scale_x as i32)
+                .max(0)
+                .min(max_x.max(0)) as u16,
+            (self.offset.1 as i32 + dy * scale_y as i32)
+                .max(0)
+                .min(max_y.max(0)) as u16,
+        );
+    }
+
+    pub fn set_zoom(&mut self, zoom: u16) {
+        self.zoom = zoom.clamp(1, MAX_ZOOM);
+        self.scroll_by(0, 0);
+    }
+
+    pub fn toggle_braille(&mut self) {
+        self.braille = !self.braille;
+        self.scroll_by(0, 0);
+    }
+
+    /// convert a position on the screen to the canvas position it's showing
+    pub fn to_canvas_coord(&self, Coord(x, y): Coord) -> Coord {
+        let (scale_x, scale_y) = self.scale();
+        Coord(self.offset.0 + x * scale_x, self.offset.1 + y * scale_y)
+    }
+
+    /// convert a canvas position to the position on the screen it is shown at,
+    /// if it isn't scrolled out of view to the top or left
+    pub fn to_screen_coord(&self, Coord(x, y): Coord) -> Option<Coord> {
+        let (scale_x, scale_y) = self.scale();
+        if x < self.offset.0 || y < self.offset.1 {
+            None
+        } else {
+            Some(Coord(
+                (x - self.offset.0) / scale_x,
+                (y - self.offset.1) / scale_y,
+            ))
+        }
+    }
+
+    /// like `to_screen_coord`, but also returns which of the 2x4 braille dots
+    /// of the terminal cell the canvas position is shown as.
+    pub fn to_screen_dot(&self, coord: Coord) -> Option<(Coord, (u16, u16))> {
+        let screen_coord = self.to_screen_coord(coord)?;
+        let dot = (
+            ((coord.0 - self.offset.0) / self.zoom) % 2,
+            ((coord.1 - self.offset.1) / self.zoom) % 4,
+        );
+        Some((screen_coord, dot))
     }
 }
 
+/// the pane that key presses go to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Chat,
+    /// scrolling, zooming and the eraser, so typing doesn't end up there by accident
+    Canvas,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Chat {
-    pub input: String,
+    pub input: LineInput,
     pub messages: Vec<Message>,
 }
 
@@ -68,9 +394,38 @@ pub struct App {
     pub chat: Chat,
     pub session: ServerSession,
     pub last_mouse_pos: Option<Coord>,
+    /// when the position of the pen was last sent to the server
+    pub pen_shared_at: Option<std::time::Instant>,
     pub current_color: CanvasColor,
+    pub erasing: bool,
+    pub eraser_size: u16,
     pub game_state: Option<SkribblState>,
+    /// the drawings of the game that just ended, shown instead of the canvas until the next one starts
+    pub gallery: Option<Vec<Drawing>>,
+    /// whether players are voting for the best drawing of the gallery
+    pub voting: bool,
+    /// the drawing of the gallery this player voted for
+    pub own_vote: Option<usize>,
+    pub revealed_word: Option<RevealedWord>,
+    /// the drawing of the last turn, replayed before its word is shown
+    pub timelapse: Option<Timelapse>,
+    /// the lines of the canvas before it was last cleared, which is the drawing of the last turn
+    /// once its word gets revealed
+    last_drawing: Vec<Line>,
     pub remaining_time: Option<u32>,
+    /// seconds until the game starts, while waiting for more players
+    pub starting_in: Option<u32>,
+    pub leader: Option<Username>,
+    pub config: Config,
+    /// hide the sidebar, giving the whole terminal to the canvas
+    pub sidebar_collapsed: bool,
+    pub focus: Focus,
+    /// show the debug panel with the latest log lines and network stats
+    pub show_debug: bool,
+    /// show the frame rate, the events handled per second, the traffic and the ping over the canvas
+    pub show_stats: bool,
+    pub frame_rate: RateCounter,
+    pub event_rate: RateCounter,
 }
 
 impl App {
@@ -79,10 +434,28 @@ impl App {
             canvas: AppCanvas::new(initial_state.dimensions, initial_state.lines),
             chat: Chat::default(),
             last_mouse_pos: None,
+            pen_shared_at: None,
             current_color: CanvasColor::White,
+            erasing: false,
+            eraser_size: 3,
             game_state: initial_state.skribbl_state,
+            gallery: None,
+            voting: false,
+            own_vote: None,
+            revealed_word: None,
+            timelapse: None,
+            last_drawing: Vec::new(),
             session,
             remaining_time: None,
+            starting_in: None,
+            leader: initial_state.leader,
+            config: Config::load(),
+            sidebar_collapsed: false,
+            focus: Focus::Chat,
+            show_debug: false,
+            show_stats: false,
+            frame_rate: RateCounter::new(std::time::Instant::now()),
+            event_rate: RateCounter::new(std::time::Instant::now()),
         }
     }
 
@@ -92,6 +465,92 @@ impl App {
             .and_then(|state| state.player_states.get(&self.session.username))
     }
 
+    /// whether a chat message should be shown to this player.
+    /// Messages of players who already guessed the word are hidden from players that didn't.
+    pub fn is_message_visible(&self, msg: &Message) -> bool {
+        match msg {
+            Message::SystemMsg(_) | Message::Event(_) => true,
+            Message::UserMsg(username, _) => self.game_state.as_ref().is_none_or(|state| {
+                self.is_drawing()
+                    || self.own_player().is_some_and(|x| x.has_solved)
+                    || (&state.drawing_user != username
+                        && !state
+                            .player_states
+                            .get(username)
+                            .is_some_and(|player_state| player_state.has_solved))
+            }),
+        }
+    }
+
+    /// pick the layout that fits the terminal, keeping the canvas scrolled within bounds
+    pub fn update_layout(&mut self) {
+        let drawing_turn = self
+            .game_state
+            .as_ref()
+            .is_some_and(|state| state.is_drawing(&self.session.username));
+        let layout = ui::ScreenLayout::for_terminal(
+            self.canvas.terminal_size,
+            self.sidebar_collapsed,
+            drawing_turn,
+        );
+        if layout != self.canvas.layout || self.config.sidebar_width != self.canvas.sidebar_width {
+            self.canvas.layout = layout;
+            self.canvas.sidebar_width = self.config.sidebar_width;
+            self.canvas.scroll_by(0, 0);
+        }
+    }
+
+    /// make the sidebar wider by the given amount of columns, or narrower if it's negative,
+    /// remembering the width for the next time
+    fn resize_sidebar(&mut self, delta: i16) {
+        let max_width = self
+            .canvas
+            .terminal_size
+            .0
+            .saturating_sub(ui::MIN_CANVAS_WIDTH)
+            .max(ui::MIN_SIDEBAR_WIDTH);
+        // it may be wider than fits, after the terminal got smaller
+        let width = self.config.sidebar_width.min(max_width) as i16 + delta;
+        self.config.sidebar_width =
+            width.clamp(ui::MIN_SIDEBAR_WIDTH as i16, max_width as i16) as u16;
+        self.save_config();
+    }
+
+    fn save_config(&self) {
+        if let Err(err) = self.config.save() {
+            log_debug!("Could not save the config: {}", err);
+        }
+    }
+
+    /// the link others can join the server this client is connected to with
+    pub fn invite_link(&self) -> String {
+        let addr = &self.session.addr;
+        match addr.strip_prefix("ws://") {
+            Some(host) => format!("{}{}", INVITE_SCHEME, host),
+            // invite links can't say that the server uses TLS, so those get the plain address
+            None => addr.clone(),
+        }
+    }
+
+    /// copy the invite link to the clipboard, and show it in the chat in case that didn't work
+    fn share_invite(&mut self) {
+        let invite = self.invite_link();
+        let copied = copy_to_clipboard(&invite);
+        if !copied {
+            log_debug!("Could not copy the invite to the clipboard");
+        }
+        self.chat.messages.push(Message::SystemMsg(format!(
+            "Invite others with: {}{}. They join with: termibbl client {}",
+            invite,
+            if copied {
+                " (copied to the clipboard)"
+            } else {
+                ""
+            },
+            invite
+        )));
+    }
+
     pub fn is_drawing(&self) -> bool {
         self.game_state
             .as_ref()
@@ -100,131 +559,720 @@ impl App {
     }
 
     pub async fn handle_mouse_event(&mut self, evt: MouseEvent) -> Result<()> {
+        let visible_size = self.canvas.visible_size();
+        // clicking on a pane focuses it
+        if let MouseEvent::Down(_, x, y, _) = evt {
+            if x < visible_size.0 && y < visible_size.1 {
+                self.focus = Focus::Canvas;
+            } else {
+                self.focus = Focus::Chat;
+                return Ok(());
+            }
+        }
         if !self.is_drawing() {
             return Ok(());
         }
 
-        let dimensions = self.canvas.dimensions;
+        // the canvas isn't shown, because there is no room for it
+        if visible_size.0 == 0 || visible_size.1 == 0 {
+            return Ok(());
+        }
         match evt {
             MouseEvent::Down(_, x, y, _) => {
                 if y == 0 {
-                    let swatch_size = dimensions.0 / self.canvas.palette.len() as usize;
-                    let selected_color = self.canvas.palette.get(x as usize / swatch_size);
+                    let swatch_size = visible_size.0 as usize / self.canvas.palette.len() as usize;
+                    let selected_color = x
+                        .checked_div(swatch_size as u16)
+                        .and_then(|idx| self.canvas.palette.get(idx as usize));
                     match selected_color {
-                        Some(color) => self.current_color = color.clone(),
+                        Some(color) => {
+                            self.current_color = color.clone();
+                            self.set_erasing(false);
+                        }
                         _ => {}
                     }
                 } else {
-                    self.last_mouse_pos = Some(Coord(x, y));
+                    let mouse_pos = self.canvas.to_canvas_coord(Coord(x, y));
+                    self.draw_stroke(mouse_pos, mouse_pos).await?;
+                    self.share_pen(Some(mouse_pos)).await?;
                 }
             }
             MouseEvent::Up(_, _, _, _) => {
-                self.last_mouse_pos = None;
+                let was_drawing = self.last_mouse_pos.take().is_some();
+                if was_drawing {
+                    self.share_pen(None).await?;
+                }
             }
             MouseEvent::Drag(_, x, y, _) => {
-                let mouse_pos = Coord(x, y);
-                let line = Line::new(
-                    self.last_mouse_pos.unwrap_or(mouse_pos),
-                    mouse_pos,
-                    self.current_color,
-                );
-                self.canvas.draw_line(line);
-                self.session.send(ToServerMsg::NewLine(line)).await?;
-                self.last_mouse_pos = Some(mouse_pos);
+                let mouse_pos = self.canvas.to_canvas_coord(Coord(x, y));
+                self.draw_stroke(self.last_mouse_pos.unwrap_or(mouse_pos), mouse_pos)
+                    .await?;
+                self.share_pen(Some(mouse_pos)).await?;
             }
             _ => {}
         }
         Ok(())
     }
 
+    /// draw a line with the current color, or erase along it when the eraser is active
+    async fn draw_stroke(&mut self, start: Coord, end: Coord) -> Result<()> {
+        let line = if self.erasing {
+            self.canvas.eraser_cursor = Some((end, self.eraser_size));
+            Line::erase(start, end, self.eraser_size)
+        } else {
+            Line::new(start, end, self.current_color)
+        };
+        if let Some(line) = line.clipped(self.canvas.dimensions) {
+            self.canvas.pending_lines.push(line);
+            self.session.send(ToServerMsg::NewLine(line)).await?;
+        }
+        self.last_mouse_pos = Some(end);
+        Ok(())
+    }
+
+    /// tell the server where the pen is, at most every `PEN_INTERVAL`, or that it was lifted.
+    /// The terminal only reports the mouse while a button is held, so hovering can't be shared.
+    async fn share_pen(&mut self, pos: Option<Coord>) -> Result<()> {
+        let now = std::time::Instant::now();
+        let too_soon = self
+            .pen_shared_at
+            .is_some_and(|shared_at| now - shared_at < PEN_INTERVAL);
+        if pos.is_some() && too_soon {
+            return Ok(());
+        }
+        self.pen_shared_at = Some(now);
+        self.session.send(ToServerMsg::PenMoved(pos)).await?;
+        Ok(())
+    }
+
+    fn set_erasing(&mut self, erasing: bool) {
+        self.erasing = erasing;
+        if !erasing {
+            self.canvas.eraser_cursor = None;
+        }
+    }
+
+    fn resize_eraser(&mut self, delta: i16) {
+        self.eraser_size = (self.eraser_size as i16 + delta).clamp(1, MAX_BRUSH_SIZE as i16) as u16;
+        if let Some((pos, _)) = self.canvas.eraser_cursor {
+            self.canvas.eraser_cursor = Some((pos, self.eraser_size));
+        }
+    }
+
+    /// send the current chat input, either as a message or as a command
+    pub async fn submit_chat_input(&mut self) -> Result<()> {
+        if self.chat.input.text().trim().is_empty() {
+            self.chat.input.clear();
+            return Ok(());
+        }
+
+        if self.chat.input.text().chars().count() > MAX_MESSAGE_LENGTH {
+            self.chat.messages.push(Message::SystemMsg(format!(
+                "Messages can be at most {} characters long",
+                MAX_MESSAGE_LENGTH
+            )));
+            return Ok(());
+        }
+
+        let msg_content = self.chat.input.submit();
+        if msg_content.starts_with("!") {
+            if msg_content.starts_with("!kick ") {
+                let msg_without_cmd = msg_content.trim_start_matches("!kick ").trim().to_string();
+                let command = CommandMsg::KickPlayer(Username::from(msg_without_cmd));
+                self.session.send(ToServerMsg::CommandMsg(command)).await?;
+            } else if msg_content.starts_with("!size ") {
+                let msg_without_cmd = msg_content.trim_start_matches("!size ").trim();
+                match parse_dimensions(msg_without_cmd) {
+                    Some(dimensions) => {
+                        let command = CommandMsg::SetDimensions(dimensions);
+                        self.session.send(ToServerMsg::CommandMsg(command)).await?;
+                    }
+                    None => self.chat.messages.push(Message::SystemMsg(
+                        "Usage: !size <width>x<height>".to_string(),
+                    )),
+                }
+            } else if msg_content.starts_with("!difficulty ") {
+                let difficulties = msg_content
+                    .trim_start_matches("!difficulty ")
+                    .split_whitespace()
+                    .map(|difficulty| difficulty.parse::<Difficulty>())
+                    .collect::<std::result::Result<Vec<_>, _>>();
+                match difficulties {
+                    Ok(difficulties) if !difficulties.is_empty() => {
+                        let command = CommandMsg::SetDifficulties(difficulties);
+                        self.session.send(ToServerMsg::CommandMsg(command)).await?;
+                    }
+                    _ => self.chat.messages.push(Message::SystemMsg(
+                        "Usage: !difficulty <easy|medium|hard>...".to_string(),
+                    )),
+                }
+            } else if msg_content.starts_with("!language ") {
+                let language = msg_content.trim_start_matches("!language ").trim();
+                let language = match language {
+                    "all" | "" => None,
+                    language => Some(language.to_string()),
+                };
+                let command = CommandMsg::SetLanguage(language);
+                self.session.send(ToServerMsg::CommandMsg(command)).await?;
+            } else if msg_content.starts_with("!wordlength ") {
+                let lengths = match msg_content.trim_start_matches("!wordlength ").trim() {
+                    "all" => Some((1, MAX_WORD_LENGTH)),
+                    lengths => parse_word_lengths(lengths),
+                };
+                match lengths {
+                    Some(lengths) => {
+                        let command = CommandMsg::SetWordLengths(lengths);
+                        self.session.send(ToServerMsg::CommandMsg(command)).await?;
+                    }
+                    None => self.chat.messages.push(Message::SystemMsg(
+                        "Usage: !wordlength <min>-<max> or !wordlength all".to_string(),
+                    )),
+                }
+            } else if msg_content.starts_with("!vote ") {
+                let number = msg_content
+                    .trim_start_matches("!vote ")
+                    .trim()
+                    .parse::<usize>();
+                let drawings = self.gallery.as_ref().map_or(0, |drawings| drawings.len());
+                match number {
+                    Ok(number) if self.voting && number >= 1 && number <= drawings => {
+                        self.own_vote = Some(number - 1);
+                        self.session
+                            .send(ToServerMsg::VoteDrawing(number - 1))
+                            .await?;
+                    }
+                    _ if !self.voting => self.chat.messages.push(Message::SystemMsg(
+                        "There is no vote for the best drawing right now".to_string(),
+                    )),
+                    _ => self
+                        .chat
+                        .messages
+                        .push(Message::SystemMsg(format!("Usage: !vote <1-{}>", drawings))),
+                }
+            } else if msg_content.trim() == "!clear" || msg_content.starts_with("!clear ") {
+                let skip_turn = match msg_content.trim_start_matches("!clear").trim() {
+                    "" => Some(false),
+                    "skip" => Some(true),
+                    _ => None,
+                };
+                match skip_turn {
+                    Some(skip_turn) => {
+                        let command = CommandMsg::ModerateCanvas { skip_turn };
+                        self.session.send(ToServerMsg::CommandMsg(command)).await?;
+                    }
+                    None => self
+                        .chat
+                        .messages
+                        .push(Message::SystemMsg("Usage: !clear [skip]".to_string())),
+                }
+            } else if msg_content.starts_with("!turntime ") {
+                match msg_content.trim_start_matches("!turntime ").trim().parse() {
+                    Ok(secs) => {
+                        let command = CommandMsg::SetRoundDuration(secs);
+                        self.session.send(ToServerMsg::CommandMsg(command)).await?;
+                    }
+                    Err(_) => self
+                        .chat
+                        .messages
+                        .push(Message::SystemMsg("Usage: !turntime <seconds>".to_string())),
+                }
+            } else if msg_content.trim() == "!end" {
+                let command = CommandMsg::EndGame;
+                self.session.send(ToServerMsg::CommandMsg(command)).await?;
+            } else if msg_content.starts_with("!drawingvote ") {
+                let enabled = match msg_content.trim_start_matches("!drawingvote ").trim() {
+                    "on" => Some(true),
+                    "off" => Some(false),
+                    _ => None,
+                };
+                match enabled {
+                    Some(enabled) => {
+                        let command = CommandMsg::SetDrawingVote(enabled);
+                        self.session.send(ToServerMsg::CommandMsg(command)).await?;
+                    }
+                    None => self.chat.messages.push(Message::SystemMsg(
+                        "Usage: !drawingvote <on|off>".to_string(),
+                    )),
+                }
+            } else if msg_content.starts_with("!pen ") {
+                let enabled = match msg_content.trim_start_matches("!pen ").trim() {
+                    "on" => Some(true),
+                    "off" => Some(false),
+                    _ => None,
+                };
+                match enabled {
+                    Some(enabled) => {
+                        let command = CommandMsg::SetPenSharing(enabled);
+                        self.session.send(ToServerMsg::CommandMsg(command)).await?;
+                    }
+                    None => self
+                        .chat
+                        .messages
+                        .push(Message::SystemMsg("Usage: !pen <on|off>".to_string())),
+                }
+            } else if msg_content.starts_with("!series ") {
+                let games = match msg_content.trim_start_matches("!series ").trim() {
+                    "off" => Some(None),
+                    games => games.parse().ok().map(Some),
+                };
+                match games {
+                    Some(games) => {
+                        let command = CommandMsg::SetSeries(games);
+                        self.session.send(ToServerMsg::CommandMsg(command)).await?;
+                    }
+                    None => self.chat.messages.push(Message::SystemMsg(
+                        "Usage: !series <number of games|off>".to_string(),
+                    )),
+                }
+            } else if msg_content.trim() == "!replays" {
+                self.session.send(ToServerMsg::ListReplays).await?;
+            } else if msg_content.starts_with("!replay ") {
+                match msg_content.trim_start_matches("!replay ").trim().parse() {
+                    Ok(id) => self.session.send(ToServerMsg::FetchReplay(id)).await?,
+                    Err(_) => self.chat.messages.push(Message::SystemMsg(
+                        "Usage: !replay <number>, see !replays".to_string(),
+                    )),
+                }
+            } else if msg_content.trim() == "!extend" {
+                self.session.send(ToServerMsg::VoteExtendTime).await?;
+            } else if msg_content.starts_with("!rotation ") {
+                match msg_content.trim_start_matches("!rotation ").parse() {
+                    Ok(rotation) => {
+                        let command = CommandMsg::SetTurnRotation(rotation);
+                        self.session.send(ToServerMsg::CommandMsg(command)).await?;
+                    }
+                    Err(_) => self.chat.messages.push(Message::SystemMsg(
+                        "Usage: !rotation <order|random|winner>".to_string(),
+                    )),
+                }
+            };
+        } else {
+            let message = Message::UserMsg(self.session.username.clone(), msg_content);
+            self.session.send(ToServerMsg::NewMessage(message)).await?;
+        }
+        Ok(())
+    }
+
+    /// handle the keys that work in both modes, and pass the others on to the focused pane
+    pub async fn handle_key_event(&mut self, event: &KeyEvent) -> Result<()> {
+        let KeyEvent { modifiers, code } = event;
+        match code {
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Chat => Focus::Canvas,
+                    Focus::Canvas => Focus::Chat,
+                };
+            }
+            KeyCode::F(2) => {
+                self.canvas.toggle_braille();
+            }
+            KeyCode::F(3) => {
+                self.session.send(ToServerMsg::VoteExtendTime).await?;
+            }
+            KeyCode::F(4) => {
+                self.sidebar_collapsed = !self.sidebar_collapsed;
+            }
+            KeyCode::F(5) => {
+                self.config.hide_player_list = !self.config.hide_player_list;
+                self.save_config();
+            }
+            KeyCode::Left if modifiers.contains(KeyModifiers::ALT) => {
+                self.resize_sidebar(2);
+            }
+            KeyCode::Right if modifiers.contains(KeyModifiers::ALT) => {
+                self.resize_sidebar(-2);
+            }
+            KeyCode::F(6) => {
+                self.share_invite();
+            }
+            KeyCode::F(7) => {
+                self.show_stats = !self.show_stats;
+            }
+            KeyCode::F(12) => {
+                self.show_debug = !self.show_debug;
+            }
+            _ => match self.focus {
+                Focus::Chat => self.handle_chat_key_event(event).await?,
+                Focus::Canvas => self.handle_canvas_key_event(event).await?,
+            },
+        }
+        Ok(())
+    }
+
     pub async fn handle_chat_key_event(&mut self, event: &KeyEvent) -> Result<()> {
         let KeyEvent { modifiers, code } = event;
         match code {
             KeyCode::Enter => {
-                if self.chat.input.trim().is_empty() {
-                    self.chat.input = String::new();
-                    return Ok(());
-                }
-
-                let msg_content = self.chat.input.clone();
-                if msg_content.starts_with("!") {
-                    if msg_content.starts_with("!kick ") {
-                        let msg_without_cmd =
-                            msg_content.trim_start_matches("!kick ").trim().to_string();
-                        let command = CommandMsg::KickPlayer(Username::from(msg_without_cmd));
-                        self.session.send(ToServerMsg::CommandMsg(command)).await?;
-                    };
-                } else {
-                    let message =
-                        Message::UserMsg(self.session.username.clone(), self.chat.input.clone());
-                    self.session.send(ToServerMsg::NewMessage(message)).await?;
-                }
-                self.chat.input = String::new();
+                self.submit_chat_input().await?;
+            }
+            KeyCode::Backspace if modifiers.contains(KeyModifiers::ALT) => {
+                self.chat.input.delete_word();
+            }
+            KeyCode::Char('w') if modifiers.contains(KeyModifiers::CONTROL) => {
+                self.chat.input.delete_word();
             }
             KeyCode::Backspace => {
-                self.chat.input.pop();
+                self.chat.input.backspace();
             }
             KeyCode::Char('h') if modifiers.contains(KeyModifiers::CONTROL) => {
-                self.chat.input.pop();
+                self.chat.input.backspace();
+            }
+            KeyCode::Delete => {
+                self.chat.input.delete();
+            }
+            KeyCode::Left if modifiers.contains(KeyModifiers::CONTROL) => {
+                self.chat.input.move_word_left();
+            }
+            KeyCode::Right if modifiers.contains(KeyModifiers::CONTROL) => {
+                self.chat.input.move_word_right();
+            }
+            KeyCode::Left => {
+                self.chat.input.move_left();
+            }
+            KeyCode::Right => {
+                self.chat.input.move_right();
+            }
+            KeyCode::Home => {
+                self.chat.input.home();
+            }
+            KeyCode::Char('a') if modifiers.contains(KeyModifiers::CONTROL) => {
+                self.chat.input.home();
+            }
+            KeyCode::End => {
+                self.chat.input.end();
+            }
+            KeyCode::Char('e') if modifiers.contains(KeyModifiers::CONTROL) => {
+                self.chat.input.end();
+            }
+            KeyCode::Up => {
+                self.chat.input.history_previous();
+            }
+            KeyCode::Down => {
+                self.chat.input.history_next();
+            }
+            KeyCode::Char(c) if self.chat.input.text().chars().count() < MAX_MESSAGE_LENGTH => {
+                self.chat.input.insert(*c);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub async fn handle_canvas_key_event(&mut self, event: &KeyEvent) -> Result<()> {
+        let KeyEvent { modifiers, code } = event;
+        match code {
+            KeyCode::Up if modifiers.contains(KeyModifiers::CONTROL) => {
+                self.resize_eraser(1);
+            }
+            KeyCode::Down if modifiers.contains(KeyModifiers::CONTROL) => {
+                self.resize_eraser(-1);
+            }
+            KeyCode::Left => {
+                self.canvas.scroll_by(-1, 0);
+            }
+            KeyCode::Right => {
+                self.canvas.scroll_by(1, 0);
+            }
+            KeyCode::Up => {
+                self.canvas.scroll_by(0, -1);
+            }
+            KeyCode::Down => {
+                self.canvas.scroll_by(0, 1);
+            }
+            KeyCode::PageUp => {
+                self.canvas.set_zoom(self.canvas.zoom - 1);
+            }
+            KeyCode::PageDown => {
+                self.canvas.set_zoom(self.canvas.zoom + 1);
+            }
+            KeyCode::Char('e')
+                if modifiers.contains(KeyModifiers::CONTROL) && self.is_drawing() =>
+            {
+                self.set_erasing(!self.erasing);
             }
             KeyCode::Delete => {
                 if self.is_drawing() {
                     self.session.send(ToServerMsg::ClearCanvas).await?;
-                    self.canvas.lines.clear();
+                    self.canvas.clear();
                 }
             }
-            KeyCode::Char(c) => {
-                self.chat.input.push(*c);
-            }
             _ => {}
         }
         Ok(())
     }
 
+    /// ring the bell and send desktop notifications for the events enabled in the config
+    fn notify_for_changes(&self, new_state: &SkribblState) -> Result<()> {
+        let bell = &self.config.bell;
+        let old_state = match &self.game_state {
+            Some(old_state) => old_state,
+            None => {
+                if self.config.desktop_notifications {
+                    send_desktop_notification("The game has started!");
+                }
+                if bell.game_start {
+                    ring_bell()?;
+                }
+                return Ok(());
+            }
+        };
+        let solved_count = |state: &SkribblState| {
+            state
+                .player_states
+                .values()
+                .filter(|x| x.has_solved)
+                .count()
+        };
+
+        let turn_started = old_state.drawing_user != new_state.drawing_user;
+        let your_turn = turn_started && new_state.is_drawing(&self.session.username);
+        let someone_guessed = !turn_started && solved_count(new_state) > solved_count(old_state);
+        if your_turn && self.config.desktop_notifications {
+            send_desktop_notification("It's your turn to draw!");
+        }
+        if bell.your_turn && your_turn || bell.correct_guess && someone_guessed {
+            ring_bell()?;
+        }
+        Ok(())
+    }
+
     pub async fn handle_event(&mut self, evt: ClientEvent) -> Result<()> {
+        self.event_rate.record(std::time::Instant::now());
         match evt {
             ClientEvent::KeyInput(evt) => {
-                self.handle_chat_key_event(&evt).await?;
+                self.handle_key_event(&evt).await?;
+            }
+            ClientEvent::Paste(text) => {
+                // pasting doesn't do anything on the canvas, rather than triggering its keys
+                if self.focus == Focus::Chat {
+                    let room =
+                        MAX_MESSAGE_LENGTH.saturating_sub(self.chat.input.text().chars().count());
+                    let text = input::single_line(&text)
+                        .chars()
+                        .take(room)
+                        .collect::<String>();
+                    self.chat.input.insert_str(&text);
+                }
             }
             ClientEvent::MouseInput(mouse_evt) => {
                 self.handle_mouse_event(mouse_evt).await?;
             }
+            ClientEvent::Resized(width, height) => {
+                self.on_resize((width, height)).await?;
+            }
             ClientEvent::ServerMessage(m) => match m {
                 ToClientMsg::TimeChanged(new_time) => {
+                    let old_time = self.remaining_time.unwrap_or(u32::MAX);
+                    if new_time <= 10 && old_time > 10 && self.config.bell.time_running_out {
+                        ring_bell()?;
+                    }
                     self.remaining_time = Some(new_time);
                 }
-                ToClientMsg::NewMessage(message) => self.chat.messages.push(message),
-                ToClientMsg::NewLine(line) => {
-                    self.canvas.draw_line(line);
+                ToClientMsg::NewMessage(message) => {
+                    if let Message::Event(GameEvent::WordRevealed {
+                        word, definition, ..
+                    }) = &message
+                    {
+                        let mut shown_until = std::time::Instant::now() + REVEAL_DURATION;
+                        let drawing = std::mem::take(&mut self.last_drawing);
+                        if !drawing.is_empty() && !self.config.low_bandwidth {
+                            self.timelapse = Some(Timelapse::new(drawing));
+                            shown_until += TIMELAPSE_DURATION;
+                        }
+                        self.revealed_word = Some(RevealedWord {
+                            word: word.clone(),
+                            definition: definition.clone(),
+                            shown_until,
+                        });
+                    }
+                    self.chat.messages.push(message);
+                }
+                ToClientMsg::GameStarting(secs) => self.starting_in = secs,
+                ToClientMsg::NewLine(index, line) => {
+                    let added = if self.config.low_bandwidth {
+                        self.canvas.add_line(index, line)
+                    } else {
+                        self.canvas
+                            .add_line_smoothly(index, line, std::time::Instant::now())
+                    };
+                    if !added {
+                        log_debug!("Line {} arrived before the ones in front of it", index);
+                        self.request_canvas_sync().await?;
+                    }
+                }
+                ToClientMsg::LineAccepted(index, line) => {
+                    self.canvas.resolve_pending_line();
+                    if !self.canvas.add_line(index, line) {
+                        self.request_canvas_sync().await?;
+                    }
+                }
+                ToClientMsg::LineRejected => self.canvas.resolve_pending_line(),
+                ToClientMsg::CanvasChecksum(line_count, checksum) => {
+                    if line_count != self.canvas.lines().len()
+                        || checksum != data::canvas_checksum(self.canvas.lines())
+                    {
+                        log_debug!("The canvas differs from the one of the server");
+                        self.request_canvas_sync().await?;
+                    }
+                }
+                ToClientMsg::CanvasSync(lines) => {
+                    self.canvas.set_lines(lines);
+                    self.canvas.sync_requested = false;
                 }
                 ToClientMsg::SkribblStateChanged(new_state) => {
+                    self.notify_for_changes(&new_state)?;
+                    let drawer_changed = self.game_state.as_ref().map(|s| &s.drawing_user)
+                        != Some(&new_state.drawing_user);
+                    if drawer_changed {
+                        self.canvas.pen_cursor = None;
+                    }
                     self.game_state = Some(new_state);
+                    self.gallery = None;
+                    self.starting_in = None;
                 }
                 ToClientMsg::ClearCanvas => {
-                    self.canvas.lines.clear();
+                    self.last_drawing = self.canvas.lines().to_vec();
+                    self.canvas.clear();
+                }
+                ToClientMsg::PenMoved(pos) => {
+                    // the cursor moves too often to be worth redrawing for
+                    if !self.config.low_bandwidth {
+                        self.canvas.pen_cursor = pos;
+                    }
                 }
-                ToClientMsg::GameOver(state) => {
-                    dbg!(state);
-                    panic!("Game over, I couldn't yet be bothered to implement this in a better way yet,...");
+                ToClientMsg::LeaderChanged(leader) => {
+                    self.leader = Some(leader);
                 }
-                ToClientMsg::InitialState(_) => {}
+                ToClientMsg::DimensionsChanged(dimensions) => {
+                    self.canvas.resize(dimensions);
+                }
+                ToClientMsg::GameOver(state, drawings) => {
+                    let mut players: Vec<_> = state.player_states.iter().collect();
+                    players.sort_by_key(|(_, player)| std::cmp::Reverse(player.score));
+                    for (username, player) in players {
+                        self.chat.messages.push(Message::SystemMsg(format!(
+                            "{}: {} points, {}",
+                            username, player.score, player.stats
+                        )));
+                    }
+                    self.game_state = Some(state);
+                    self.gallery = Some(drawings);
+                    self.voting = false;
+                }
+                ToClientMsg::DrawingVoteStarted(drawings) => {
+                    self.gallery = Some(drawings);
+                    self.voting = true;
+                    self.own_vote = None;
+                }
+                ToClientMsg::ReplayList(replays) => {
+                    if replays.is_empty() {
+                        self.chat
+                            .messages
+                            .push(Message::SystemMsg("No games finished yet".to_string()));
+                    }
+                    for replay in replays {
+                        let players = replay
+                            .players
+                            .iter()
+                            .map(|player| player.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        self.chat.messages.push(Message::SystemMsg(format!(
+                            "#{}: {} drawings by {}, {} ago",
+                            replay.id,
+                            replay.drawings,
+                            players,
+                            format_age(replay.finished_at)
+                        )));
+                    }
+                }
+                ToClientMsg::Replay(id, drawings) => {
+                    self.chat.messages.push(Message::SystemMsg(format!(
+                        "Showing the drawings of game #{}",
+                        id
+                    )));
+                    self.gallery = Some(drawings);
+                    self.voting = false;
+                }
+                ToClientMsg::InitialState(_) | ToClientMsg::UsernameRejected(_) => {}
             },
         }
         Ok(())
     }
 
+    /// ask the server for all lines, unless that already happened
+    async fn request_canvas_sync(&mut self) -> Result<()> {
+        if !self.canvas.sync_requested {
+            self.canvas.sync_requested = true;
+            self.session.send(ToServerMsg::RequestCanvasSync).await?;
+        }
+        Ok(())
+    }
+
+    async fn on_resize(&mut self, size: (u16, u16)) -> Result<()> {
+        self.canvas.terminal_size = size;
+        self.canvas.scroll_by(0, 0);
+        self.session.send(ToServerMsg::TerminalSize(size)).await?;
+        Ok(())
+    }
+
+    /// whether something on the screen changes by itself, so it has to be redrawn every frame
+    fn is_animating(&self) -> bool {
+        self.canvas.has_incoming()
+            || self
+                .timelapse
+                .as_ref()
+                .and_then(Timelapse::visible_lines)
+                .is_some()
+    }
+
     pub async fn run<B: Backend>(
         &mut self,
         mut terminal: &mut Terminal<B>,
         mut chan: tokio::sync::mpsc::Receiver<ClientEvent>,
     ) -> Result<()> {
+        let size = terminal.size()?;
+        self.on_resize((size.width, size.height)).await?;
         loop {
+            let now = std::time::Instant::now();
+            self.canvas.show_incoming(now);
+            self.event_rate.update(now);
+            self.frame_rate.record(now);
             ui::draw(self, &mut terminal)?;
-            if let Some(event) = chan.recv().await {
+            let frame_interval = if self.config.low_bandwidth {
+                LOW_BANDWIDTH_FRAME_INTERVAL
+            } else {
+                FRAME_INTERVAL
+            };
+            let next_frame = tokio::time::Instant::now() + frame_interval;
+            let wake_at = if self.is_animating() {
+                // keep redrawing while a timelapse plays or lines are held back, even if nothing happens
+                Some(next_frame)
+            } else if self.show_stats {
+                Some(tokio::time::Instant::now() + STATS_INTERVAL)
+            } else {
+                None
+            };
+            if let Some(wake_at) = wake_at {
+                match tokio::time::timeout_at(wake_at, chan.recv()).await {
+                    Ok(Some(event)) => self.handle_event(event).await?,
+                    Ok(None) => break Ok(()),
+                    Err(_) => continue,
+                }
+            } else if let Some(event) = chan.recv().await {
                 self.handle_event(event).await?;
             } else {
                 break Ok(());
             }
+            // handle everything that arrives until the next frame is due, so fast strokes
+            // or bursts of server messages only cause a single redraw
+            loop {
+                match tokio::time::timeout_at(next_frame, chan.recv()).await {
+                    Ok(Some(event)) => self.handle_event(event).await?,
+                    Ok(None) => return Ok(()),
+                    Err(_) => break,
+                }
+            }
         }
     }
 }
@@ -233,60 +1281,165 @@ impl App {
 pub struct ServerSession {
     to_server_send: tokio::sync::mpsc::Sender<ToServerMsg>,
     pub username: Username,
+    pub stats: Arc<NetStats>,
+    /// the address of the server, as it was connected to
+    pub addr: String,
 }
 
 impl ServerSession {
+    /// a session that isn't connected to any server, for rendering fixed states in tests
+    #[cfg(test)]
+    pub fn offline(username: Username) -> ServerSession {
+        let (to_server_send, _) = tokio::sync::mpsc::channel(1);
+        ServerSession {
+            to_server_send,
+            username,
+            stats: Arc::new(NetStats::default()),
+            addr: "ws://localhost:8888".to_string(),
+        }
+    }
+
     pub async fn establish_connection(
         addr: &str,
         username: Username,
         mut evt_send: tokio::sync::mpsc::Sender<ClientEvent>,
     ) -> Result<App> {
         let (to_server_send, mut to_server_recv) = tokio::sync::mpsc::channel::<ToServerMsg>(1);
+        // the server would strip the same characters, so use the name it will know us by
+        let username = data::sanitize_username(&username.to_string())
+            .map_err(|reason| Error::UsernameRejected(reason.to_string()))?;
 
-        let ws: WebSocketStream<_> = tokio_tungstenite::connect_async(addr)
-            .await
-            .expect("Could not connect to server")
-            .0;
+        let proxy = Proxy::configured()?;
+        let connection = tokio::time::timeout(CONNECT_TIMEOUT, async {
+            match &proxy {
+                Some(proxy) => {
+                    let stream = proxy.connect(addr).await?;
+                    tokio_tungstenite::client_async(message::connect_request(addr)?, stream)
+                        .await
+                        .map_err(connection_error)
+                }
+                None => tokio_tungstenite::connect_async(message::connect_request(addr)?)
+                    .await
+                    .map_err(connection_error),
+            }
+        });
+        log_debug!("Connecting to {}", addr);
+        let ws: WebSocketStream<_> = match connection.await {
+            Ok(Ok((ws, _))) => ws,
+            Ok(Err(err)) => {
+                log_debug!("Could not connect to {}: {}", addr, err);
+                return Err(err);
+            }
+            Err(_) => {
+                log_debug!("Connecting to {} timed out", addr);
+                return Err(Error::TimedOut);
+            }
+        };
         let (mut ws_send, mut ws_recv) = ws.split();
 
         // first send the username to the server
         ws_send
             .send(tungstenite::Message::Text(username.clone().into()))
-            .await
-            .unwrap();
+            .await?;
 
         // and wait for the initial state
-        let initial_state: InitialState = loop {
-            let msg = ws_recv.next().await;
-            if let Some(Ok(tungstenite::Message::Text(msg))) = msg {
-                if let Ok(ToClientMsg::InitialState(state)) = serde_json::from_str(&msg) {
-                    break state;
+        let wait_for_initial_state = async {
+            loop {
+                match ws_recv.next().await {
+                    Some(Ok(tungstenite::Message::Text(msg))) => match message::decode(&msg) {
+                        Ok(Some(ToClientMsg::InitialState(state))) => return Ok(state),
+                        Ok(Some(ToClientMsg::UsernameRejected(reason))) => {
+                            return Err(Error::UsernameRejected(reason))
+                        }
+                        Ok(_) => {}
+                        // the first message should always be understood, so this server is different
+                        Err(err) => return Err(Error::ProtocolMismatch(err.to_string())),
+                    },
+                    Some(Err(err)) => return Err(err.into()),
+                    None => return Err(tungstenite::Error::ConnectionClosed.into()),
+                    _ => {}
                 }
             }
         };
+        let initial_state: InitialState =
+            match tokio::time::timeout(CONNECT_TIMEOUT, wait_for_initial_state).await {
+                Ok(Ok(state)) => state,
+                Ok(Err(err)) => {
+                    log_debug!("The server didn't let us join: {}", err);
+                    return Err(err);
+                }
+                Err(_) => return Err(Error::TimedOut),
+            };
+        log_debug!("Joined {} as {}", addr, username);
+        let stats = Arc::new(NetStats::default());
+
+        // older servers only understand bare messages
+        let envelopes = initial_state.envelopes;
 
         // forward events to the server
+        let send_stats = stats.clone();
         let send_handle = tokio::spawn(async move {
             loop {
                 let msg = to_server_recv.recv().await;
-                let msg = serde_json::to_string(&msg).unwrap();
-                if let Err(_) = ws_send.send(tungstenite::Message::Text(msg)).await {
+                let is_heartbeat = matches!(msg, Some(ToServerMsg::Heartbeat));
+                let msg = if envelopes {
+                    message::encode_in_envelope(&msg)
+                } else {
+                    message::encode(&msg)
+                };
+                send_stats.record_sent(msg.len());
+                if let Err(err) = ws_send.send(tungstenite::Message::Text(msg)).await {
+                    log_debug!("Could not send to the server: {}", err);
+                    break;
+                }
+                // measure the ping along with every heartbeat. The server answers pings by itself.
+                if is_heartbeat {
+                    send_stats.record_ping_sent();
+                    if let Err(err) = ws_send.send(tungstenite::Message::Ping(Vec::new())).await {
+                        log_debug!("Could not send to the server: {}", err);
+                        break;
+                    }
+                }
+            }
+        });
+
+        // show the server we're still there, even when the player is idle. The interval varies
+        // a bit, so clients that connected at the same time don't all send their heartbeats at once.
+        let heartbeat = initial_state.heartbeat;
+        let mut heartbeat_send = to_server_send.clone();
+        tokio::spawn(async move {
+            loop {
+                let (min, max) = message::HEARTBEAT_JITTER;
+                let jitter = rand::thread_rng().gen_range(min, max);
+                tokio::time::delay_for(heartbeat.interval.mul_f64(jitter)).await;
+                if heartbeat_send.send(ToServerMsg::Heartbeat).await.is_err() {
                     break;
                 }
             }
         });
 
         // and receive messages from the server
+        let recv_stats = stats.clone();
         tokio::spawn(async move {
             loop {
                 match ws_recv.next().await {
                     Some(Ok(tungstenite::Message::Text(msg))) => {
-                        let msg = serde_json::from_str(&msg).unwrap();
-                        let _ = evt_send.send(ClientEvent::ServerMessage(msg)).await;
+                        recv_stats.record_received(msg.len());
+                        // skip what a newer server sends that we don't understand
+                        match message::decode(&msg) {
+                            Ok(Some(msg)) => {
+                                let _ = evt_send.send(ClientEvent::ServerMessage(msg)).await;
+                            }
+                            Ok(None) => log_debug!("Skipping an unknown message: {}", msg),
+                            Err(err) => log_debug!("Could not decode {}: {}", msg, err),
+                        }
                     }
+                    Some(Ok(tungstenite::Message::Pong(_))) => recv_stats.record_pong(),
                     Some(Ok(tungstenite::Message::Close(_))) => {
+                        log_debug!("The server closed the connection");
                         break;
                     }
+                    Some(Err(err)) => log_debug!("Could not receive from the server: {}", err),
                     _ => {}
                 }
             }
@@ -297,6 +1450,8 @@ impl ServerSession {
             ServerSession {
                 to_server_send,
                 username,
+                stats,
+                addr: addr.to_string(),
             },
             initial_state,
         ))
@@ -307,3 +1462,111 @@ impl ServerSession {
         Ok(())
     }
 }
+
+/// tell apart why opening the websocket connection failed
+fn connection_error(err: tungstenite::Error) -> Error {
+    match err {
+        tungstenite::Error::Io(err) if err.kind() == std::io::ErrorKind::ConnectionRefused => {
+            Error::ConnectionRefused
+        }
+        err @ tungstenite::Error::Http(_) | err @ tungstenite::Error::Protocol(_) => {
+            Error::ProtocolMismatch(err.to_string())
+        }
+        err => err.into(),
+    }
+}
+
+/// how long ago a point in time given in seconds since the unix epoch was, like "5 minutes"
+fn format_age(unix_secs: u64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    match now.saturating_sub(unix_secs) {
+        secs if secs < 60 => format!("{} seconds", secs),
+        secs if secs < 60 * 60 => format!("{} minutes", secs / 60),
+        secs => format!("{} hours", secs / 60 / 60),
+    }
+}
+
+fn parse_dimensions(s: &str) -> Option<(usize, usize)> {
+    let mut split = s.split('x');
+    let width = split.next()?.trim().parse().ok()?;
+    let height = split.next()?.trim().parse().ok()?;
+    Some((width, height))
+}
+
+fn parse_word_lengths(s: &str) -> Option<(usize, usize)> {
+    let mut split = s.split('-');
+    let min = split.next()?.trim().parse().ok()?;
+    let max = split.next()?.trim().parse().ok()?;
+    Some((min, max))
+}
+
+fn ring_bell() -> Result<()> {
+    let mut stdout = std::io::stdout();
+    stdout.write_all(b"\x07")?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// put `text` into the system clipboard using the clipboard tool of the platform,
+/// returning whether one of them took it
+fn copy_to_clipboard(text: &str) -> bool {
+    let tools: &[(&str, &[&str])] = if cfg!(target_os = "macos") {
+        &[("pbcopy", &[])]
+    } else if cfg!(windows) {
+        &[("clip", &[])]
+    } else {
+        &[
+            ("wl-copy", &[]),
+            ("xclip", &["-selection", "clipboard"]),
+            ("xsel", &["--clipboard", "--input"]),
+        ]
+    };
+    tools.iter().any(|(program, args)| {
+        let child = std::process::Command::new(program)
+            .args(*args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(_) => return false,
+        };
+        let written = child
+            .stdin
+            .take()
+            .is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+        // the x11 tools stay around to serve the clipboard, but fork away from the process we wait for
+        child.wait().is_ok_and(|status| status.success()) && written
+    })
+}
+
+/// show a desktop notification using the notification tool of the platform, ignoring failures.
+/// The terminal doesn't tell us whether it's focused, so this is opt-in via the config.
+fn send_desktop_notification(text: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = std::process::Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification {:?} with title \"Termibbl\"",
+            text
+        ));
+        command
+    } else {
+        let mut command = std::process::Command::new("notify-send");
+        command.arg("Termibbl").arg(text);
+        command
+    };
+    let child = command
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+    // wait for the notifier in the background so it doesn't linger as a zombie process
+    if let Ok(mut child) = child {
+        std::thread::spawn(move || {
+            let _ = child.wait();
+        });
+    }
+}
@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// client settings, read from `$XDG_CONFIG_HOME/termibbl/config.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// draw a pattern for every color, so colors can be told apart without seeing them
+    pub colorblind: bool,
+    pub bell: BellConfig,
+    /// show desktop notifications when it's your turn to draw or the game starts
+    pub desktop_notifications: bool,
+    /// connect to servers through this proxy, like `socks5://127.0.0.1:9050` or `http://proxy:3128`.
+    /// If it's not set, the `ALL_PROXY` environment variable is used.
+    pub proxy: Option<String>,
+    /// how many columns the chat keeps next to the canvas, changed with Alt+Left and Alt+Right
+    pub sidebar_width: u16,
+    /// only show the word and the time above the chat, instead of every player
+    pub hide_player_list: bool,
+    /// redraw less often and without animations, for slow connections like SSH sessions
+    pub low_bandwidth: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            colorblind: false,
+            bell: BellConfig::default(),
+            desktop_notifications: false,
+            proxy: None,
+            sidebar_width: crate::client::ui::MIN_SIDEBAR_WIDTH,
+            hide_player_list: false,
+            low_bandwidth: false,
+        }
+    }
+}
+
+/// which events ring the terminal bell
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BellConfig {
+    pub your_turn: bool,
+    pub time_running_out: bool,
+    pub correct_guess: bool,
+    pub game_start: bool,
+}
+
+impl Default for BellConfig {
+    fn default() -> Self {
+        BellConfig {
+            your_turn: true,
+            time_running_out: false,
+            correct_guess: false,
+            game_start: true,
+        }
+    }
+}
+
+impl Config {
+    /// read the config file, falling back to the default config if there is none
+    pub fn load() -> Config {
+        let path = match config_dir() {
+            Some(dir) => dir.join("config.json"),
+            None => return Config::default(),
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|err| {
+                eprintln!("Ignoring invalid config file {}: {}", path.display(), err);
+                Config::default()
+            }),
+            Err(_) => Config::default(),
+        }
+    }
+
+    /// write the config file, e.g. after a setting was changed in the game
+    pub fn save(&self) -> std::io::Result<()> {
+        let dir = config_dir().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no config directory")
+        })?;
+        std::fs::create_dir_all(&dir)?;
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(dir.join("config.json"), content)
+    }
+}
+
+/// the server and username of the last game the client joined, offered again in the start menu
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastSession {
+    pub host: String,
+    pub username: String,
+}
+
+impl LastSession {
+    /// read the last session from `$XDG_STATE_HOME/termibbl/last_session.json`, if there was one
+    pub fn load() -> Option<LastSession> {
+        let content = std::fs::read_to_string(state_dir()?.join("last_session.json")).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let dir = state_dir().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no state directory")
+        })?;
+        std::fs::create_dir_all(&dir)?;
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(dir.join("last_session.json"), content)
+    }
+}
+
+/// the directory termibbl stores its client configuration in
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("termibbl"))
+}
+
+/// the directory termibbl keeps files in that can be deleted any time, like the client log
+pub fn cache_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|dir| dir.join("termibbl"))
+}
+
+/// the directory termibbl keeps what it remembers between runs in, like the last server
+pub fn state_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        })
+        .map(|dir| dir.join("termibbl"))
+}
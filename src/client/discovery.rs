@@ -0,0 +1,61 @@
+use crate::{
+    client::error::Result,
+    message::{DiscoveryReply, DISCOVERY_PORT, DISCOVERY_REQUEST},
+};
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+use tokio::net::UdpSocket;
+
+/// how long to wait for servers to answer a discovery request
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// a server in the local network that answered a discovery request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanServer {
+    pub addr: SocketAddr,
+    pub players: usize,
+}
+
+impl std::fmt::Display for LanServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.players {
+            1 => write!(f, "{} (1 player)", self.addr),
+            players => write!(f, "{} ({} players)", self.addr, players),
+        }
+    }
+}
+
+/// broadcast a discovery request into the local network and collect the servers that
+/// answer within `DISCOVERY_TIMEOUT`. Only servers started with `--lan` answer.
+pub async fn discover() -> Result<Vec<LanServer>> {
+    let mut socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+    socket
+        .send_to(DISCOVERY_REQUEST, (Ipv4Addr::BROADCAST, DISCOVERY_PORT))
+        .await?;
+
+    let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+    let mut servers: Vec<LanServer> = Vec::new();
+    let mut buf = [0; 512];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let (len, peer) = match tokio::time::timeout(remaining, socket.recv_from(&mut buf)).await {
+            Ok(received) => received?,
+            Err(_) => break,
+        };
+        // anything else could be sending to this port, so ignore what doesn't parse
+        if let Ok(reply) = serde_json::from_slice::<DiscoveryReply>(&buf[..len]) {
+            let addr = SocketAddr::new(peer.ip(), reply.port);
+            if !servers.iter().any(|server| server.addr == addr) {
+                servers.push(LanServer {
+                    addr,
+                    players: reply.players,
+                });
+            }
+        }
+    }
+    servers.sort_by_key(|server| server.addr);
+    Ok(servers)
+}
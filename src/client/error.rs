@@ -6,6 +6,47 @@ pub enum Error {
     CrosstermError(crossterm::ErrorKind),
     IOError(std::io::Error),
     WebSocketError(tungstenite::error::Error),
+    /// the server didn't accept the username, for the given reason
+    UsernameRejected(String),
+    /// nothing is listening on the address of the server
+    ConnectionRefused,
+    /// the server didn't answer in time
+    TimedOut,
+    /// the server doesn't speak the same protocol, like a different version of termibbl
+    ProtocolMismatch(String),
+    /// connecting through the proxy failed, for the given reason
+    Proxy(String),
+}
+
+impl Error {
+    /// whether trying again later might work, because the server might just not be up yet
+    pub fn is_temporary(&self) -> bool {
+        matches!(
+            self,
+            Error::ConnectionRefused
+                | Error::TimedOut
+                | Error::IOError(_)
+                | Error::WebSocketError(_)
+        )
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::SendError(e) => write!(f, "{}", e),
+            Error::CrosstermError(e) => write!(f, "{}", e),
+            Error::IOError(e) => write!(f, "{}", e),
+            Error::WebSocketError(e) => write!(f, "{}", e),
+            Error::UsernameRejected(reason) => write!(f, "{}", reason),
+            Error::ConnectionRefused => write!(f, "the server refused the connection"),
+            Error::TimedOut => write!(f, "the server didn't answer in time"),
+            Error::ProtocolMismatch(err) => {
+                write!(f, "the server speaks a different protocol ({})", err)
+            }
+            Error::Proxy(reason) => write!(f, "{}", reason),
+        }
+    }
 }
 
 impl<T> From<tokio::sync::mpsc::error::SendError<T>> for Error {
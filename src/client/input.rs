@@ -0,0 +1,178 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// a line of text being typed, with a cursor that moves by whole graphemes,
+/// and the lines that were entered before it
+#[derive(Debug, Clone, Default)]
+pub struct LineInput {
+    text: String,
+    /// byte offset of the cursor into `text`, always at a grapheme boundary
+    cursor: usize,
+    history: Vec<String>,
+    /// the entry of the history that's shown, while going through it
+    history_pos: Option<usize>,
+    /// what was typed before going through the history, shown again after its newest entry
+    draft: String,
+}
+
+impl LineInput {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// the text before the cursor, and the text from the cursor on
+    pub fn split_at_cursor(&self) -> (&str, &str) {
+        self.text.split_at(self.cursor)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// replace the text, putting the cursor at its end
+    pub fn set_text(&mut self, text: String) {
+        self.cursor = text.len();
+        self.text = text;
+    }
+
+    /// remove the text, remembering it in the history, and return it
+    pub fn submit(&mut self) -> String {
+        let text = std::mem::take(&mut self.text);
+        self.cursor = 0;
+        self.history_pos = None;
+        self.draft.clear();
+        if !text.trim().is_empty() && self.history.last() != Some(&text) {
+            self.history.push(text.clone());
+        }
+        text
+    }
+
+    pub fn clear(&mut self) {
+        self.set_text(String::new());
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    pub fn insert_str(&mut self, text: &str) {
+        self.text.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    /// remove the grapheme before the cursor
+    pub fn backspace(&mut self) {
+        let start = self.previous_boundary();
+        self.text.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    /// remove the grapheme after the cursor
+    pub fn delete(&mut self) {
+        let end = self.next_boundary();
+        self.text.drain(self.cursor..end);
+    }
+
+    /// remove the word before the cursor, together with the spaces after it
+    pub fn delete_word(&mut self) {
+        let start = self.previous_word_start();
+        self.text.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.previous_boundary();
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = self.next_boundary();
+    }
+
+    pub fn move_word_left(&mut self) {
+        self.cursor = self.previous_word_start();
+    }
+
+    pub fn move_word_right(&mut self) {
+        let after_cursor = &self.text[self.cursor..];
+        self.cursor += after_cursor
+            .split_word_bound_indices()
+            .map(|(idx, word)| idx + word.len())
+            .find(|&end| !after_cursor[..end].trim().is_empty())
+            .unwrap_or(after_cursor.len());
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    /// show the entry of the history before the one shown, or the newest one
+    pub fn history_previous(&mut self) {
+        let pos = match self.history_pos {
+            Some(0) => return,
+            Some(pos) => pos - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.text.clone();
+                self.history.len() - 1
+            }
+        };
+        self.history_pos = Some(pos);
+        self.set_text(self.history[pos].clone());
+    }
+
+    /// show the entry of the history after the one shown, or what was typed before
+    pub fn history_next(&mut self) {
+        match self.history_pos {
+            Some(pos) if pos + 1 < self.history.len() => {
+                self.history_pos = Some(pos + 1);
+                self.set_text(self.history[pos + 1].clone());
+            }
+            Some(_) => {
+                self.history_pos = None;
+                let draft = std::mem::take(&mut self.draft);
+                self.set_text(draft);
+            }
+            None => {}
+        }
+    }
+
+    fn previous_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(idx, _)| idx)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |grapheme| self.cursor + grapheme.len())
+    }
+
+    /// where the word before the cursor starts, skipping the spaces right before the cursor
+    fn previous_word_start(&self) -> usize {
+        let before_cursor = &self.text[..self.cursor];
+        before_cursor
+            .split_word_bound_indices()
+            .rev()
+            .find(|(_, word)| !word.trim().is_empty())
+            .map_or(0, |(idx, _)| idx)
+    }
+}
+
+/// pasted text as a single line, for inputs that can't hold line breaks
+pub fn single_line(text: &str) -> String {
+    text.trim_end_matches(['\n', '\r'])
+        .chars()
+        .filter_map(|c| match c {
+            '\n' | '\t' => Some(' '),
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect()
+}
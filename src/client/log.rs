@@ -0,0 +1,240 @@
+//! logging for the client, which can't print anything while the TUI owns the terminal.
+//! Lines go to a log file, if one was opened, and the latest ones are kept for the debug panel.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock, TryLockError};
+use std::time::{Duration, Instant};
+
+/// how many of the latest log lines are kept for the debug panel
+pub const RECENT_LINES: usize = 100;
+
+static LOGGER: OnceLock<Mutex<Logger>> = OnceLock::new();
+
+/// log a line, formatted like `format!`
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        $crate::client::log::log(format!($($arg)*))
+    };
+}
+
+#[derive(Debug)]
+pub struct Logger {
+    file: Option<File>,
+    recent: VecDeque<String>,
+    started_at: Instant,
+}
+
+impl Logger {
+    pub fn new() -> Logger {
+        Logger {
+            file: None,
+            recent: VecDeque::with_capacity(RECENT_LINES),
+            started_at: Instant::now(),
+        }
+    }
+
+    /// write a line to the log file, prefixed with the seconds since the client started
+    pub fn log(&mut self, line: String) {
+        let line = format!(
+            "[{:>8.3}] {}",
+            self.started_at.elapsed().as_secs_f64(),
+            line
+        );
+        if let Some(file) = &mut self.file {
+            // there is nowhere left to report a failing log file to
+            let _ = writeln!(file, "{}", line);
+        }
+        if self.recent.len() >= RECENT_LINES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(line);
+    }
+
+    /// the latest lines, oldest first
+    pub fn recent(&self) -> impl Iterator<Item = &String> {
+        self.recent.iter()
+    }
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Logger::new()
+    }
+}
+
+fn logger() -> &'static Mutex<Logger> {
+    LOGGER.get_or_init(|| Mutex::new(Logger::new()))
+}
+
+/// append the log to the file at `path`, creating it and its directory if needed
+pub fn init(path: &Path) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    logger().lock().unwrap().file = Some(file);
+    Ok(())
+}
+
+/// log a line. Use `log_debug!` to format it in place.
+pub fn log(line: String) {
+    logger().lock().unwrap().log(line);
+}
+
+/// log a line if nobody else is logging right now, instead of waiting.
+/// For panics, which may happen while the log is locked and would deadlock otherwise.
+pub fn try_log(line: String) {
+    let mut logger = match logger().try_lock() {
+        Ok(logger) => logger,
+        // a panic while logging left the log behind, which is still fine to write to
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+        Err(TryLockError::WouldBlock) => return,
+    };
+    logger.log(line);
+}
+
+/// the latest log lines, oldest first
+pub fn recent_lines() -> Vec<String> {
+    logger().lock().unwrap().recent().cloned().collect()
+}
+
+/// where the log is written to if no file is given, `$XDG_CACHE_HOME/termibbl/client.log`
+pub fn default_path() -> Option<PathBuf> {
+    crate::client::config::cache_dir().map(|dir| dir.join("client.log"))
+}
+
+/// how much was sent to and received from the server, shown in the debug panel and the stats overlay
+#[derive(Debug, Default)]
+pub struct NetStats {
+    messages_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_received: AtomicU64,
+    /// when the latest ping was sent, until the server answered it
+    ping_sent_at: Mutex<Option<Instant>>,
+    /// how long the server took to answer the latest ping, in microseconds, plus one.
+    /// Zero until the first ping was answered.
+    ping_micros: AtomicU64,
+}
+
+impl NetStats {
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    pub fn record_ping_sent(&self) {
+        *self.ping_sent_at.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn record_pong(&self) {
+        if let Some(sent_at) = self.ping_sent_at.lock().unwrap().take() {
+            let micros = sent_at.elapsed().as_micros() as u64;
+            self.ping_micros.store(micros + 1, Ordering::Relaxed);
+        }
+    }
+
+    /// how long the server took to answer the latest ping
+    pub fn ping(&self) -> Option<Duration> {
+        match self.ping_micros.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros - 1)),
+        }
+    }
+
+    pub fn record_sent(&self, bytes: usize) {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_received(&self, bytes: usize) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+impl std::fmt::Display for NetStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sent {} ({} B), received {} ({} B)",
+            self.messages_sent.load(Ordering::Relaxed),
+            self.bytes_sent.load(Ordering::Relaxed),
+            self.messages_received.load(Ordering::Relaxed),
+            self.bytes_received.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// how often something happens per second, like frames being drawn, counted over the last full second
+#[derive(Debug, Clone)]
+pub struct RateCounter {
+    window_start: Instant,
+    count: u32,
+    per_second: f64,
+}
+
+impl RateCounter {
+    pub fn new(now: Instant) -> RateCounter {
+        RateCounter {
+            window_start: now,
+            count: 0,
+            per_second: 0.0,
+        }
+    }
+
+    pub fn record(&mut self, now: Instant) {
+        self.update(now);
+        self.count += 1;
+    }
+
+    /// finish the second that's being counted, if it's over.
+    /// Without this, the rate would stay the same while nothing happens.
+    pub fn update(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed >= Duration::from_secs(1) {
+            self.per_second = self.count as f64 / elapsed.as_secs_f64();
+            self.window_start = now;
+            self.count = 0;
+        }
+    }
+
+    pub fn per_second(&self) -> f64 {
+        self.per_second
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_are_counted_over_the_last_second() {
+        let start = Instant::now();
+        let mut rate = RateCounter::new(start);
+        for i in 0..30 {
+            rate.record(start + Duration::from_millis(i * 33));
+        }
+        assert_eq!(rate.per_second(), 0.0);
+        rate.update(start + Duration::from_secs(1));
+        assert_eq!(rate.per_second(), 30.0);
+        // nothing happened in the second after that
+        rate.update(start + Duration::from_secs(2));
+        assert_eq!(rate.per_second(), 0.0);
+    }
+
+    #[test]
+    fn trying_to_log_does_not_wait_for_the_log() {
+        let _guard = logger().lock().unwrap();
+        try_log("from a panic".to_string());
+    }
+}
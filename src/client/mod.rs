@@ -1,4 +1,16 @@
 pub use crate::*;
 pub mod app;
+pub mod config;
+pub mod discovery;
 pub mod error;
+pub mod input;
+pub mod log;
+pub mod narrator;
+pub mod proxy;
+pub mod start_menu;
+pub mod stress;
+pub mod terminal;
 pub mod ui;
+
+#[cfg(test)]
+mod tests;
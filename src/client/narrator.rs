@@ -0,0 +1,101 @@
+use crate::{
+    client::app::App,
+    client::error::Result,
+    data::{Message, Username},
+    ClientEvent,
+};
+use tokio::io::{stdin, AsyncBufReadExt, BufReader};
+
+/// remaining seconds of a turn at which a warning is printed
+const TIME_WARNINGS: [u32; 2] = [30, 10];
+
+/// run the client without a canvas, printing game events as plain lines of text
+/// and reading chat messages from stdin, so the game can be played with a screen reader.
+pub async fn run(app: &mut App, mut chan: tokio::sync::mpsc::Receiver<ClientEvent>) -> Result<()> {
+    let mut input_lines = BufReader::new(stdin()).lines();
+    let mut narrator = Narrator::default();
+    println!(
+        "Connected as {}. Type a message and press enter to guess.",
+        app.session.username
+    );
+    narrator.narrate(app);
+    loop {
+        tokio::select! {
+            evt = chan.recv() => match evt {
+                Some(evt) => app.handle_event(evt).await?,
+                None => break,
+            },
+            line = input_lines.next_line() => match line? {
+                Some(line) => {
+                    app.chat.input.set_text(line);
+                    app.submit_chat_input().await?;
+                }
+                None => break,
+            },
+        }
+        narrator.narrate(app);
+    }
+    Ok(())
+}
+
+/// keeps track of what has already been told to the player
+#[derive(Debug, Default)]
+struct Narrator {
+    printed_messages: usize,
+    drawing_user: Option<Username>,
+    hint: Option<String>,
+    remaining_time: Option<u32>,
+}
+
+impl Narrator {
+    fn narrate(&mut self, app: &App) {
+        for msg in app.chat.messages.iter().skip(self.printed_messages) {
+            if app.is_message_visible(msg) {
+                match msg {
+                    Message::SystemMsg(text) => println!("{}", text),
+                    Message::Event(event) => println!("{}", event),
+                    Message::UserMsg(username, text) => println!("{} says: {}", username, text),
+                }
+            }
+        }
+        self.printed_messages = app.chat.messages.len();
+
+        let state = match &app.game_state {
+            Some(state) => state,
+            None => return,
+        };
+
+        if self.drawing_user.as_ref() != Some(&state.drawing_user) {
+            self.drawing_user = Some(state.drawing_user.clone());
+            self.hint = None;
+            if app.is_drawing() {
+                println!(
+                    "It's your turn to draw \"{}\". Drawing isn't possible in this mode.",
+                    state.current_word()
+                );
+            } else {
+                println!(
+                    "{} is drawing now. The word has {} letters.",
+                    state.drawing_user,
+                    state.current_word().chars().count()
+                );
+            }
+        }
+
+        let hint = state.hinted_current_word();
+        if !app.is_drawing() && self.hint.as_ref() != Some(&hint) {
+            println!("Hint: {}", hint);
+            self.hint = Some(hint);
+        }
+
+        if let Some(remaining_time) = app.remaining_time {
+            let previous_time = self.remaining_time.unwrap_or(u32::MAX);
+            for warning in TIME_WARNINGS.iter() {
+                if remaining_time <= *warning && previous_time > *warning {
+                    println!("{} seconds left!", remaining_time);
+                }
+            }
+            self.remaining_time = Some(remaining_time);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use crate::client::{
+    config::Config,
+    error::{Error, Result},
+};
+use std::net::IpAddr;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// a proxy to connect to servers through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Proxy {
+    /// a SOCKS5 proxy at the given address, like the one of Tor.
+    /// The proxy resolves the address of the server, so it also works for hidden services.
+    Socks5(String),
+    /// an HTTP proxy at the given address that supports `CONNECT`
+    Http(String),
+}
+
+impl std::fmt::Display for Proxy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Proxy::Socks5(addr) => write!(f, "socks5://{}", addr),
+            Proxy::Http(addr) => write!(f, "http://{}", addr),
+        }
+    }
+}
+
+impl Proxy {
+    /// parse a proxy URL like `socks5://127.0.0.1:9050` or `http://proxy:3128`
+    pub fn parse(url: &str) -> std::result::Result<Proxy, String> {
+        let url = url.trim().trim_end_matches('/');
+        let (scheme, addr) = match url.find("://") {
+            Some(idx) => (&url[..idx], &url[idx + 3..]),
+            None => ("http", url),
+        };
+        if addr.is_empty() {
+            return Err(format!("the proxy {} has no address", url));
+        }
+        match scheme.to_lowercase().as_str() {
+            "socks5" | "socks5h" => Ok(Proxy::Socks5(addr.to_string())),
+            "http" => Ok(Proxy::Http(addr.to_string())),
+            _ => Err(format!(
+                "proxies of type {} aren't supported, only socks5 and http",
+                scheme
+            )),
+        }
+    }
+
+    /// the proxy set in the config file, or else in the `ALL_PROXY` environment variable
+    pub fn configured() -> Result<Option<Proxy>> {
+        let url = Config::load().proxy.or_else(|| {
+            std::env::var("ALL_PROXY")
+                .or_else(|_| std::env::var("all_proxy"))
+                .ok()
+        });
+        match url {
+            Some(url) if !url.trim().is_empty() => {
+                Proxy::parse(&url).map(Some).map_err(Error::Proxy)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// open a connection to the server at the websocket address `addr` through the proxy
+    pub async fn connect(&self, addr: &str) -> Result<TcpStream> {
+        let (host, port) = target(addr)?;
+        let proxy_addr = match self {
+            Proxy::Socks5(proxy_addr) | Proxy::Http(proxy_addr) => proxy_addr,
+        };
+        let mut stream = TcpStream::connect(proxy_addr.as_str())
+            .await
+            .map_err(|err| Error::Proxy(format!("could not reach {}: {}", self, err)))?;
+        match self {
+            Proxy::Socks5(_) => socks5_handshake(&mut stream, &host, port).await?,
+            Proxy::Http(_) => http_connect(&mut stream, &host, port).await?,
+        }
+        Ok(stream)
+    }
+}
+
+/// the host and port of a websocket address like `ws://example.com:8888`
+fn target(addr: &str) -> Result<(String, u16)> {
+    let url = reqwest::Url::parse(addr)
+        .map_err(|err| Error::Proxy(format!("invalid address {}: {}", addr, err)))?;
+    match (url.host_str(), url.port_or_known_default()) {
+        (Some(host), Some(port)) => Ok((host.to_string(), port)),
+        _ => Err(Error::Proxy(format!("the address {} needs a port", addr))),
+    }
+}
+
+/// ask a SOCKS5 proxy to connect to `host:port`, without authentication
+async fn socks5_handshake(stream: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+    // version 5, offering one method: no authentication
+    stream.write_all(&[5, 1, 0]).await?;
+    let mut method = [0; 2];
+    stream.read_exact(&mut method).await?;
+    if method != [5, 0] {
+        return Err(Error::Proxy(
+            "the proxy wants a login, which isn't supported".to_string(),
+        ));
+    }
+
+    // version 5, CONNECT, reserved, then the address
+    let mut request = vec![5, 1, 0];
+    match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) if host.len() <= 255 => {
+            request.push(3);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+        Err(_) => return Err(Error::Proxy(format!("the host name {} is too long", host))),
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        let reason = match reply[1] {
+            2 => "the proxy doesn't allow this connection",
+            3 => "the network of the server can't be reached",
+            4 => "the server can't be reached",
+            5 => "the server refused the connection",
+            6 => "the connection timed out",
+            _ => "the proxy could not connect to the server",
+        };
+        return Err(Error::Proxy(reason.to_string()));
+    }
+    // skip the address the proxy connected from, which isn't needed
+    let addr_len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => stream.read_u8().await? as usize,
+        _ => return Err(Error::Proxy("the proxy sent an invalid answer".to_string())),
+    };
+    let mut bound_addr = vec![0; addr_len + 2];
+    stream.read_exact(&mut bound_addr).await?;
+    Ok(())
+}
+
+/// ask an HTTP proxy to open a tunnel to `host:port`
+async fn http_connect(stream: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+    let request = format!(
+        "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n\r\n",
+        host = host,
+        port = port
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    // read the answer byte by byte, so nothing the server sends after it gets lost
+    let mut answer = Vec::new();
+    while !answer.ends_with(b"\r\n\r\n") {
+        if answer.len() > 8192 {
+            return Err(Error::Proxy("the proxy sent an invalid answer".to_string()));
+        }
+        answer.push(stream.read_u8().await?);
+    }
+    let answer = String::from_utf8_lossy(&answer);
+    let status_line = answer.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(Error::Proxy(format!("the proxy answered {}", status_line))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_proxy_urls() {
+        assert_eq!(
+            Proxy::parse("socks5h://127.0.0.1:9050"),
+            Ok(Proxy::Socks5("127.0.0.1:9050".to_string()))
+        );
+        assert_eq!(
+            Proxy::parse("http://proxy.example.com:3128/"),
+            Ok(Proxy::Http("proxy.example.com:3128".to_string()))
+        );
+        assert_eq!(
+            Proxy::parse("proxy:3128"),
+            Ok(Proxy::Http("proxy:3128".to_string()))
+        );
+        assert!(Proxy::parse("https://proxy:3128").is_err());
+    }
+}
@@ -0,0 +1,463 @@
+use crate::{
+    client::app::{self, App, ServerSession},
+    client::config::LastSession,
+    client::discovery::{self, LanServer},
+    client::error::Result,
+    client::input,
+    client::proxy::Proxy,
+    client::ui,
+    data::Username,
+    log_debug, ClientEvent,
+};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::time::{Duration, Instant};
+use tui::{backend::Backend, Terminal};
+
+/// how long to wait before trying to connect again after the first failed attempt.
+/// The wait doubles with every failed attempt, up to `MAX_RETRY_DELAY`.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// how often the logo and the spinner of the start menu move on
+const ANIMATION_INTERVAL: Duration = Duration::from_millis(120);
+
+/// the parts of the start menu that can be focused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuField {
+    Host,
+    Username,
+    Connect,
+    /// look for servers in the local network
+    Discover,
+    /// one of the servers found in the local network, by its index
+    Server(usize),
+}
+
+impl MenuField {
+    /// the field after this one, given how many servers were found in the local network
+    fn next(self, servers: usize) -> MenuField {
+        match self {
+            MenuField::Host => MenuField::Username,
+            MenuField::Username => MenuField::Connect,
+            MenuField::Connect => MenuField::Discover,
+            MenuField::Discover if servers > 0 => MenuField::Server(0),
+            MenuField::Server(idx) if idx + 1 < servers => MenuField::Server(idx + 1),
+            MenuField::Discover | MenuField::Server(_) => MenuField::Host,
+        }
+    }
+
+    fn previous(self, servers: usize) -> MenuField {
+        match self {
+            MenuField::Host if servers > 0 => MenuField::Server(servers - 1),
+            MenuField::Host => MenuField::Discover,
+            MenuField::Username => MenuField::Host,
+            MenuField::Connect => MenuField::Username,
+            MenuField::Discover => MenuField::Connect,
+            MenuField::Server(0) => MenuField::Discover,
+            MenuField::Server(idx) => MenuField::Server(idx - 1),
+        }
+    }
+}
+
+/// what the player wants to do after pressing a key in the start menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MenuAction {
+    Connect,
+    Discover,
+}
+
+#[derive(Debug)]
+pub struct StartMenu {
+    pub host: String,
+    pub username: String,
+    pub focus: MenuField,
+    pub error: Option<String>,
+    pub status: Option<String>,
+    /// the servers that answered the last search in the local network
+    pub servers: Vec<LanServer>,
+    /// how many times in a row connecting failed
+    pub failed_attempts: u32,
+    /// when to try connecting again, after connecting failed for a reason that might go away
+    retry_at: Option<Instant>,
+    /// connect right away, because both the host and the username were given on the command line
+    connect_on_start: bool,
+    /// the server and username of the last game, which F5 connects to again
+    pub last_session: Option<LastSession>,
+    /// how many animation frames were shown, moving the colors of the logo and the spinner
+    pub frame: usize,
+    /// whether something is going on that the status tells about, like connecting, shown by a spinner
+    pub waiting: bool,
+    /// don't animate anything, so nothing has to be redrawn while the player isn't doing anything
+    pub low_bandwidth: bool,
+}
+
+impl StartMenu {
+    pub fn new(host: Option<String>, username: Option<String>) -> StartMenu {
+        StartMenu {
+            connect_on_start: host.is_some() && username.is_some(),
+            focus: if host.is_none() {
+                MenuField::Host
+            } else if username.is_none() {
+                MenuField::Username
+            } else {
+                MenuField::Connect
+            },
+            host: host.unwrap_or_default(),
+            username: username.unwrap_or_default(),
+            error: None,
+            status: None,
+            servers: Vec::new(),
+            failed_attempts: 0,
+            retry_at: None,
+            last_session: None,
+            frame: 0,
+            waiting: false,
+            low_bandwidth: false,
+        }
+    }
+
+    /// offer to rejoin the last session, and use its username if none was given
+    pub fn remember(&mut self, last_session: LastSession) {
+        if self.username.trim().is_empty() {
+            self.username = last_session.username.clone();
+            if self.focus == MenuField::Username {
+                self.focus = MenuField::Connect;
+            }
+        }
+        self.last_session = Some(last_session);
+    }
+
+    /// check the inputs, returning the websocket address to connect to
+    fn validate(&self) -> std::result::Result<String, String> {
+        let invite = app::address_of_invite(&self.host);
+        let host = invite.as_deref().unwrap_or_else(|| self.host.trim());
+        if invite.is_none() && host.starts_with(app::INVITE_SCHEME) {
+            return Err("The invite link doesn't say which server to join".to_string());
+        }
+        if host.is_empty() {
+            return Err("Please enter the address of a server".to_string());
+        }
+        let host_without_scheme = host
+            .trim_start_matches("ws://")
+            .trim_start_matches("wss://");
+        match host_without_scheme.rsplit(':').next() {
+            Some(port) if port != host_without_scheme && port.parse::<u16>().is_ok() => {}
+            _ => return Err("The address needs a port, like <host>:<port>".to_string()),
+        }
+        if self.username.trim().is_empty() {
+            return Err("Please enter a username".to_string());
+        }
+
+        Ok(if host.starts_with("ws://") || host.starts_with("wss://") {
+            host.to_string()
+        } else {
+            format!("ws://{}", host)
+        })
+    }
+
+    /// react to a key press, returning what the user wants to do, if anything
+    fn handle_key_event(&mut self, event: &KeyEvent) -> Option<MenuAction> {
+        let KeyEvent { modifiers, code } = event;
+        let servers = self.servers.len();
+        let focused_input = match self.focus {
+            MenuField::Host => Some(&mut self.host),
+            MenuField::Username => Some(&mut self.username),
+            _ => None,
+        };
+        match code {
+            KeyCode::Tab | KeyCode::Down => self.focus = self.focus.next(servers),
+            KeyCode::BackTab | KeyCode::Up => self.focus = self.focus.previous(servers),
+            KeyCode::F(5) => {
+                let last_session = self.last_session.clone()?;
+                self.host = last_session.host;
+                self.username = last_session.username;
+                self.focus = MenuField::Connect;
+                return Some(MenuAction::Connect);
+            }
+            KeyCode::Left if self.focus == MenuField::Discover => self.focus = MenuField::Connect,
+            KeyCode::Right if self.focus == MenuField::Connect => self.focus = MenuField::Discover,
+            KeyCode::Enter => match self.focus {
+                MenuField::Connect => return Some(MenuAction::Connect),
+                MenuField::Discover => return Some(MenuAction::Discover),
+                MenuField::Server(idx) => {
+                    self.host = self.servers[idx].addr.to_string();
+                    // the address is known now, so only the username might be missing
+                    if self.username.trim().is_empty() {
+                        self.focus = MenuField::Username;
+                    } else {
+                        return Some(MenuAction::Connect);
+                    }
+                }
+                _ => self.focus = self.focus.next(servers),
+            },
+            KeyCode::Backspace => {
+                if let Some(input) = focused_input {
+                    input.pop();
+                }
+            }
+            KeyCode::Char('h') if modifiers.contains(KeyModifiers::CONTROL) => {
+                if let Some(input) = focused_input {
+                    input.pop();
+                }
+            }
+            KeyCode::Char(c) => {
+                if let Some(input) = focused_input {
+                    input.push(*c);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// insert pasted text into the focused input, like an address copied from a chat
+    fn paste(&mut self, text: &str) {
+        let text = input::single_line(text);
+        match self.focus {
+            MenuField::Host => self.host.push_str(text.trim()),
+            MenuField::Username => self.username.push_str(&text),
+            _ => {}
+        }
+    }
+
+    /// look for servers in the local network and list them in the menu
+    async fn discover<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
+        self.error = None;
+        self.status = Some("Looking for servers in the local network...".to_string());
+        let found = self.wait_for(terminal, discovery::discover()).await?;
+        self.status = None;
+        match found {
+            Ok(servers) if servers.is_empty() => {
+                self.servers.clear();
+                self.error = Some("No servers found in the local network".to_string());
+            }
+            Ok(servers) => {
+                self.servers = servers;
+                self.focus = MenuField::Server(0);
+            }
+            Err(err) => {
+                self.servers.clear();
+                self.error = Some(format!("Could not search the local network: {}", err));
+            }
+        }
+        Ok(())
+    }
+
+    /// resolve the address and connect to the server, showing the progress in the status line.
+    /// Returns `None` if connecting failed, in which case the error is shown in the menu.
+    async fn connect<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        evt_send: tokio::sync::mpsc::Sender<ClientEvent>,
+    ) -> Result<Option<App>> {
+        let addr = match self.validate() {
+            Ok(addr) => addr,
+            Err(err) => {
+                self.error = Some(err);
+                return Ok(None);
+            }
+        };
+        self.error = None;
+
+        let host = addr
+            .trim_start_matches("ws://")
+            .trim_start_matches("wss://");
+        let proxy = match Proxy::configured() {
+            Ok(proxy) => proxy,
+            Err(err) => {
+                self.error = Some(format!("Invalid proxy: {}", err));
+                return Ok(None);
+            }
+        };
+        let resolved = match proxy {
+            // the proxy resolves the address, which might not even be known here, like with Tor
+            Some(proxy) => format!("{} through {}", host, proxy),
+            None => {
+                self.status = Some(format!("Resolving {}...", host));
+                let resolved = match self
+                    .wait_for(terminal, tokio::net::lookup_host(host))
+                    .await?
+                {
+                    Ok(mut addrs) => addrs.next(),
+                    Err(_) => None,
+                };
+                match resolved {
+                    Some(resolved) => resolved.to_string(),
+                    None => {
+                        self.status = None;
+                        self.error = Some(format!("Could not find a server called {}", host));
+                        return Ok(None);
+                    }
+                }
+            }
+        };
+
+        self.status = Some(if self.failed_attempts == 0 {
+            format!("Connecting to {}...", resolved)
+        } else {
+            format!(
+                "Connecting to {} (attempt {})...",
+                resolved,
+                self.failed_attempts + 1
+            )
+        });
+        let username = Username::from(self.username.trim().to_string());
+        let connection = ServerSession::establish_connection(&addr, username, evt_send);
+        let connection = self.wait_for(terminal, connection).await?;
+        self.status = None;
+        match connection {
+            Ok(app) => {
+                let session = LastSession {
+                    host: self.host.trim().to_string(),
+                    username: self.username.trim().to_string(),
+                };
+                if let Err(err) = session.save() {
+                    log_debug!("Could not remember the session: {}", err);
+                }
+                self.last_session = Some(session);
+                Ok(Some(app))
+            }
+            Err(err) => {
+                self.failed_attempts += 1;
+                if err.is_temporary() {
+                    self.retry_at = Some(Instant::now() + retry_delay(self.failed_attempts));
+                }
+                self.error = Some(format!("Could not connect to {}: {}", resolved, err));
+                Ok(None)
+            }
+        }
+    }
+
+    /// how long to wait before redrawing the menu, if nothing happens.
+    /// Without animations, only the countdown to the next attempt to connect changes.
+    fn redraw_interval(&self) -> Duration {
+        if self.low_bandwidth {
+            Duration::from_secs(1)
+        } else {
+            ANIMATION_INTERVAL
+        }
+    }
+
+    /// move the animation on to its next frame
+    fn tick(&mut self) {
+        if !self.low_bandwidth {
+            self.frame += 1;
+        }
+    }
+
+    /// wait for `future` to finish, redrawing the menu every frame of the animation in the meantime,
+    /// so the spinner shows that something is going on
+    async fn wait_for<B: Backend, F: std::future::Future>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        future: F,
+    ) -> Result<F::Output> {
+        tokio::pin!(future);
+        self.waiting = true;
+        loop {
+            ui::draw_start_menu(self, terminal)?;
+            let tick = tokio::time::delay_for(self.redraw_interval());
+            tokio::select! {
+                output = &mut future => {
+                    self.waiting = false;
+                    return Ok(output);
+                }
+                _ = tick => self.tick(),
+            }
+        }
+    }
+
+    /// stop trying to connect again
+    fn cancel_retry(&mut self) {
+        self.retry_at = None;
+        self.failed_attempts = 0;
+        self.status = None;
+    }
+
+    /// show the start menu until a connection to a server is established
+    pub async fn run<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        chan: &mut tokio::sync::mpsc::Receiver<ClientEvent>,
+        evt_send: tokio::sync::mpsc::Sender<ClientEvent>,
+    ) -> Result<App> {
+        let mut should_connect = self.connect_on_start;
+        loop {
+            if should_connect {
+                should_connect = false;
+                self.retry_at = None;
+                if let Some(app) = self.connect(terminal, evt_send.clone()).await? {
+                    return Ok(app);
+                }
+            }
+
+            // count down to the next attempt, redrawing with every frame of the animation
+            let evt = match self.retry_at {
+                Some(retry_at) => {
+                    let remaining = retry_at.saturating_duration_since(Instant::now());
+                    if remaining == Duration::from_secs(0) {
+                        should_connect = true;
+                        continue;
+                    }
+                    self.status = Some(format!(
+                        "Retrying in {}s, attempt {} (any key cancels)",
+                        remaining.as_secs_f64().ceil(),
+                        self.failed_attempts + 1
+                    ));
+                    ui::draw_start_menu(self, terminal)?;
+                    let tick = tokio::time::delay_for(remaining.min(self.redraw_interval()));
+                    tokio::select! {
+                        evt = chan.recv() => evt,
+                        _ = tick => {
+                            self.tick();
+                            continue;
+                        }
+                    }
+                }
+                None if self.low_bandwidth => {
+                    ui::draw_start_menu(self, terminal)?;
+                    chan.recv().await
+                }
+                None => {
+                    ui::draw_start_menu(self, terminal)?;
+                    let tick = tokio::time::delay_for(ANIMATION_INTERVAL);
+                    tokio::select! {
+                        evt = chan.recv() => evt,
+                        _ = tick => {
+                            self.tick();
+                            continue;
+                        }
+                    }
+                }
+            };
+            match evt {
+                Some(ClientEvent::KeyInput(evt)) => {
+                    // the player wants to do something else, like fixing the address,
+                    // and connecting by hand starts counting from the beginning
+                    self.cancel_retry();
+                    match self.handle_key_event(&evt) {
+                        Some(MenuAction::Connect) => should_connect = true,
+                        Some(MenuAction::Discover) => self.discover(terminal).await?,
+                        None => {}
+                    }
+                }
+                Some(ClientEvent::Paste(text)) => {
+                    self.cancel_retry();
+                    self.paste(&text);
+                }
+                Some(_) => {}
+                None => {
+                    return Err(crate::client::error::Error::SendError(
+                        "input closed".into(),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// how long to wait after connecting failed `failed_attempts` times in a row
+fn retry_delay(failed_attempts: u32) -> Duration {
+    let factor = 2u32.pow(failed_attempts.saturating_sub(1).min(16));
+    (RETRY_DELAY * factor).min(MAX_RETRY_DELAY)
+}
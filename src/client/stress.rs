@@ -0,0 +1,216 @@
+use crate::{
+    client::error::Result,
+    data::{Brush, CanvasColor, Coord, Line, Message, Username},
+    message::{self, Heartbeat, ToClientMsg, ToServerMsg},
+};
+use futures_util::{SinkExt, StreamExt};
+use rand::Rng;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// how the simulated players behave
+#[derive(Debug, Clone)]
+pub struct StressOpts {
+    pub clients: usize,
+    /// lines every client draws per second
+    pub lines_per_sec: f64,
+    /// chat messages every client sends per second
+    pub messages_per_sec: f64,
+    pub duration: Duration,
+}
+
+/// the shortest time between two lines or chat messages of a client, however high the rate
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+/// what a single simulated player measured
+#[derive(Debug, Default)]
+struct ClientStats {
+    /// time from sending a line until the server accepted or rejected it
+    line_latencies: Vec<Duration>,
+    /// time from sending a chat message until it was broadcast back
+    chat_latencies: Vec<Duration>,
+    lines_sent: usize,
+    lines_rejected: usize,
+    messages_sent: usize,
+    /// whether the server closed the connection before the test was over
+    disconnected: bool,
+}
+
+/// connect `opts.clients` headless players to the server, let them draw and chat for
+/// `opts.duration`, and print how long the server took to answer them.
+pub async fn run(addr: &str, opts: StressOpts) -> Result<()> {
+    println!(
+        "Connecting {} clients to {}, running for {}s",
+        opts.clients,
+        addr,
+        opts.duration.as_secs()
+    );
+    let handles = (0..opts.clients)
+        .map(|idx| tokio::spawn(run_client(addr.to_string(), idx, opts.clone())))
+        .collect::<Vec<_>>();
+
+    let mut all_stats = Vec::new();
+    for result in futures_util::future::join_all(handles).await {
+        match result {
+            Ok(Ok(stats)) => all_stats.push(stats),
+            Ok(Err(err)) => eprintln!("A client failed: {}", err),
+            Err(err) => eprintln!("A client panicked: {}", err),
+        }
+    }
+
+    let mut line_latencies: Vec<Duration> = all_stats
+        .iter()
+        .flat_map(|stats| stats.line_latencies.iter().cloned())
+        .collect();
+    let mut chat_latencies: Vec<Duration> = all_stats
+        .iter()
+        .flat_map(|stats| stats.chat_latencies.iter().cloned())
+        .collect();
+    let lines_sent: usize = all_stats.iter().map(|stats| stats.lines_sent).sum();
+    let lines_rejected: usize = all_stats.iter().map(|stats| stats.lines_rejected).sum();
+    let messages_sent: usize = all_stats.iter().map(|stats| stats.messages_sent).sum();
+    let disconnected = all_stats.iter().filter(|stats| stats.disconnected).count();
+
+    println!(
+        "{} of {} clients finished, {} got disconnected",
+        all_stats.len(),
+        opts.clients,
+        disconnected
+    );
+    println!(
+        "lines: {} sent, {} answered, {} rejected",
+        lines_sent,
+        line_latencies.len(),
+        lines_rejected
+    );
+    print_percentiles("line latency", &mut line_latencies);
+    println!(
+        "chat: {} sent, {} broadcast back",
+        messages_sent,
+        chat_latencies.len()
+    );
+    print_percentiles("chat latency", &mut chat_latencies);
+    Ok(())
+}
+
+fn print_percentiles(name: &str, latencies: &mut [Duration]) {
+    if latencies.is_empty() {
+        println!("{}: no samples", name);
+        return;
+    }
+    latencies.sort();
+    let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+    println!(
+        "{}: p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+        name,
+        percentile(50),
+        percentile(90),
+        percentile(99),
+        latencies[latencies.len() - 1]
+    );
+}
+
+async fn run_client(addr: String, idx: usize, opts: StressOpts) -> Result<ClientStats> {
+    let username = Username::from(format!("stress-{}", idx));
+    let (ws, _) = tokio_tungstenite::connect_async(message::connect_request(&addr)?).await?;
+    let (mut ws_send, mut ws_recv) = ws.split();
+    ws_send
+        .send(tungstenite::Message::Text(username.clone().into()))
+        .await?;
+
+    let mut stats = ClientStats::default();
+    // the server answers lines in the order they were sent, so the oldest one is answered next
+    let mut pending_lines = VecDeque::new();
+    // chat messages might get dropped by the rate limit, so they are matched by their text
+    let mut pending_messages = HashMap::new();
+
+    let mut draw_interval = tokio::time::interval(interval_for_rate(opts.lines_per_sec));
+    let mut chat_interval = tokio::time::interval(interval_for_rate(opts.messages_per_sec));
+    // the server drops clients it doesn't hear from, which idle clients would be otherwise
+    let mut heartbeat = Heartbeat::default();
+    let mut next_heartbeat = tokio::time::delay_for(heartbeat.interval);
+    let mut end = tokio::time::delay_for(opts.duration);
+    loop {
+        tokio::select! {
+            _ = &mut next_heartbeat => {
+                let msg = message::encode(&ToServerMsg::Heartbeat);
+                ws_send.send(tungstenite::Message::Text(msg)).await?;
+                next_heartbeat.reset(tokio::time::Instant::now() + heartbeat.interval);
+            }
+            _ = draw_interval.tick(), if opts.lines_per_sec > 0.0 => {
+                let msg = message::encode(&ToServerMsg::NewLine(random_line()));
+                ws_send.send(tungstenite::Message::Text(msg)).await?;
+                pending_lines.push_back(Instant::now());
+                stats.lines_sent += 1;
+            }
+            _ = chat_interval.tick(), if opts.messages_per_sec > 0.0 => {
+                let text = format!("stress message {}", stats.messages_sent);
+                let msg = Message::UserMsg(username.clone(), text.clone());
+                let msg = message::encode(&ToServerMsg::NewMessage(msg));
+                ws_send.send(tungstenite::Message::Text(msg)).await?;
+                pending_messages.insert(text, Instant::now());
+                stats.messages_sent += 1;
+            }
+            msg = ws_recv.next() => match msg {
+                Some(Ok(tungstenite::Message::Text(msg))) => {
+                    match message::decode(&msg) {
+                        Ok(Some(ToClientMsg::InitialState(state))) => heartbeat = state.heartbeat,
+                        Ok(Some(ToClientMsg::LineAccepted(..))) => {
+                            if let Some(sent_at) = pending_lines.pop_front() {
+                                stats.line_latencies.push(sent_at.elapsed());
+                            }
+                        }
+                        Ok(Some(ToClientMsg::LineRejected)) => {
+                            if let Some(sent_at) = pending_lines.pop_front() {
+                                stats.line_latencies.push(sent_at.elapsed());
+                            }
+                            stats.lines_rejected += 1;
+                        }
+                        Ok(Some(ToClientMsg::NewMessage(Message::UserMsg(user, text)))) if user == username => {
+                            if let Some(sent_at) = pending_messages.remove(&text) {
+                                stats.chat_latencies.push(sent_at.elapsed());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Some(Ok(tungstenite::Message::Close(_))) | None => {
+                    stats.disconnected = true;
+                    break;
+                }
+                Some(Err(err)) => return Err(err.into()),
+                Some(Ok(_)) => {}
+            },
+            _ = &mut end => break,
+        }
+    }
+    let _ = ws_send.send(tungstenite::Message::Close(None)).await;
+    Ok(stats)
+}
+
+fn interval_for_rate(per_sec: f64) -> Duration {
+    if per_sec > 0.0 {
+        Duration::from_secs_f64(1.0 / per_sec).max(MIN_INTERVAL)
+    } else {
+        // never ticks in practice, the branch is disabled anyway
+        Duration::from_secs(3600)
+    }
+}
+
+/// a short line somewhere on a canvas of the default size
+fn random_line() -> Line {
+    let mut rng = rand::thread_rng();
+    let start = Coord(rng.gen_range(0, 100), rng.gen_range(0, 50));
+    let end = Coord(
+        (start.0 + rng.gen_range(0, 5)).min(99),
+        (start.1 + rng.gen_range(0, 5)).min(49),
+    );
+    Line {
+        start,
+        end,
+        brush: Brush::Paint(CanvasColor::Black),
+        size: 1,
+    }
+}
@@ -0,0 +1,91 @@
+use crate::client::{error::Result, log};
+use crossterm::{
+    event::{
+        poll, read, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers,
+    },
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use std::io::{stdout, Write};
+use std::time::Duration;
+
+/// puts the terminal into the mode the TUI needs while it's alive, and restores it when dropped,
+/// no matter whether the client quit normally, returned an error or panicked
+#[derive(Debug)]
+pub struct TerminalGuard {
+    _private: (),
+}
+
+impl TerminalGuard {
+    pub fn enter() -> Result<TerminalGuard> {
+        // from here on the guard restores whatever part of this already happened
+        let guard = TerminalGuard { _private: () };
+        enable_raw_mode()?;
+        execute!(stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// leave the alternate screen and raw mode and stop capturing the mouse, ignoring failures.
+/// Doing this more than once is fine.
+pub fn restore() {
+    let _ = execute!(stdout(), DisableMouseCapture, LeaveAlternateScreen);
+    let _ = disable_raw_mode();
+}
+
+/// restore the terminal before a panic message gets printed, so it ends up on the normal screen
+/// with proper line breaks, instead of being garbled in raw mode or lost with the alternate screen
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore();
+        // the panic may have happened while logging, so don't wait for the log
+        log::try_log(info.to_string());
+        default_hook(info);
+    }));
+}
+
+/// whether more input is waiting already. Key presses that arrive all at once come from pasting
+/// text, as nobody types that fast. The terminal could mark pasted text, but crossterm drops those markers.
+pub fn input_pending() -> crossterm::Result<bool> {
+    poll(Duration::from_millis(0))
+}
+
+/// read the rest of a paste starting with `first`, which is all the text of the key presses
+/// that are already waiting. Returns the text together with the event that ended the paste,
+/// which still has to be handled.
+pub fn read_paste(first: char) -> crossterm::Result<(String, Option<Event>)> {
+    let mut text = first.to_string();
+    while input_pending()? {
+        match read()? {
+            Event::Key(key) => match pasted_char(&key) {
+                Some(c) => text.push(c),
+                None => return Ok((text, Some(Event::Key(key)))),
+            },
+            other => return Ok((text, Some(other))),
+        }
+    }
+    Ok((text, None))
+}
+
+/// the character a key press stands for when it's part of pasted text
+pub fn pasted_char(key: &KeyEvent) -> Option<char> {
+    if key
+        .modifiers
+        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+    {
+        return None;
+    }
+    match key.code {
+        KeyCode::Char(c) => Some(c),
+        KeyCode::Enter => Some('\n'),
+        KeyCode::Tab => Some('\t'),
+        _ => None,
+    }
+}
@@ -0,0 +1,566 @@
+use crate::{
+    client::{
+        app::{
+            address_of_invite, AppCanvas, Focus, RevealedWord, Timelapse, STROKE_SMOOTHING,
+            STROKE_SPACING,
+        },
+        config::LastSession,
+        discovery::LanServer,
+        input::LineInput,
+        log::{Logger, RECENT_LINES},
+        start_menu::{MenuField, StartMenu},
+        ui,
+    },
+    data::{Brush, CanvasColor, Coord, Line, Message, TurnRotation, Username},
+    game::SkribblState,
+    message::{InitialState, ToClientMsg},
+    server::{server::ROUND_DURATION, skribbl::SkribblGame, words::Word},
+    testing::{buffer_lines, offline_app},
+    ClientEvent,
+};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use rand::{rngs::StdRng, SeedableRng};
+use tui::{backend::TestBackend, Terminal};
+
+fn terminal(width: u16, height: u16) -> Terminal<TestBackend> {
+    Terminal::new(TestBackend::new(width, height)).unwrap()
+}
+
+fn game(word: &str) -> SkribblState {
+    let words = vec![Word {
+        text: word.to_string(),
+        difficulty: crate::data::Difficulty::Easy,
+        language: None,
+        definition: None,
+    }];
+    let users = vec![
+        Username::from("alice".to_string()),
+        Username::from("bob".to_string()),
+    ];
+    SkribblGame::new(
+        users,
+        words,
+        TurnRotation::InOrder,
+        ROUND_DURATION,
+        &mut StdRng::seed_from_u64(0),
+    )
+    .view
+}
+
+fn initial_state(skribbl_state: Option<SkribblState>) -> InitialState {
+    InitialState {
+        leader: Some(Username::from("alice".to_string())),
+        lines: vec![Line {
+            start: Coord(2, 1),
+            end: Coord(9, 1),
+            brush: Brush::Paint(CanvasColor::Red),
+            size: 1,
+        }],
+        dimensions: (20, 6),
+        skribbl_state,
+        heartbeat: Default::default(),
+        envelopes: false,
+    }
+}
+
+/// render the app and compare every row of the terminal with `expected`
+fn assert_renders(app: &mut crate::client::app::App, expected: &[&str]) {
+    let mut terminal = terminal(52, 8);
+    ui::draw(app, &mut terminal).unwrap();
+    assert_eq!(buffer_lines(terminal.backend().buffer()), expected);
+}
+
+#[test]
+fn start_menu() {
+    let mut terminal = terminal(40, 14);
+    let mut menu = StartMenu::new(Some("localhost:8888".to_string()), None);
+    menu.error = Some("Could not connect".to_string());
+    ui::draw_start_menu(&menu, &mut terminal).unwrap();
+    assert_eq!(
+        buffer_lines(terminal.backend().buffer()),
+        [
+            "                                        ",
+            "                Termibbl                ",
+            "                                        ",
+            "┌Server address (<host>:<port>)────────┐",
+            "│localhost:8888                        │",
+            "└──────────────────────────────────────┘",
+            "┌Username──────────────────────────────┐",
+            "│                                      │",
+            "└──────────────────────────────────────┘",
+            "    [ Connect ]  [ Find LAN servers ]   ",
+            "                                        ",
+            "                                        ",
+            "            Could not connect           ",
+            "                                        ",
+        ]
+    );
+}
+
+#[test]
+fn start_menu_shows_the_logo_if_there_is_room() {
+    let mut terminal = terminal(44, 15);
+    let mut menu = StartMenu::new(Some("localhost:8888".to_string()), Some("bob".to_string()));
+    menu.status = Some("Connecting...".to_string());
+    menu.waiting = true;
+    menu.frame = 2;
+    ui::draw_start_menu(&menu, &mut terminal).unwrap();
+    let lines = buffer_lines(terminal.backend().buffer());
+    assert_eq!(
+        lines[..4],
+        [
+            "  _____                   _  _     _     _  ",
+            " |_   _| ___  _ _  _ __  (_)| |__ | |__ | | ",
+            "   | |  / -_)| '_|| '  \\ | || '_ \\| '_ \\| | ",
+            "   |_|  \\___||_|  |_|_|_||_||_.__/|_.__/|_| ",
+        ]
+    );
+    assert_eq!(lines[13], "               ⠹ Connecting...              ");
+
+    // the colors move on with every frame
+    let color_at =
+        |terminal: &Terminal<TestBackend>| terminal.backend().buffer().get(2, 1).style.fg;
+    let before = color_at(&terminal);
+    menu.frame += 1;
+    ui::draw_start_menu(&menu, &mut terminal).unwrap();
+    assert_ne!(color_at(&terminal), before);
+}
+
+#[test]
+fn start_menu_offers_to_rejoin_the_last_server() {
+    let mut terminal = terminal(40, 14);
+    let mut menu = StartMenu::new(None, None);
+    menu.last_session = Some(LastSession {
+        host: "localhost:8888".to_string(),
+        username: "bob".to_string(),
+    });
+    ui::draw_start_menu(&menu, &mut terminal).unwrap();
+    assert_eq!(
+        buffer_lines(terminal.backend().buffer())[9..12],
+        [
+            "    [ Connect ]  [ Find LAN servers ]   ",
+            "                                        ",
+            "    F5: rejoin localhost:8888 as bob    ",
+        ]
+    );
+}
+
+#[test]
+fn start_menu_fills_in_the_last_username() {
+    let last_session = LastSession {
+        host: "localhost:8888".to_string(),
+        username: "bob".to_string(),
+    };
+    let mut menu = StartMenu::new(Some("example.com:8888".to_string()), None);
+    menu.remember(last_session.clone());
+    assert_eq!(menu.username, "bob");
+    assert_eq!(menu.focus, MenuField::Connect);
+
+    let mut menu = StartMenu::new(None, Some("alice".to_string()));
+    menu.remember(last_session);
+    assert_eq!(menu.username, "alice");
+    assert_eq!(menu.focus, MenuField::Host);
+}
+
+#[test]
+fn start_menu_lists_lan_servers() {
+    let mut terminal = terminal(40, 14);
+    let mut menu = StartMenu::new(None, None);
+    menu.servers = vec![
+        LanServer {
+            addr: "192.168.0.2:8888".parse().unwrap(),
+            players: 1,
+        },
+        LanServer {
+            addr: "192.168.0.7:9000".parse().unwrap(),
+            players: 3,
+        },
+    ];
+    menu.focus = MenuField::Server(1);
+    ui::draw_start_menu(&menu, &mut terminal).unwrap();
+    assert_eq!(
+        buffer_lines(terminal.backend().buffer())[8..],
+        [
+            "    [ Connect ]  [ Find LAN servers ]   ",
+            "                                        ",
+            "                                        ",
+            "                                        ",
+            "       192.168.0.2:8888 (1 player)      ",
+            "      192.168.0.7:9000 (3 players)      ",
+        ]
+    );
+}
+
+#[test]
+fn free_draw() {
+    let mut app = offline_app("alice", initial_state(None), (52, 8));
+    app.chat.messages.push(Message::UserMsg(
+        Username::from("bob".to_string()),
+        "hi".to_string(),
+    ));
+    app.chat.input.set_text("hello".to_string());
+    assert_renders(
+        &mut app,
+        &[
+            "┌──────────────────┐┌Your message──────────────────┐",
+            "│                  ││hello                         │",
+            "│                  │└──────────────────────────────┘",
+            "│                  │┌Chat──────────────────────────┐",
+            "│                  ││bob: hi                       │",
+            "└──────────────────┘│                              │",
+            "                    │                              │",
+            "                    └──────────────────────────────┘",
+        ],
+    );
+}
+
+#[test]
+fn stats_are_shown_over_the_game() {
+    let mut app = offline_app("alice", initial_state(None), (52, 8));
+    app.show_stats = true;
+    assert_renders(
+        &mut app,
+        &[
+            "┌──────────────────┐┌Your message───────┌Stats─────┐",
+            "│                  ││                   │0 fps     │",
+            "│                  │└───────────────────│0 events/s│",
+            "│                  │┌Chat───────────────│up 0 B    │",
+            "│                  ││                   │down 0 B  │",
+            "└──────────────────┘│                   │ping -    │",
+            "                    │                   └──────────┘",
+            "                    └──────────────────────────────┘",
+        ],
+    );
+}
+
+#[test]
+fn lines_are_painted_onto_the_canvas() {
+    let mut terminal = terminal(52, 8);
+    let mut app = offline_app("alice", initial_state(None), (52, 8));
+    ui::draw(&mut app, &mut terminal).unwrap();
+    let buffer = terminal.backend().buffer();
+    // without zoom or scrolling, every canvas cell is shown by the terminal cell at the same position
+    let red = ui::tui_color(CanvasColor::Red);
+    assert_eq!(buffer.get(2, 1).style.bg, red);
+    assert_eq!(buffer.get(9, 1).style.bg, red);
+    assert_ne!(buffer.get(10, 1).style.bg, red);
+    assert_ne!(buffer.get(2, 2).style.bg, red);
+}
+
+#[test]
+fn lines_arriving_at_once_are_shown_one_after_another() {
+    let mut app = offline_app("bob", initial_state(None), (52, 8));
+    let canvas = &mut app.canvas;
+    let painted = |canvas: &AppCanvas| canvas.cells().count();
+    let already_painted = painted(canvas);
+    let line = |y| Line::new(Coord(15, y), Coord(15, y), CanvasColor::Blue);
+    let now = std::time::Instant::now();
+    for idx in 0..20 {
+        let index = canvas.lines().len();
+        assert!(canvas.add_line_smoothly(index, line(idx % 6), now));
+    }
+    canvas.show_incoming(now);
+    assert_eq!(painted(canvas), already_painted);
+    canvas.show_incoming(now + STROKE_SPACING * 2);
+    assert_eq!(painted(canvas), already_painted + 2);
+    canvas.show_incoming(now + STROKE_SMOOTHING);
+    assert_eq!(painted(canvas), already_painted + 6);
+    assert!(!canvas.has_incoming());
+}
+
+#[tokio::test]
+async fn lines_are_shown_right_away_with_low_bandwidth() {
+    let mut app = offline_app("bob", initial_state(None), (52, 8));
+    app.config.low_bandwidth = true;
+    let already_painted = app.canvas.cells().count();
+    for y in 0..3 {
+        let index = app.canvas.lines().len();
+        let line = Line::new(Coord(15, y), Coord(15, y), CanvasColor::Blue);
+        app.handle_event(ClientEvent::ServerMessage(ToClientMsg::NewLine(
+            index, line,
+        )))
+        .await
+        .unwrap();
+    }
+    assert_eq!(app.canvas.cells().count(), already_painted + 3);
+    assert!(!app.canvas.has_incoming());
+}
+
+#[test]
+fn drawer_sees_the_word() {
+    let mut app = offline_app("alice", initial_state(Some(game("polybar"))), (52, 8));
+    app.remaining_time = Some(42);
+    assert_renders(
+        &mut app,
+        &[
+            "┌──────────────────┐alice drawing polybar (easy)    ",
+            "│                  │┌Players [time: 42]────────────┐",
+            "│                  ││alice (leader): 0             │",
+            "│                  ││bob: 0                        │",
+            "│                  │└──────────────────────────────┘",
+            "└──────────────────┘┌Your message──────────────────┐",
+            "                    │                              │",
+            "                    └──────────────────────────────┘",
+        ],
+    );
+}
+
+#[test]
+fn guesser_only_sees_revealed_characters() {
+    let mut state = game("polybar");
+    state.revealed_characters = vec![3];
+    let mut app = offline_app("bob", initial_state(Some(state)), (52, 8));
+    app.remaining_time = Some(42);
+    assert_renders(
+        &mut app,
+        &[
+            "┌alice is drawing──┐alice drawing ???y??? (easy)    ",
+            "│                  │┌Players [time: 42]────────────┐",
+            "│                  ││alice (leader): 0             │",
+            "│                  ││bob: 0                        │",
+            "│                  │└──────────────────────────────┘",
+            "└_ _ _ y _ _ _  (7)┘┌Your message──────────────────┐",
+            "                    │                              │",
+            "                    └──────────────────────────────┘",
+        ],
+    );
+}
+
+#[test]
+fn drawing_is_replayed_before_the_word_is_shown() {
+    let mut terminal = terminal(52, 8);
+    let mut app = offline_app("alice", initial_state(Some(game("polybar"))), (52, 8));
+    let drawing = app.canvas.lines().to_vec();
+    app.canvas.clear();
+    app.timelapse = Some(Timelapse::new(drawing));
+    app.revealed_word = Some(RevealedWord {
+        word: "polybar".to_string(),
+        definition: None,
+        shown_until: std::time::Instant::now() + std::time::Duration::from_secs(60),
+    });
+    ui::draw(&mut app, &mut terminal).unwrap();
+    let lines = buffer_lines(terminal.backend().buffer());
+    assert!(lines[0].starts_with("┌Replay"));
+    assert!(lines.iter().all(|line| !line.contains("The word was")));
+}
+
+#[test]
+fn logger_only_keeps_the_latest_lines() {
+    let mut logger = Logger::new();
+    for idx in 0..RECENT_LINES + 5 {
+        logger.log(format!("line {}", idx));
+    }
+    let recent = logger.recent().collect::<Vec<_>>();
+    assert_eq!(recent.len(), RECENT_LINES);
+    assert!(recent[0].ends_with("] line 5"));
+}
+
+#[test]
+fn tiny_terminals_ask_for_more_room() {
+    let mut terminal = terminal(16, 6);
+    let mut app = offline_app("alice", initial_state(None), (16, 6));
+    ui::draw(&mut app, &mut terminal).unwrap();
+    assert_eq!(
+        buffer_lines(terminal.backend().buffer()),
+        [
+            "                ",
+            "  Terminal too  ",
+            "   small (need  ",
+            "      20x8)     ",
+            "                ",
+            "                ",
+        ]
+    );
+}
+
+#[test]
+fn narrow_terminals_only_show_the_chat_to_guessers() {
+    let mut terminal = terminal(32, 8);
+    let mut app = offline_app("bob", initial_state(Some(game("polybar"))), (32, 8));
+    app.remaining_time = Some(42);
+    ui::draw(&mut app, &mut terminal).unwrap();
+    assert_eq!(
+        buffer_lines(terminal.backend().buffer())[..2],
+        [
+            "alice drawing ??????? (easy)    ",
+            "┌Players [time: 42]────────────┐",
+        ]
+    );
+}
+
+#[test]
+fn narrow_terminals_only_show_the_canvas_to_the_drawer() {
+    let mut terminal = terminal(32, 8);
+    let mut app = offline_app("alice", initial_state(Some(game("polybar"))), (32, 8));
+    app.remaining_time = Some(42);
+    ui::draw(&mut app, &mut terminal).unwrap();
+    let lines = buffer_lines(terminal.backend().buffer());
+    assert_eq!(lines[0], "┌──────────────────┐            ");
+    assert_eq!(lines[7], "alice drawing polybar [time: 42]");
+}
+
+#[test]
+fn a_wider_sidebar_leaves_less_room_for_the_canvas() {
+    let mut app = offline_app("alice", initial_state(None), (52, 8));
+    app.config.sidebar_width = 40;
+    let mut terminal = terminal(52, 8);
+    ui::draw(&mut app, &mut terminal).unwrap();
+    assert_eq!(
+        buffer_lines(terminal.backend().buffer())[0],
+        "┌──────────┐┌Your message──────────────────────────┐"
+    );
+    assert_eq!(app.canvas.visible_size(), (12, 6));
+}
+
+#[test]
+fn only_the_drawer_gets_the_palette() {
+    for (username, has_palette) in [("alice", true), ("bob", false)].iter() {
+        let mut app = offline_app(username, initial_state(Some(game("polybar"))), (52, 8));
+        let mut terminal = terminal(52, 8);
+        ui::draw(&mut app, &mut terminal).unwrap();
+        let swatch = terminal.backend().buffer().get(0, 0);
+        assert_eq!(swatch.style.bg != tui::style::Color::Reset, *has_palette);
+    }
+}
+
+#[tokio::test]
+async fn guessers_see_where_the_pen_is() {
+    let mut app = offline_app("bob", initial_state(Some(game("polybar"))), (52, 8));
+    let mut terminal = terminal(52, 8);
+    let pen = Coord(4, 2);
+    let moved = |pos| ClientEvent::ServerMessage(ToClientMsg::PenMoved(pos));
+    app.handle_event(moved(Some(pen))).await.unwrap();
+    ui::draw(&mut app, &mut terminal).unwrap();
+    let cell = app.canvas.to_screen_coord(pen).unwrap();
+    assert_eq!(terminal.backend().buffer().get(cell.0, cell.1).symbol, "+");
+
+    app.handle_event(moved(None)).await.unwrap();
+    ui::draw(&mut app, &mut terminal).unwrap();
+    assert_eq!(terminal.backend().buffer().get(cell.0, cell.1).symbol, " ");
+}
+
+#[test]
+fn hints_show_a_slot_for_every_letter() {
+    assert_eq!(ui::hint_text("??y"), "_ _ y  (3)");
+    assert_eq!(
+        ui::hint_text("i?? c????-?"),
+        "i _ _   c _ _ _ _ - _  (3, 7)"
+    );
+}
+
+#[test]
+fn the_player_list_can_be_hidden() {
+    let mut app = offline_app("bob", initial_state(Some(game("polybar"))), (52, 8));
+    app.remaining_time = Some(42);
+    app.config.hide_player_list = true;
+    assert_renders(
+        &mut app,
+        &[
+            "┌alice is drawing──┐alice drawing ??????? (easy) [ti",
+            "│                  │┌Your message──────────────────┐",
+            "│                  ││                              │",
+            "│                  │└──────────────────────────────┘",
+            "│                  │┌Chat──────────────────────────┐",
+            "└_ _ _ _ _ _ _  (7)┘│                              │",
+            "                    │                              │",
+            "                    └──────────────────────────────┘",
+        ],
+    );
+}
+
+#[test]
+fn invites_link_to_the_server() {
+    let mut app = offline_app("bob", initial_state(None), (52, 8));
+    assert_eq!(app.invite_link(), "termibbl://localhost:8888");
+    assert_eq!(
+        address_of_invite(&app.invite_link()).as_deref(),
+        Some("ws://localhost:8888")
+    );
+    app.session.addr = "wss://example.com:443".to_string();
+    assert_eq!(app.invite_link(), "wss://example.com:443");
+
+    assert_eq!(
+        address_of_invite("termibbl://example.com:8888/ROOMKEY").as_deref(),
+        Some("ws://example.com:8888")
+    );
+    assert_eq!(address_of_invite("example.com:8888"), None);
+    assert_eq!(address_of_invite("termibbl://"), None);
+    assert_eq!(address_of_invite("termibbl:///ROOMKEY"), None);
+}
+
+#[tokio::test]
+async fn keys_only_go_to_the_focused_pane() {
+    let mut app = offline_app("alice", initial_state(None), (52, 8));
+    let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+    app.handle_key_event(&key(KeyCode::Tab)).await.unwrap();
+    app.handle_key_event(&key(KeyCode::Char('a')))
+        .await
+        .unwrap();
+    assert_eq!(app.chat.input.text(), "");
+
+    let mut terminal = terminal(52, 8);
+    ui::draw(&mut app, &mut terminal).unwrap();
+    assert!(buffer_lines(terminal.backend().buffer())[0].starts_with("┌Draw mode"));
+
+    app.handle_key_event(&key(KeyCode::Tab)).await.unwrap();
+    app.handle_key_event(&key(KeyCode::Char('a')))
+        .await
+        .unwrap();
+    assert_eq!(app.chat.input.text(), "a");
+}
+
+#[test]
+fn input_moves_and_deletes_whole_graphemes() {
+    let mut input = LineInput::default();
+    input.set_text("ne\u{301}e".to_string());
+    input.move_left();
+    input.move_left();
+    input.backspace();
+    assert_eq!(input.text(), "e\u{301}e");
+    input.delete();
+    assert_eq!(input.text(), "e");
+    input.insert('x');
+    assert_eq!(input.split_at_cursor(), ("x", "e"));
+}
+
+#[test]
+fn input_deletes_the_word_before_the_cursor() {
+    let mut input = LineInput::default();
+    input.set_text("guess the word  ".to_string());
+    input.delete_word();
+    assert_eq!(input.text(), "guess the ");
+    input.home();
+    input.move_word_right();
+    assert_eq!(input.split_at_cursor(), ("guess", " the "));
+}
+
+#[test]
+fn input_history_keeps_what_was_typed() {
+    let mut input = LineInput::default();
+    input.set_text("first".to_string());
+    input.submit();
+    input.set_text("second".to_string());
+    input.submit();
+    input.set_text("draft".to_string());
+    input.history_previous();
+    input.history_previous();
+    assert_eq!(input.text(), "first");
+    input.history_next();
+    assert_eq!(input.text(), "second");
+    input.history_next();
+    assert_eq!(input.text(), "draft");
+}
+
+#[tokio::test]
+async fn pasted_text_is_inserted_as_a_single_line() {
+    let mut app = offline_app("alice", initial_state(None), (52, 8));
+    app.chat.input.set_text("I guess ".to_string());
+    let paste = || ClientEvent::Paste("polybar\nor\tpolly\n".to_string());
+    app.handle_event(paste()).await.unwrap();
+    assert_eq!(app.chat.input.text(), "I guess polybar or polly");
+
+    // nothing happens on the canvas, instead of every character acting as a key
+    app.focus = Focus::Canvas;
+    app.handle_event(paste()).await.unwrap();
+    assert_eq!(app.chat.input.text(), "I guess polybar or polly");
+}
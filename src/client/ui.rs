@@ -1,97 +1,444 @@
 use crate::{
-    client::app::{App, AppCanvas},
+    client::app::{App, AppCanvas, Focus, RevealedWord, Timelapse},
     client::error::Result,
-    data::{Coord, Message},
-    server::skribbl::{PlayerState, SkribblState},
+    client::input::LineInput,
+    client::log::{self, NetStats},
+    client::start_menu::{MenuField, StartMenu},
+    data::{Brush, CanvasColor, Coord, GameEvent, Line, Message},
+    game::{Drawing, PlayerState, SkribblState},
 };
 
 use super::Username;
+use std::collections::HashMap;
 use tui::{
     backend::Backend,
-    layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
-    widgets::{Block, Borders, List, Paragraph, Text, Widget},
-    Terminal,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, Clear, List, Paragraph, Text, Widget},
+    Frame, Terminal,
 };
+use unicode_segmentation::UnicodeSegmentation;
+
+/// width the sidebar keeps even when the canvas is larger than the terminal
+pub const MIN_SIDEBAR_WIDTH: u16 = 30;
+
+/// the narrowest the canvas may get next to the sidebar, before one of them is hidden
+pub const MIN_CANVAS_WIDTH: u16 = 10;
+
+/// the smallest terminal anything useful fits into, which is the chat on its own
+pub const MIN_TERMINAL_SIZE: (u16, u16) = (20, 8);
+
+/// the logo above the start menu, shown instead of the plain title if there is room for it
+const LOGO: [&str; 4] = [
+    r" _____                   _  _     _     _ ",
+    r"|_   _| ___  _ _  _ __  (_)| |__ | |__ | |",
+    r"  | |  / -_)| '_|| '  \ | || '_ \| '_ \| |",
+    r"  |_|  \___||_|  |_|_|_||_||_.__/|_.__/|_|",
+];
+
+/// the colors that sweep over the logo, moving by one column every animation frame
+const LOGO_COLORS: [Color; 6] = [
+    Color::Red,
+    Color::Yellow,
+    Color::Green,
+    Color::Cyan,
+    Color::Blue,
+    Color::Magenta,
+];
+
+/// the frames of the spinner shown while the start menu waits, e.g. for a server to answer
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// how the screen is split between the canvas and the sidebar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenLayout {
+    /// the canvas on the left, the game state and the chat on the right
+    Full,
+    /// only the canvas, with a status line below it
+    CanvasOnly,
+    /// only the game state and the chat, as there is no room for the canvas
+    ChatOnly,
+    /// not even the chat fits, so only a hint to enlarge the terminal is shown
+    TooSmall,
+}
+
+impl ScreenLayout {
+    /// the layout for a terminal of the given size. If the canvas and the sidebar don't fit
+    /// side by side, the canvas is kept while it's this player's turn to draw.
+    pub fn for_terminal(size: (u16, u16), sidebar_collapsed: bool, drawing: bool) -> Self {
+        if size.0 < MIN_TERMINAL_SIZE.0 || size.1 < MIN_TERMINAL_SIZE.1 {
+            ScreenLayout::TooSmall
+        } else if sidebar_collapsed || size.0 < MIN_CANVAS_WIDTH + MIN_SIDEBAR_WIDTH && drawing {
+            ScreenLayout::CanvasOnly
+        } else if size.0 < MIN_CANVAS_WIDTH + MIN_SIDEBAR_WIDTH {
+            ScreenLayout::ChatOnly
+        } else {
+            ScreenLayout::Full
+        }
+    }
+}
 
 pub fn draw<B: Backend>(app: &mut App, terminal: &mut Terminal<B>) -> Result<()> {
-    let dimensions = app.canvas.dimensions;
+    app.update_layout();
+    let visible_size = app.canvas.visible_size();
     terminal.draw(|mut f| {
         use Constraint::*;
         let size = f.size();
-        let main_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .margin(0)
-            .constraints(
-                [
-                    Length(dimensions.0 as u16),
-                    Length(if size.width < dimensions.0 as u16 {
-                        size.width
-                    } else {
-                        size.width - dimensions.0 as u16
-                    }),
-                ]
-                .as_ref(),
-            )
-            .split(size);
+        match app.canvas.layout {
+            ScreenLayout::Full => {
+                let main_chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .margin(0)
+                    .constraints(
+                        [
+                            Length(visible_size.0),
+                            Length(size.width.saturating_sub(visible_size.0)),
+                        ]
+                        .as_ref(),
+                    )
+                    .split(size);
+                draw_canvas(&mut f, app, main_chunks[0]);
+                draw_sidebar(&mut f, app, main_chunks[1]);
+            }
+            ScreenLayout::CanvasOnly => {
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .margin(0)
+                    .constraints([Min(0), Length(1)].as_ref())
+                    .split(size);
+                draw_canvas(&mut f, app, chunks[0]);
+                f.render_widget(
+                    Paragraph::new([Text::raw(status_line(app))].iter()),
+                    chunks[1],
+                );
+            }
+            ScreenLayout::ChatOnly => draw_sidebar(&mut f, app, size),
+            ScreenLayout::TooSmall => {
+                let text = format!(
+                    "Terminal too small (need {}x{})",
+                    MIN_TERMINAL_SIZE.0, MIN_TERMINAL_SIZE.1
+                );
+                let rect = Rect {
+                    y: size.y + size.height.saturating_sub(3) / 2,
+                    height: u16::min(size.height, 3),
+                    ..size
+                };
+                f.render_widget(
+                    Paragraph::new([Text::styled(text, Style::default().fg(Color::Red))].iter())
+                        .alignment(Alignment::Center)
+                        .wrap(true),
+                    rect,
+                );
+            }
+        }
+        if app.show_stats && app.canvas.layout != ScreenLayout::TooSmall {
+            f.render_widget(StatsWidget::new(app), size);
+        }
+    })?;
+    Ok(())
+}
 
-        let canvas_widget = CanvasWidget::new(
-            &app.canvas,
+/// the canvas, or the gallery of drawings while one is shown
+fn draw_canvas<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let visible_size = app.canvas.visible_size();
+    if let Some(drawings) = &app.gallery {
+        let gallery = GalleryWidget::new(drawings)
+            .numbered(app.voting)
+            .selected(app.own_vote);
+        f.render_widget(gallery, area);
+        return;
+    }
+
+    let canvas_rect = Rect {
+        width: u16::min(visible_size.0, area.width),
+        height: u16::min(visible_size.1, area.height),
+        ..area
+    };
+    let timelapse = app.timelapse.as_ref().and_then(Timelapse::visible_lines);
+    if let Some(lines) = timelapse {
+        let canvas = app.canvas.with_lines(lines.to_vec());
+        let timelapse_widget = CanvasWidget::new(
+            &canvas,
             Block::default()
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(app.current_color.into())),
-        );
-
-        let game_state_height = app
+                .title("Replay")
+                .border_style(Style::default().fg(tui_color(app.current_color))),
+        )
+        .patterns(app.config.colorblind);
+        f.render_widget(timelapse_widget, canvas_rect);
+    } else {
+        // the drawing tools are only shown to the player whose turn it is
+        let drawing_user = app
             .game_state
             .as_ref()
-            .map(|x| x.player_states.len() + 3)
-            .unwrap_or(0) as u16;
+            .filter(|state| !state.is_drawing(&app.session.username))
+            .map(|state| format!("{} is drawing", state.drawing_user));
+        let mut block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(tui_color(app.current_color)));
+        if let Some(drawing_user) = &drawing_user {
+            block = block.title(drawing_user);
+        } else if app.focus == Focus::Canvas {
+            block = block.title("Draw mode");
+        }
+        let canvas_widget = CanvasWidget::new(&app.canvas, block)
+            .patterns(app.config.colorblind)
+            .palette(drawing_user.is_none());
+        f.render_widget(canvas_widget, canvas_rect);
+        let revealed_word = app
+            .revealed_word
+            .as_ref()
+            .filter(|revealed| revealed.shown_until > std::time::Instant::now());
+        if let Some(revealed) = revealed_word {
+            f.render_widget(RevealWidget::new(revealed), canvas_rect);
+        } else if let Some(state) = &app.game_state {
+            if !state.knows_word(&app.session.username) {
+                let hint = state.hinted_current_word();
+                f.render_widget(HintWidget::new(&hint), canvas_rect);
+            }
+        }
+    }
+}
+
+/// the game state above the chat
+fn draw_sidebar<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    use Constraint::*;
+    let game_state_height = match (&app.game_state, app.starting_in) {
+        (Some(_), _) if app.config.hide_player_list => 1,
+        (Some(state), _) => (state.player_states.len() + state.waiting_players.len()) as u16 + 3,
+        (None, Some(_)) => 1,
+        (None, None) => 0,
+    };
+
+    let sidebar_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(0)
+        .constraints([Length(game_state_height), Percentage(100)].as_ref())
+        .split(area);
+
+    if let Some(skribbl_state) = app.game_state.as_ref() {
+        let skribbl_widget = SkribblStateWidget::new(
+            skribbl_state,
+            &app.session.username,
+            app.leader.as_ref(),
+            app.remaining_time.unwrap_or(0),
+            Block::default().borders(Borders::NONE),
+        )
+        .show_players(!app.config.hide_player_list);
+        f.render_widget(skribbl_widget, sidebar_chunks[0]);
+    } else if let Some(secs) = app.starting_in {
+        let text = format!("The game starts in {}s", secs);
+        f.render_widget(
+            Paragraph::new([Text::styled(text, Style::default().fg(Color::Cyan))].iter()),
+            sidebar_chunks[0],
+        );
+    }
+
+    let displayed_messages = app
+        .chat
+        .messages
+        .iter()
+        .filter(|msg| app.is_message_visible(msg))
+        .collect::<Vec<_>>();
 
-        let sidebar_chunks = Layout::default()
+    let chat_widget = ChatWidget::new(
+        displayed_messages.as_slice(),
+        &app.chat.input,
+        Block::default().borders(Borders::NONE),
+    )
+    .focused(app.focus == Focus::Chat);
+    if app.show_debug {
+        let chat_chunks = Layout::default()
             .direction(Direction::Vertical)
             .margin(0)
-            .constraints([Length(game_state_height), Percentage(100)].as_ref())
-            .split(main_chunks[1]);
-
-        if let Some(skribbl_state) = app.game_state.as_mut() {
-            let skribbl_widget = SkribblStateWidget::new(
-                &skribbl_state,
-                &app.session.username,
-                app.remaining_time.unwrap_or(0),
-                Block::default().borders(Borders::NONE),
-            );
-            f.render_widget(skribbl_widget, sidebar_chunks[0]);
+            .constraints([Percentage(50), Percentage(50)].as_ref())
+            .split(sidebar_chunks[1]);
+        f.render_widget(chat_widget, chat_chunks[0]);
+        let log_lines = log::recent_lines();
+        let debug_widget = DebugWidget::new(&log_lines, &app.session.stats);
+        f.render_widget(debug_widget, chat_chunks[1]);
+    } else {
+        f.render_widget(chat_widget, sidebar_chunks[1]);
+    }
+}
+
+/// what's shown below the canvas while the sidebar is hidden: the message being typed,
+/// or otherwise the word and the remaining time
+fn status_line(app: &App) -> String {
+    if !app.chat.input.is_empty() {
+        return format!("> {}", app.chat.input.text());
+    }
+    match &app.game_state {
+        Some(state) => {
+            let word = if state.is_drawing(&app.session.username) {
+                state.current_word().to_string()
+            } else {
+                state.hinted_current_word().to_string()
+            };
+            format!(
+                "{} drawing {} [time: {}]",
+                state.drawing_user,
+                word,
+                app.remaining_time.unwrap_or(0)
+            )
         }
+        None => "F4 shows the chat".to_string(),
+    }
+}
 
-        let canvas_rect = Rect {
-            height: u16::min(dimensions.1 as u16, main_chunks[0].height),
-            ..main_chunks[0]
+pub fn draw_start_menu<B: Backend>(menu: &StartMenu, terminal: &mut Terminal<B>) -> Result<()> {
+    terminal.draw(|mut f| {
+        use Constraint::*;
+        let size = f.size();
+        let width = u16::min(50, size.width);
+        let logo_width = LOGO[0].chars().count() as u16;
+        let menu_height = 12 + menu.servers.len() as u16;
+        let show_logo =
+            size.width >= logo_width && size.height >= menu_height + LOGO.len() as u16 - 1;
+        let title_height = if show_logo { LOGO.len() as u16 + 1 } else { 2 };
+        let height = u16::min(menu_height - 2 + title_height, size.height);
+        let area = Rect {
+            x: (size.width - width) / 2,
+            y: (size.height - height) / 2,
+            width,
+            height,
+        };
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(0)
+            .constraints(
+                [
+                    Length(title_height),
+                    Length(3),
+                    Length(3),
+                    Length(2),
+                    Length(1),
+                    Length(1),
+                    Min(0),
+                ]
+                .as_ref(),
+            )
+            .split(area);
+
+        let focus_style = |field: MenuField| {
+            if menu.focus == field {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            }
         };
-        f.render_widget(canvas_widget, canvas_rect);
 
-        let displayed_messages = (&app.chat.messages)
+        if show_logo {
+            let logo = LOGO.iter().flat_map(|row| {
+                row.chars()
+                    .enumerate()
+                    .map(|(column, c)| {
+                        let color = LOGO_COLORS[(column / 3 + menu.frame) % LOGO_COLORS.len()];
+                        Text::styled(c.to_string(), Style::default().fg(color))
+                    })
+                    .chain(std::iter::once(Text::raw("\n")))
+            });
+            let logo_area = Rect {
+                x: chunks[0].x + (chunks[0].width.saturating_sub(logo_width)) / 2,
+                width: logo_width.min(chunks[0].width),
+                height: LOGO.len() as u16,
+                ..chunks[0]
+            };
+            f.render_widget(Paragraph::new(logo.collect::<Vec<_>>().iter()), logo_area);
+        } else {
+            f.render_widget(
+                Paragraph::new(
+                    [Text::styled(
+                        "Termibbl",
+                        Style::default().modifier(Modifier::BOLD),
+                    )]
+                    .iter(),
+                )
+                .alignment(Alignment::Center),
+                chunks[0],
+            );
+        }
+        f.render_widget(
+            Paragraph::new([Text::raw(&menu.host)].iter()).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(focus_style(MenuField::Host))
+                    .title("Server address (<host>:<port>)"),
+            ),
+            chunks[1],
+        );
+        f.render_widget(
+            Paragraph::new([Text::raw(&menu.username)].iter()).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(focus_style(MenuField::Username))
+                    .title("Username"),
+            ),
+            chunks[2],
+        );
+        let button_style = |field: MenuField| {
+            if menu.focus == field {
+                Style::default().modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            }
+        };
+        f.render_widget(
+            Paragraph::new(
+                [
+                    Text::styled("[ Connect ]", button_style(MenuField::Connect)),
+                    Text::raw("  "),
+                    Text::styled("[ Find LAN servers ]", button_style(MenuField::Discover)),
+                ]
+                .iter(),
+            )
+            .alignment(Alignment::Center),
+            chunks[3],
+        );
+        if let Some(status) = &menu.status {
+            let status = if menu.waiting {
+                format!("{} {}", SPINNER[menu.frame % SPINNER.len()], status)
+            } else {
+                status.clone()
+            };
+            f.render_widget(
+                Paragraph::new([Text::styled(status, Style::default().fg(Color::Cyan))].iter())
+                    .alignment(Alignment::Center),
+                chunks[4],
+            );
+        } else if let Some(last_session) = &menu.last_session {
+            let hint = format!(
+                "F5: rejoin {} as {}",
+                last_session.host, last_session.username
+            );
+            f.render_widget(
+                Paragraph::new([Text::styled(hint, Style::default().fg(Color::DarkGray))].iter())
+                    .alignment(Alignment::Center),
+                chunks[4],
+            );
+        }
+        if let Some(error) = &menu.error {
+            f.render_widget(
+                Paragraph::new([Text::styled(error, Style::default().fg(Color::Red))].iter())
+                    .alignment(Alignment::Center),
+                chunks[5],
+            );
+        }
+        let servers = menu
+            .servers
             .iter()
-            .filter(|msg| match msg {
-                Message::SystemMsg(_) => true,
-                Message::UserMsg(username, _) => app.game_state.as_ref().map_or(true, |state| {
-                    app.is_drawing()
-                        || app.own_player().map_or(false, |x| x.has_solved)
-                        || (&state.drawing_user != username
-                            && !state
-                                .player_states
-                                .get(&username)
-                                .map_or(false, |player_state| player_state.has_solved))
-                }),
+            .enumerate()
+            .map(|(idx, server)| {
+                Text::styled(
+                    format!("{}\n", server),
+                    button_style(MenuField::Server(idx)),
+                )
             })
             .collect::<Vec<_>>();
-
-        let chat_widget = ChatWidget::new(
-            displayed_messages.as_slice(),
-            &app.chat.input,
-            Block::default().borders(Borders::NONE),
+        f.render_widget(
+            Paragraph::new(servers.iter()).alignment(Alignment::Center),
+            chunks[6],
         );
-        f.render_widget(chat_widget, sidebar_chunks[1]);
     })?;
     Ok(())
 }
@@ -99,11 +446,38 @@ pub fn draw<B: Backend>(app: &mut App, terminal: &mut Terminal<B>) -> Result<()>
 pub struct CanvasWidget<'a, 't> {
     block: Block<'a>,
     canvas: &'t AppCanvas,
+    patterns: bool,
+    palette: bool,
 }
 
 impl<'a, 't> CanvasWidget<'a, 't> {
     pub fn new(canvas: &'t AppCanvas, block: Block<'a>) -> CanvasWidget<'a, 't> {
-        CanvasWidget { block, canvas }
+        CanvasWidget {
+            block,
+            canvas,
+            patterns: false,
+            palette: true,
+        }
+    }
+
+    /// show the colors to pick from along the top border, for players who may draw
+    pub fn palette(mut self, palette: bool) -> CanvasWidget<'a, 't> {
+        self.palette = palette;
+        self
+    }
+
+    /// draw a distinct pattern for every color, on top of the color itself
+    pub fn patterns(mut self, patterns: bool) -> CanvasWidget<'a, 't> {
+        self.patterns = patterns;
+        self
+    }
+
+    fn paint_cell(&self, cell: &mut tui::buffer::Cell, color: CanvasColor) {
+        cell.set_bg(tui_color(color));
+        if self.patterns {
+            let (symbol, fg) = color_pattern(color);
+            cell.set_char(symbol).set_fg(fg);
+        }
     }
 }
 
@@ -112,40 +486,176 @@ impl<'a, 't, 'b> Widget for CanvasWidget<'a, 't> {
         self.block.render(area, buf);
         let area = self.block.inner(area);
 
-        for line in self.canvas.lines.iter() {
-            for cell in line.coords_in() {
-                if cell.within(
-                    &Coord(area.x, area.y),
-                    &Coord(area.x + area.width, area.y + area.height),
-                ) {
-                    buf.get_mut(cell.0, cell.1).set_bg(line.color.into());
+        let top_left = Coord(area.x, area.y);
+        let bottom_right = Coord(area.x + area.width, area.y + area.height);
+        // the confirmed lines were already turned into cells, only the pending ones are left to rasterize
+        let pending_cells = self.canvas.pending_lines.iter().flat_map(|line| {
+            let color = match line.brush {
+                Brush::Paint(color) => Some(color),
+                Brush::Erase => None,
+            };
+            line.coords_in()
+                .into_iter()
+                .map(move |coord| (coord, color))
+        });
+        let cells = self
+            .canvas
+            .cells()
+            .map(|(coord, color)| (coord, Some(color)))
+            .chain(pending_cells);
+
+        if self.canvas.braille {
+            let mut dots: HashMap<(u16, u16), (u8, Color)> = HashMap::new();
+            for (cell, color) in cells {
+                let (cell, (dot_x, dot_y)) = match self.canvas.to_screen_dot(cell) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                if cell.within(&top_left, &bottom_right) {
+                    let entry = dots.entry((cell.0, cell.1)).or_insert((0, Color::Reset));
+                    match color {
+                        Some(color) => {
+                            entry.0 |= braille_dot(dot_x, dot_y);
+                            entry.1 = tui_color(color);
+                        }
+                        None => entry.0 &= !braille_dot(dot_x, dot_y),
+                    }
+                }
+            }
+            for ((x, y), (bits, color)) in dots {
+                let symbol = std::char::from_u32(0x2800 + bits as u32).unwrap_or(' ');
+                buf.get_mut(x, y).set_char(symbol).set_fg(color);
+            }
+        } else {
+            for (cell, color) in cells {
+                let cell = match self.canvas.to_screen_coord(cell) {
+                    Some(cell) => cell,
+                    None => continue,
+                };
+                if cell.within(&top_left, &bottom_right) {
+                    let cell = buf.get_mut(cell.0, cell.1);
+                    match color {
+                        Some(color) => self.paint_cell(cell, color),
+                        None => {
+                            cell.set_bg(Color::Reset).set_char(' ');
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some((pos, size)) = self.canvas.eraser_cursor {
+            for cell in Line::erase(pos, pos, size).coords_in() {
+                match self.canvas.to_screen_coord(cell) {
+                    Some(cell) if cell.within(&top_left, &bottom_right) => {
+                        buf.get_mut(cell.0, cell.1).set_char('░');
+                    }
+                    _ => {}
                 }
             }
         }
+        if let Some(pos) = self.canvas.pen_cursor {
+            match self.canvas.to_screen_coord(pos) {
+                Some(cell) if cell.within(&top_left, &bottom_right) => {
+                    buf.get_mut(cell.0, cell.1)
+                        .set_char('+')
+                        .set_modifier(Modifier::BOLD);
+                }
+                _ => {}
+            }
+        }
+        if !self.palette {
+            return;
+        }
         let swatch_size = area.width / self.canvas.palette.len() as u16;
         for (idx, col) in self.canvas.palette.iter().enumerate() {
             for offset in 0..swatch_size {
-                buf.get_mut(offset + (idx as u16 * swatch_size), 0)
-                    .set_bg((*col).into());
+                self.paint_cell(buf.get_mut(offset + (idx as u16 * swatch_size), 0), *col);
             }
         }
     }
 }
 
+/// the terminal color a canvas color is shown in
+pub fn tui_color(color: CanvasColor) -> Color {
+    match color {
+        CanvasColor::White => Color::White,
+        CanvasColor::Gray => Color::Gray,
+        CanvasColor::DarkGray => Color::DarkGray,
+        CanvasColor::Black => Color::Black,
+        CanvasColor::Red => Color::Red,
+        CanvasColor::LightRed => Color::LightRed,
+        CanvasColor::Green => Color::Green,
+        CanvasColor::LightGreen => Color::LightGreen,
+        CanvasColor::Blue => Color::Blue,
+        CanvasColor::LightBlue => Color::LightBlue,
+        CanvasColor::Yellow => Color::Yellow,
+        CanvasColor::LightYellow => Color::LightYellow,
+        CanvasColor::Cyan => Color::Cyan,
+        CanvasColor::LightCyan => Color::LightCyan,
+        CanvasColor::Magenta => Color::Magenta,
+        CanvasColor::LightMagenta => Color::LightMagenta,
+    }
+}
+
+/// a symbol and a contrasting foreground color that identify a canvas color without seeing it
+fn color_pattern(color: CanvasColor) -> (char, Color) {
+    match color {
+        CanvasColor::White => ('%', Color::Black),
+        CanvasColor::Gray => ('·', Color::Black),
+        CanvasColor::DarkGray => (':', Color::White),
+        CanvasColor::Black => ('@', Color::White),
+        CanvasColor::Red => ('x', Color::White),
+        CanvasColor::LightRed => ('+', Color::Black),
+        CanvasColor::Green => ('#', Color::White),
+        CanvasColor::LightGreen => ('=', Color::Black),
+        CanvasColor::Blue => ('/', Color::White),
+        CanvasColor::LightBlue => ('\\', Color::Black),
+        CanvasColor::Yellow => ('o', Color::Black),
+        CanvasColor::LightYellow => ('~', Color::Black),
+        CanvasColor::Cyan => ('|', Color::Black),
+        CanvasColor::LightCyan => ('-', Color::Black),
+        CanvasColor::Magenta => ('*', Color::White),
+        CanvasColor::LightMagenta => ('^', Color::Black),
+    }
+}
+
+/// the bit representing the dot at the given position in a unicode braille character
+fn braille_dot(x: u16, y: u16) -> u8 {
+    match (x, y) {
+        (0, 3) => 0x40,
+        (1, 3) => 0x80,
+        (0, y) => 1 << y,
+        (_, y) => 1 << (y + 3),
+    }
+}
+
 pub struct ChatWidget<'a, 't> {
     block: Block<'a>,
     messages: &'t [&'t Message],
-    input: &'t str,
+    input: &'t LineInput,
+    focused: bool,
 }
 
 impl<'a, 't> ChatWidget<'a, 't> {
-    pub fn new(messages: &'t [&Message], input: &'t str, block: Block<'a>) -> ChatWidget<'a, 't> {
+    pub fn new(
+        messages: &'t [&Message],
+        input: &'t LineInput,
+        block: Block<'a>,
+    ) -> ChatWidget<'a, 't> {
         ChatWidget {
             block,
             messages,
             input,
+            focused: false,
         }
     }
+
+    /// highlight the input and show the cursor in it, as that's where typing goes
+    pub fn focused(mut self, focused: bool) -> ChatWidget<'a, 't> {
+        self.focused = focused;
+        self
+    }
 }
 impl<'a, 't, 'b> Widget for ChatWidget<'a, 't> {
     fn render(self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
@@ -159,35 +669,334 @@ impl<'a, 't, 'b> Widget for ChatWidget<'a, 't> {
             .constraints([Length(3), Percentage(100)].as_ref())
             .split(area);
 
-        Paragraph::new([Text::Raw(self.input.clone().into())].iter())
-            .block(Block::default().borders(Borders::ALL).title("Your message"))
-            .render(chunks[0], buf);
+        let input_style = if self.focused {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        };
+        Paragraph::new(
+            input_text(self.input, self.focused, chunks[0].width.saturating_sub(2)).iter(),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(input_style)
+                .title("Your message"),
+        )
+        .render(chunks[0], buf);
 
-        List::new(self.messages.iter().rev().map(|msg| {
-            Text::styled(
-                format!("{}", msg),
-                if msg.is_system() {
-                    Style::default().fg(Color::Cyan)
-                } else {
-                    Style::default()
-                },
-            )
-        }))
+        List::new(
+            self.messages
+                .iter()
+                .rev()
+                .map(|msg| Text::styled(format!("{}", msg), message_style(msg))),
+        )
         .block(Block::default().borders(Borders::ALL).title("Chat"))
         .render(chunks[1], buf);
     }
 }
 
+/// the text of a chat input that fits into `width` columns, scrolled so the cursor is visible
+fn input_text(input: &LineInput, show_cursor: bool, width: u16) -> Vec<Text<'_>> {
+    if !show_cursor {
+        return vec![Text::raw(input.text())];
+    }
+    let (before, after) = input.split_at_cursor();
+    let mut graphemes = after.graphemes(true);
+    let at_cursor = graphemes.next().unwrap_or(" ");
+    let after = graphemes.as_str();
+    // leave room for the cursor at the end
+    let hidden = before
+        .graphemes(true)
+        .count()
+        .saturating_sub((width as usize).saturating_sub(1));
+    let before = before
+        .grapheme_indices(true)
+        .nth(hidden)
+        .map_or("", |(idx, _)| &before[idx..]);
+    vec![
+        Text::raw(before),
+        Text::styled(at_cursor, Style::default().modifier(Modifier::REVERSED)),
+        Text::raw(after),
+    ]
+}
+
+/// the latest log lines, newest first, with how much was sent to and received from the server
+pub struct DebugWidget<'t> {
+    lines: &'t [String],
+    stats: &'t NetStats,
+}
+
+impl<'t> DebugWidget<'t> {
+    pub fn new(lines: &'t [String], stats: &'t NetStats) -> DebugWidget<'t> {
+        DebugWidget { lines, stats }
+    }
+}
+
+impl<'t> Widget for DebugWidget<'t> {
+    fn render(self, area: Rect, buf: &mut tui::buffer::Buffer) {
+        List::new(
+            self.lines
+                .iter()
+                .rev()
+                .map(|line| Text::styled(line.as_str(), Style::default().fg(Color::DarkGray))),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(&format!("Debug [{}]", self.stats)),
+        )
+        .render(area, buf);
+    }
+}
+
+/// the frame rate, the events handled per second, the traffic and the ping,
+/// in a box in the top right corner of the area
+pub struct StatsWidget<'a> {
+    app: &'a App,
+}
+
+impl<'a> StatsWidget<'a> {
+    pub fn new(app: &'a App) -> StatsWidget<'a> {
+        StatsWidget { app }
+    }
+}
+
+impl<'a> Widget for StatsWidget<'a> {
+    fn render(self, area: Rect, buf: &mut tui::buffer::Buffer) {
+        let stats = &self.app.session.stats;
+        let ping = match stats.ping() {
+            Some(ping) => format!("{} ms", ping.as_millis()),
+            None => "-".to_string(),
+        };
+        let lines = [
+            format!("{:.0} fps", self.app.frame_rate.per_second()),
+            format!("{:.0} events/s", self.app.event_rate.per_second()),
+            format!("up {}", format_bytes(stats.bytes_sent())),
+            format!("down {}", format_bytes(stats.bytes_received())),
+            format!("ping {}", ping),
+        ];
+        let inner_width = lines.iter().map(|line| line.len()).max().unwrap_or(0) as u16;
+        let width = u16::min(inner_width + 2, area.width);
+        let height = u16::min(lines.len() as u16 + 2, area.height);
+        let rect = Rect {
+            x: area.x + area.width - width,
+            y: area.y,
+            width,
+            height,
+        };
+        let text = lines
+            .iter()
+            .map(|line| Text::raw(format!("{}\n", line)))
+            .collect::<Vec<_>>();
+        Clear.render(rect, buf);
+        Paragraph::new(text.iter())
+            .block(Block::default().borders(Borders::ALL).title("Stats"))
+            .render(rect, buf);
+    }
+}
+
+/// an amount of bytes, in the largest unit it's at least one of
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+/// the word that was just revealed and its definition, in a box in the middle of the area
+pub struct RevealWidget<'a> {
+    revealed: &'a RevealedWord,
+}
+
+impl<'a> RevealWidget<'a> {
+    pub fn new(revealed: &'a RevealedWord) -> RevealWidget<'a> {
+        RevealWidget { revealed }
+    }
+}
+
+impl<'a> Widget for RevealWidget<'a> {
+    fn render(self, area: Rect, buf: &mut tui::buffer::Buffer) {
+        let width = u16::min(40, area.width);
+        let inner_width = width.saturating_sub(2).max(1) as usize;
+        let definition_height = self.revealed.definition.as_ref().map_or(0, |definition| {
+            definition.chars().count().div_ceil(inner_width)
+        });
+        let height = u16::min(definition_height as u16 + 3, area.height);
+        let rect = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+
+        let mut text = vec![Text::styled(
+            format!("{}\n", self.revealed.word),
+            Style::default().fg(Color::Green).modifier(Modifier::BOLD),
+        )];
+        if let Some(definition) = &self.revealed.definition {
+            text.push(Text::raw(definition.as_str()));
+        }
+        Clear.render(rect, buf);
+        Paragraph::new(text.iter())
+            .block(Block::default().borders(Borders::ALL).title("The word was"))
+            .alignment(Alignment::Center)
+            .wrap(true)
+            .render(rect, buf);
+    }
+}
+
+/// the hint of the word for the guessers, on the bottom border of the canvas,
+/// with a slot for every letter and the length of every word
+pub struct HintWidget<'a> {
+    hint: &'a str,
+}
+
+impl<'a> HintWidget<'a> {
+    /// `hint` is the word as the guessers see it, with a `?` for every letter that wasn't revealed
+    pub fn new(hint: &'a str) -> HintWidget<'a> {
+        HintWidget { hint }
+    }
+}
+
+impl<'a> Widget for HintWidget<'a> {
+    fn render(self, area: Rect, buf: &mut tui::buffer::Buffer) {
+        if area.width < 3 || area.height < 2 {
+            return;
+        }
+        let text = hint_text(self.hint);
+        let max_width = area.width - 2;
+        let width = u16::min(text.chars().count() as u16, max_width);
+        buf.set_stringn(
+            area.x + 1 + (max_width - width) / 2,
+            area.y + area.height - 1,
+            &text,
+            width as usize,
+            Style::default().fg(Color::Yellow).modifier(Modifier::BOLD),
+        );
+    }
+}
+
+/// a hint like `??y ?????` spelled out as `_ _ y   _ _ _ _ _  (3, 5)`
+pub fn hint_text(hint: &str) -> String {
+    let words = hint.split_whitespace().collect::<Vec<_>>();
+    let slots = words
+        .iter()
+        .map(|word| {
+            word.chars()
+                .map(|c| if c == '?' { '_' } else { c }.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("   ");
+    let lengths = words
+        .iter()
+        .map(|word| word.chars().count().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{}  ({})", slots, lengths)
+}
+
+fn message_style(msg: &Message) -> Style {
+    match msg {
+        Message::Event(GameEvent::Guessed(_)) | Message::Event(GameEvent::WordRevealed { .. }) => {
+            Style::default().fg(Color::Green)
+        }
+        Message::Event(GameEvent::CloseGuess(_)) => Style::default().fg(Color::Yellow),
+        Message::Event(GameEvent::Joined(_)) | Message::Event(GameEvent::Left(_)) => {
+            Style::default().fg(Color::Gray)
+        }
+        Message::Event(_) | Message::SystemMsg(_) => Style::default().fg(Color::Cyan),
+        Message::UserMsg(..) => Style::default(),
+    }
+}
+
+/// the thumbnails of all drawings of a game, with their words, side by side
+pub struct GalleryWidget<'t> {
+    drawings: &'t [Drawing],
+    /// show the number to vote for each drawing with
+    numbered: bool,
+    selected: Option<usize>,
+}
+
+impl<'t> GalleryWidget<'t> {
+    pub fn new(drawings: &'t [Drawing]) -> GalleryWidget<'t> {
+        GalleryWidget {
+            drawings,
+            numbered: false,
+            selected: None,
+        }
+    }
+
+    pub fn numbered(mut self, numbered: bool) -> Self {
+        self.numbered = numbered;
+        self
+    }
+
+    /// highlight the drawing at the given index
+    pub fn selected(mut self, selected: Option<usize>) -> Self {
+        self.selected = selected;
+        self
+    }
+}
+
+impl<'t> Widget for GalleryWidget<'t> {
+    fn render(self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
+        let (mut x, mut y) = (area.x, area.y);
+        let mut row_height = 0;
+        for (idx, drawing) in self.drawings.iter().enumerate() {
+            let (width, height) = drawing.thumbnail.dimensions();
+            let (width, height) = (width as u16 + 2, height as u16 + 2);
+            if x + width > area.right() && x > area.x {
+                x = area.x;
+                y += row_height;
+                row_height = 0;
+            }
+            if y + height > area.bottom() || x + width > area.right() {
+                break;
+            }
+            let rect = Rect::new(x, y, width, height);
+            let title = if self.numbered {
+                format!("{}: {} ({})", idx + 1, drawing.word, drawing.drawer)
+            } else {
+                format!("{} ({})", drawing.word, drawing.drawer)
+            };
+            let border_style = if self.selected == Some(idx) {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            let block = Block::default()
+                .borders(Borders::ALL)
+                .border_style(border_style)
+                .title(&title);
+            block.render(rect, buf);
+            let inner = block.inner(rect);
+            for (Coord(cell_x, cell_y), color) in drawing.thumbnail.painted_cells() {
+                buf.get_mut(inner.x + cell_x, inner.y + cell_y)
+                    .set_bg(tui_color(color));
+            }
+            x += width;
+            row_height = row_height.max(height);
+        }
+    }
+}
+
 pub struct SkribblStateWidget<'a, 't> {
     block: Block<'a>,
     state: &'t SkribblState,
     username: &'t Username,
+    leader: Option<&'t Username>,
     remaining_time: u32,
+    show_players: bool,
 }
 impl<'a, 't> SkribblStateWidget<'a, 't> {
     pub fn new(
         state: &'t SkribblState,
         username: &'t Username,
+        leader: Option<&'t Username>,
         remaining_time: u32,
         block: Block<'a>,
     ) -> SkribblStateWidget<'a, 't> {
@@ -195,15 +1004,24 @@ impl<'a, 't> SkribblStateWidget<'a, 't> {
             block,
             state,
             username,
+            leader,
             remaining_time,
+            show_players: true,
         }
     }
+
+    /// list every player with their score below the word. Without the list,
+    /// the remaining time is shown next to the word instead.
+    pub fn show_players(mut self, show_players: bool) -> Self {
+        self.show_players = show_players;
+        self
+    }
 }
 
 impl<'a, 't, 'b> Widget for SkribblStateWidget<'a, 't> {
-    fn render(self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
-        self.block.render(area, buf);
-        let area = self.block.inner(area);
+    fn render(self, outer_area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
+        self.block.render(outer_area, buf);
+        let area = self.block.inner(outer_area);
 
         let chunks = Layout::default()
             .direction(Direction::Vertical)
@@ -219,11 +1037,24 @@ impl<'a, 't, 'b> Widget for SkribblStateWidget<'a, 't> {
             self.state.hinted_current_word().to_string()
         };
 
+        let (time, word_area) = if self.show_players {
+            (String::new(), chunks[0])
+        } else {
+            // a single row is too small for a block to have an inner area
+            let word_area = Rect {
+                height: 1,
+                ..outer_area
+            };
+            (format!(" [time: {}]", self.remaining_time), word_area)
+        };
         Paragraph::new(
             [Text::Styled(
                 format!(
-                    "{} drawing {}",
-                    self.state.drawing_user, current_word_representation
+                    "{} drawing {} ({}){}",
+                    self.state.drawing_user,
+                    current_word_representation,
+                    self.state.difficulty(),
+                    time
                 )
                 .into(),
                 if is_drawing {
@@ -234,8 +1065,11 @@ impl<'a, 't, 'b> Widget for SkribblStateWidget<'a, 't> {
             )]
             .iter(),
         )
-        .render(chunks[0], buf);
+        .render(word_area, buf);
 
+        if !self.show_players {
+            return;
+        }
         let mut sorted_player_entries = self
             .state
             .player_states
@@ -247,8 +1081,22 @@ impl<'a, 't, 'b> Widget for SkribblStateWidget<'a, 't> {
             sorted_player_entries
                 .into_iter()
                 .map(|(username, player_state)| {
+                    let solve_time = match self.state.solve_time(username) {
+                        Some(secs) => format!(" ({}s)", secs),
+                        None => String::new(),
+                    };
                     Text::styled(
-                        format!("{}: {}", username, player_state.score,),
+                        format!(
+                            "{}{}: {}{}",
+                            username,
+                            if self.leader == Some(username) {
+                                " (leader)"
+                            } else {
+                                ""
+                            },
+                            player_state.score,
+                            solve_time,
+                        ),
                         if self.state.drawing_user == *username {
                             Style::default().bg(tui::style::Color::Cyan)
                         } else if self.state.has_solved(username) {
@@ -257,7 +1105,13 @@ impl<'a, 't, 'b> Widget for SkribblStateWidget<'a, 't> {
                             Style::default()
                         },
                     )
-                }),
+                })
+                .chain(self.state.waiting_players.iter().map(|username| {
+                    Text::styled(
+                        format!("{} (next round)", username),
+                        Style::default().fg(Color::DarkGray),
+                    )
+                })),
         )
         .block(
             Block::default()
@@ -267,3 +1121,20 @@ impl<'a, 't, 'b> Widget for SkribblStateWidget<'a, 't> {
         .render(chunks[1], buf);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_color_has_its_own_pattern() {
+        let mut symbols = crate::client::app::PALETTE
+            .iter()
+            .map(|color| color_pattern(*color).0)
+            .collect::<Vec<_>>();
+        symbols.sort_unstable();
+        symbols.dedup();
+        assert_eq!(symbols.len(), crate::client::app::PALETTE.len());
+        assert!(!symbols.contains(&' '));
+    }
+}
@@ -1,22 +1,23 @@
 pub mod client;
-pub mod data;
-pub mod message;
 pub mod server;
+#[cfg(test)]
+pub mod testing;
 
-use std::io::{stdout, Write};
+pub use termibbl_protocol::{data, game, message};
+
+use std::io::stdout;
+use std::net::{AddrParseError, IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
 use crossterm::{
-    event::{read, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, MouseEvent},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    event::{read, Event, KeyCode, KeyEvent, MouseEvent},
     Result,
 };
 
 use tui::{backend::CrosstermBackend, Terminal};
 
-use client::app::ServerSession;
+use client::{app::ServerSession, start_menu::StartMenu, terminal::TerminalGuard};
 use data::Username;
 pub use serde::{Deserialize, Serialize};
 
@@ -30,20 +31,204 @@ struct Opt {
 #[derive(Debug, StructOpt)]
 enum SubOpt {
     Server {
-        #[structopt(long = "--port", short = "-p")]
-        port: u32,
-        #[structopt(long = "--words", parse(from_os_str), required_if("freedraw", "true"))]
-        word_file: Option<PathBuf>,
+        #[structopt(long = "--port", short = "-p", required_unless = "check-words")]
+        port: Option<u16>,
+        #[structopt(
+            long = "--words",
+            help = "File with one word per line. Can be given multiple times",
+            parse(from_os_str)
+        )]
+        word_files: Vec<PathBuf>,
+        #[structopt(
+            long,
+            help = "Only check the given word files and print what would be loaded",
+            parse(from_os_str)
+        )]
+        check_words: Vec<PathBuf>,
         #[structopt(short, long, help = "<width>x<height>", parse(from_str = crate::parse_dimension), default_value = "100x50")]
         dimensions: (usize, usize),
+        #[structopt(
+            long,
+            help = "Address to listen on, like 0.0.0.0, 192.168.0.2 or [::]. Can be given multiple times. Defaults to 0.0.0.0",
+            parse(try_from_str = crate::parse_bind_addr)
+        )]
+        bind: Vec<IpAddr>,
+        #[structopt(
+            long,
+            help = "How many messages can be queued for a client before it counts as too slow",
+            default_value = "256",
+            parse(try_from_str = crate::parse_queue_size)
+        )]
+        queue_size: usize,
+        #[structopt(
+            long,
+            help = "Seed for the random choices of the game, like words and hints, to make games reproducible"
+        )]
+        seed: Option<u64>,
+        #[structopt(
+            long,
+            help = "Seconds to wait for more players before a game starts. Restarts whenever someone joins",
+            default_value = "15"
+        )]
+        start_countdown: u64,
+        #[structopt(
+            long,
+            help = "How many players are needed to start a game. Use 1 to practice on your own",
+            default_value = "2",
+            parse(try_from_str = crate::parse_min_players)
+        )]
+        min_players: usize,
+        #[structopt(
+            long,
+            help = "Seconds every turn lasts, between 30 and 600. The leader can change it with !turntime",
+            default_value = "120",
+            parse(try_from_str = crate::parse_round_duration)
+        )]
+        round_duration: u64,
+        #[structopt(
+            long,
+            help = "Seconds between the heartbeats clients send",
+            default_value = "10",
+            parse(try_from_str = crate::parse_heartbeat_secs)
+        )]
+        heartbeat_interval: u64,
+        #[structopt(
+            long,
+            help = "Seconds without any message from a client after which it gets disconnected",
+            default_value = "30",
+            parse(try_from_str = crate::parse_heartbeat_secs)
+        )]
+        heartbeat_timeout: u64,
+        #[structopt(
+            long,
+            help = "Answer clients looking for servers in the local network, so they don't need to know the address"
+        )]
+        lan: bool,
+        #[structopt(
+            long,
+            help = "Ask the router to forward the port to this machine using UPnP, so players outside of your network can join"
+        )]
+        upnp: bool,
+        #[structopt(
+            long,
+            help = "Port to serve a status page on, with a JSON summary at /status.json"
+        )]
+        status_port: Option<u16>,
+        #[structopt(
+            long,
+            help = "Directory to write the scores and stats of every finished game to, as JSON",
+            parse(from_os_str)
+        )]
+        stats_dir: Option<PathBuf>,
+        #[structopt(
+            long,
+            help = "File with words to remove from the word files, one per line, ignoring case",
+            parse(from_os_str)
+        )]
+        blocked_words: Option<PathBuf>,
+        #[structopt(
+            long,
+            help = "File to save the lobby settings and the leader to when the server stops, and to restore them from when it starts",
+            parse(from_os_str)
+        )]
+        state_file: Option<PathBuf>,
+        #[structopt(
+            long,
+            help = "Only check that a server with these options is running, e.g. for container health checks. Exits with 1 if it isn't"
+        )]
+        health_check: bool,
     },
     Client {
+        #[structopt(long = "address", short = "-a")]
+        addr: Option<String>,
+        username: Option<String>,
+        #[structopt(long, help = "Show a distinct pattern for every color on the canvas")]
+        colorblind: bool,
+        #[structopt(
+            long,
+            help = "Don't show the canvas, but describe the game as plain text, for screen readers"
+        )]
+        accessible: bool,
+        #[structopt(
+            long,
+            help = "Redraw less often and without animations, for slow connections like SSH sessions"
+        )]
+        low_bandwidth: bool,
+        #[structopt(
+            long,
+            help = "File to write the log to. Defaults to $XDG_CACHE_HOME/termibbl/client.log",
+            parse(from_os_str)
+        )]
+        log_file: Option<PathBuf>,
+    },
+    /// connect many headless clients to a server and measure how fast it answers them
+    Stress {
         #[structopt(long = "address", short = "-a")]
         addr: String,
-        username: String,
+        #[structopt(
+            short,
+            long,
+            help = "How many clients to connect",
+            default_value = "10"
+        )]
+        clients: usize,
+        #[structopt(
+            long,
+            help = "Lines every client draws per second",
+            default_value = "20"
+        )]
+        lines_per_sec: f64,
+        #[structopt(
+            long,
+            help = "Chat messages every client sends per second",
+            default_value = "1"
+        )]
+        messages_per_sec: f64,
+        #[structopt(long, help = "How many seconds to run for", default_value = "30")]
+        duration: u64,
     },
 }
 
+fn parse_bind_addr(s: &str) -> std::result::Result<IpAddr, AddrParseError> {
+    s.trim_start_matches('[').trim_end_matches(']').parse()
+}
+
+fn parse_min_players(s: &str) -> std::result::Result<usize, String> {
+    match s.parse() {
+        Ok(0) => Err("a game needs at least one player".to_string()),
+        Ok(players) => Ok(players),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+fn parse_queue_size(s: &str) -> std::result::Result<usize, String> {
+    match s.parse() {
+        Ok(0) => Err("at least one message has to fit into the queue".to_string()),
+        Ok(size) => Ok(size),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+fn parse_heartbeat_secs(s: &str) -> std::result::Result<u64, String> {
+    match s.parse() {
+        Ok(0) => Err("has to be at least one second".to_string()),
+        Ok(secs) => Ok(secs),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+fn parse_round_duration(s: &str) -> std::result::Result<u64, String> {
+    use server::server::{MAX_ROUND_DURATION, MIN_ROUND_DURATION};
+    match s.parse() {
+        Ok(secs) if (MIN_ROUND_DURATION..=MAX_ROUND_DURATION).contains(&secs) => Ok(secs),
+        Ok(_) => Err(format!(
+            "turns last {} to {} seconds",
+            MIN_ROUND_DURATION, MAX_ROUND_DURATION
+        )),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
 fn parse_dimension(s: &str) -> (usize, usize) {
     let mut split = s.split('x');
     (
@@ -56,19 +241,137 @@ fn parse_dimension(s: &str) -> (usize, usize) {
 async fn main() -> Result<()> {
     let opt = Opt::from_args();
     match opt.cmd {
-        SubOpt::Client { username, addr } => {
+        SubOpt::Client {
+            username,
+            addr,
+            colorblind,
+            accessible,
+            low_bandwidth,
+            log_file,
+        } => {
+            if let Some(path) = log_file.or_else(client::log::default_path) {
+                if let Err(err) = client::log::init(&path) {
+                    eprintln!("Could not open the log file {}: {}", path.display(), err);
+                }
+            }
+            // an invite link given on its own, e.g. by a handler for termibbl:// links
+            let (addr, username) = match username {
+                Some(link) if addr.is_none() && link.starts_with(client::app::INVITE_SCHEME) => {
+                    (Some(link), None)
+                }
+                username => (addr, username),
+            };
+            if accessible {
+                match (addr, username) {
+                    (Some(addr), Some(username)) => {
+                        let addr = if let Some(addr) = client::app::address_of_invite(&addr) {
+                            addr
+                        } else if addr.starts_with("ws://") || addr.starts_with("wss://") {
+                            addr
+                        } else {
+                            format!("ws://{}", addr)
+                        };
+                        if let Err(err) = run_accessible_client(&addr, username.into()).await {
+                            eprintln!("{}", err);
+                            std::process::exit(1);
+                        }
+                    }
+                    _ => eprintln!("The accessible mode needs an address and a username"),
+                }
+            } else {
+                run_client(addr, username, colorblind, low_bandwidth)
+                    .await
+                    .unwrap();
+            }
+        }
+        SubOpt::Stress {
+            addr,
+            clients,
+            lines_per_sec,
+            messages_per_sec,
+            duration,
+        } => {
             let addr = if addr.starts_with("ws://") || addr.starts_with("wss://") {
                 addr
             } else {
                 format!("ws://{}", addr)
             };
-            run_client(&addr, username.into()).await.unwrap();
+            for (name, rate) in &[
+                ("--lines-per-sec", lines_per_sec),
+                ("--messages-per-sec", messages_per_sec),
+            ] {
+                if !rate.is_finite() || *rate < 0.0 {
+                    eprintln!("{} has to be a number of at least 0, not {}", name, rate);
+                    std::process::exit(1);
+                }
+            }
+            let opts = client::stress::StressOpts {
+                clients,
+                lines_per_sec,
+                messages_per_sec,
+                duration: std::time::Duration::from_secs(duration),
+            };
+            client::stress::run(&addr, opts).await.unwrap();
         }
         SubOpt::Server {
             port,
-            word_file,
+            word_files,
+            check_words,
             dimensions,
+            bind,
+            queue_size,
+            seed,
+            start_countdown,
+            min_players,
+            round_duration,
+            heartbeat_interval,
+            heartbeat_timeout,
+            lan,
+            upnp,
+            status_port,
+            stats_dir,
+            blocked_words,
+            state_file,
+            health_check,
         } => {
+            if !check_words.is_empty() {
+                let list = server::words::read_blocked_words(blocked_words.as_deref())
+                    .and_then(|blocked| server::words::WordList::load(&check_words, &blocked));
+                match list {
+                    Ok(list) => println!("{}", list.stats),
+                    Err(err) => {
+                        eprintln!("Could not read the word files: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+                return Ok(());
+            }
+            let port = port.unwrap();
+            if health_check {
+                let ip = bind
+                    .first()
+                    .copied()
+                    .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+                let addr = SocketAddr::new(ip, port);
+                if let Err(err) = server::server::health_check(addr, status_port).await {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+            let heartbeat = message::Heartbeat {
+                interval: std::time::Duration::from_secs(heartbeat_interval),
+                timeout: std::time::Duration::from_secs(heartbeat_timeout),
+            };
+            // clients wait a bit longer than the interval sometimes, and shouldn't get dropped for it
+            let max_interval = heartbeat.interval.mul_f64(message::HEARTBEAT_JITTER.1);
+            if heartbeat.timeout <= max_interval {
+                eprintln!(
+                    "--heartbeat-timeout has to be longer than {}s, clients may wait that long between heartbeats",
+                    max_interval.as_secs_f64()
+                );
+                std::process::exit(1);
+            }
             tokio::spawn(async move {
                 if let Ok(res) = reqwest::get("http://ifconfig.me").await {
                     if let Ok(ip) = res.text().await {
@@ -78,11 +381,61 @@ async fn main() -> Result<()> {
                     }
                 }
             });
+            let port_mapping = if upnp {
+                Some(tokio::spawn(async move {
+                    match server::upnp::map_port(port).await {
+                        Ok(mapping) => {
+                            println!(
+                                "Forwarded port {} on your router, players can connect to {}",
+                                port, mapping.external
+                            );
+                            Some(mapping)
+                        }
+                        Err(err) => {
+                            eprintln!("Could not forward the port using UPnP: {}", err);
+                            None
+                        }
+                    }
+                }))
+            } else {
+                None
+            };
 
-            let addr = format!("0.0.0.0:{}", port);
-            server::server::run_server(&addr, dimensions, word_file)
-                .await
-                .unwrap();
+            let bind = if bind.is_empty() {
+                vec![IpAddr::V4(Ipv4Addr::UNSPECIFIED)]
+            } else {
+                bind
+            };
+            let addrs = bind
+                .into_iter()
+                .map(|ip| SocketAddr::new(ip, port))
+                .collect();
+            let opts = server::server::ServerOpts {
+                queue_size,
+                seed,
+                start_countdown: std::time::Duration::from_secs(start_countdown),
+                min_players,
+                round_duration,
+                heartbeat,
+                lan_discovery: lan,
+                status_port,
+                stats_dir,
+                blocked_words,
+                state_file,
+            };
+            let result = server::server::run_server(addrs, dimensions, word_files, opts).await;
+            // don't leave the port forwarded to this machine once the server is gone
+            if let Some(port_mapping) = port_mapping {
+                if let Ok(Some(mapping)) = port_mapping.await {
+                    if let Err(err) = mapping.remove().await {
+                        eprintln!("Could not remove the port forwarding: {}", err);
+                    }
+                }
+            }
+            if let Err(err) = result {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
         }
     }
     Ok(())
@@ -91,43 +444,76 @@ async fn main() -> Result<()> {
 pub enum ClientEvent {
     MouseInput(MouseEvent),
     KeyInput(KeyEvent),
+    /// text that was pasted, rather than typed key by key
+    Paste(String),
+    Resized(u16, u16),
     ServerMessage(message::ToClientMsg),
 }
 
-async fn run_client(addr: &str, username: Username) -> client::error::Result<()> {
-    let (mut client_evt_send, client_evt_recv) = tokio::sync::mpsc::channel::<ClientEvent>(1);
+async fn run_accessible_client(addr: &str, username: Username) -> client::error::Result<()> {
+    let (client_evt_send, client_evt_recv) = tokio::sync::mpsc::channel::<ClientEvent>(1);
+    let mut app = ServerSession::establish_connection(addr, username, client_evt_send).await?;
+    client::narrator::run(&mut app, client_evt_recv).await
+}
 
-    let mut app =
-        ServerSession::establish_connection(addr, username, client_evt_send.clone()).await?;
+async fn run_client(
+    addr: Option<String>,
+    username: Option<String>,
+    colorblind: bool,
+    low_bandwidth: bool,
+) -> client::error::Result<()> {
+    let (mut client_evt_send, mut client_evt_recv) = tokio::sync::mpsc::channel::<ClientEvent>(1);
 
-    enable_raw_mode()?;
-    execute!(stdout(), EnterAlternateScreen)?;
-    execute!(stdout(), EnableMouseCapture)?;
+    client::terminal::install_panic_hook();
+    let _guard = TerminalGuard::enter()?;
     let backend = CrosstermBackend::new(stdout());
     let mut terminal = Terminal::new(backend)?;
+    let mut start_menu = StartMenu::new(addr, username);
+    start_menu.low_bandwidth = low_bandwidth || client::config::Config::load().low_bandwidth;
+    if let Some(last_session) = client::config::LastSession::load() {
+        start_menu.remember(last_session);
+    }
+    let server_evt_send = client_evt_send.clone();
     tokio::spawn(async move {
+        let mut app = start_menu
+            .run(&mut terminal, &mut client_evt_recv, server_evt_send)
+            .await
+            .unwrap();
+        app.config.colorblind |= colorblind;
+        app.config.low_bandwidth |= low_bandwidth;
         app.run(&mut terminal, client_evt_recv).await.unwrap();
     });
+    // the event that ended a paste, which is handled next
+    let mut pending = None;
     loop {
-        match read()? {
-            Event::Key(evt) => match evt {
-                KeyEvent {
-                    code: KeyCode::Esc,
-                    modifiers: _,
-                } => break,
-                _ => {
-                    let _ = client_evt_send.send(ClientEvent::KeyInput(evt)).await;
+        let event = match pending.take() {
+            Some(event) => event,
+            None => read()?,
+        };
+        let evt = match event {
+            Event::Key(KeyEvent {
+                code: KeyCode::Esc,
+                modifiers: _,
+            }) => break,
+            Event::Key(evt) => match client::terminal::pasted_char(&evt) {
+                Some(c) if client::terminal::input_pending()? => {
+                    let (text, next) = client::terminal::read_paste(c)?;
+                    pending = next;
+                    if text.chars().count() > 1 {
+                        ClientEvent::Paste(text)
+                    } else {
+                        ClientEvent::KeyInput(evt)
+                    }
                 }
+                _ => ClientEvent::KeyInput(evt),
             },
-            Event::Mouse(evt) => {
-                let _ = client_evt_send.send(ClientEvent::MouseInput(evt)).await;
-            }
-            _ => {}
+            Event::Mouse(evt) => ClientEvent::MouseInput(evt),
+            Event::Resize(width, height) => ClientEvent::Resized(width, height),
+        };
+        // the app stopped, e.g. because it panicked, so there is nothing left to do
+        if client_evt_send.send(evt).await.is_err() {
+            break;
         }
     }
-
-    execute!(stdout(), DisableMouseCapture)?;
-    execute!(stdout(), LeaveAlternateScreen)?;
-    disable_raw_mode()?;
     Ok(())
 }
@@ -0,0 +1,158 @@
+use crate::data::Closeness;
+use std::cmp::min;
+
+/// words need at least this many characters for a guess with typos to count as close.
+/// Shorter words are a typo away from too many ordinary words, like "ok" from "ox".
+const MIN_LENGTH_FOR_CLOSE: usize = 5;
+
+/// how close a guess is to the word that is being drawn
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GuessFeedback {
+    Correct,
+    /// one typo away from the word
+    VeryClose,
+    /// two typos away from the word
+    Close,
+    /// the word is part of the guess, like "i use rust" for "rust"
+    ContainsWord,
+    Wrong,
+}
+
+impl GuessFeedback {
+    /// how close the guess was, if it's worth telling the guesser
+    pub fn closeness(self) -> Option<Closeness> {
+        match self {
+            GuessFeedback::VeryClose => Some(Closeness::VeryClose),
+            GuessFeedback::Close => Some(Closeness::Close),
+            GuessFeedback::ContainsWord => Some(Closeness::ContainsWord),
+            GuessFeedback::Correct | GuessFeedback::Wrong => None,
+        }
+    }
+}
+
+/// compare a guess to the word, ignoring case and surrounding whitespace
+pub fn check_guess(guess: &str, word: &str) -> GuessFeedback {
+    let guess = guess.trim().to_lowercase();
+    let word = word.trim().to_lowercase();
+    if guess == word {
+        return GuessFeedback::Correct;
+    }
+    let distance = levenshtein_distance(&guess, &word);
+    let long_enough = word.chars().count() >= MIN_LENGTH_FOR_CLOSE;
+    if distance <= 1 && long_enough {
+        GuessFeedback::VeryClose
+    } else if distance <= 2 && long_enough {
+        GuessFeedback::Close
+    } else if !word.is_empty() && contains_whole_word(&guess, &word) {
+        GuessFeedback::ContainsWord
+    } else {
+        GuessFeedback::Wrong
+    }
+}
+
+/// whether `word` appears in `text` on its own, rather than as part of a longer word
+fn contains_whole_word(text: &str, word: &str) -> bool {
+    text.match_indices(word).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + word.len()..].chars().next();
+        !before.map_or(false, char::is_alphanumeric) && !after.map_or(false, char::is_alphanumeric)
+    })
+}
+
+fn levenshtein_distance(a: &str, b: &str) -> usize {
+    let w1 = a.chars().collect::<Vec<_>>();
+    let w2 = b.chars().collect::<Vec<_>>();
+
+    let a_len = w1.len() + 1;
+    let b_len = w2.len() + 1;
+
+    let mut matrix = vec![vec![0]];
+
+    for i in 1..a_len {
+        matrix[0].push(i);
+    }
+    for j in 1..b_len {
+        matrix.push(vec![j]);
+    }
+
+    for (j, i) in (1..b_len).flat_map(|j| (1..a_len).map(move |i| (j, i))) {
+        let x: usize = if w1[i - 1].eq_ignore_ascii_case(&w2[j - 1]) {
+            matrix[j - 1][i - 1]
+        } else {
+            1 + min(
+                min(matrix[j][i - 1], matrix[j - 1][i]),
+                matrix[j - 1][i - 1],
+            )
+        };
+        matrix[j].push(x);
+    }
+    matrix[b_len - 1][a_len - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_guess_is_correct() {
+        assert_eq!(check_guess("polybar", "polybar"), GuessFeedback::Correct);
+        assert_eq!(check_guess("  PolyBar ", "polybar"), GuessFeedback::Correct);
+    }
+
+    #[test]
+    fn one_typo_is_very_close() {
+        assert_eq!(check_guess("polybat", "polybar"), GuessFeedback::VeryClose);
+        assert_eq!(check_guess("polyba", "polybar"), GuessFeedback::VeryClose);
+        // in short words, a typo would match too many unrelated messages
+        assert_eq!(check_guess("ok", "ox"), GuessFeedback::Wrong);
+    }
+
+    #[test]
+    fn two_typos_are_close_for_long_words() {
+        assert_eq!(check_guess("polibat", "polybar"), GuessFeedback::Close);
+        // in short words, two typos would match too many unrelated guesses
+        assert_eq!(check_guess("rsut", "rust"), GuessFeedback::Wrong);
+    }
+
+    #[test]
+    fn word_inside_guess() {
+        assert_eq!(
+            check_guess("rust is great", "rust"),
+            GuessFeedback::ContainsWord
+        );
+        assert_eq!(
+            check_guess("i use xmonad", "xmonad"),
+            GuessFeedback::ContainsWord
+        );
+        assert_eq!(
+            check_guess("i like ice cream!", "ice cream"),
+            GuessFeedback::ContainsWord
+        );
+        // only whole words count, other words merely containing it are ordinary chat
+        assert_eq!(
+            check_guess("rustlang is great", "rust"),
+            GuessFeedback::Wrong
+        );
+        assert_eq!(check_guess("start", "art"), GuessFeedback::Wrong);
+    }
+
+    #[test]
+    fn unrelated_guess_is_wrong() {
+        assert_eq!(check_guess("python", "polybar"), GuessFeedback::Wrong);
+        assert_eq!(check_guess("", "polybar"), GuessFeedback::Wrong);
+    }
+
+    #[test]
+    fn only_hints_for_near_misses() {
+        assert_eq!(GuessFeedback::Correct.closeness(), None);
+        assert_eq!(GuessFeedback::Wrong.closeness(), None);
+        assert!(GuessFeedback::VeryClose.closeness().is_some());
+    }
+
+    #[test]
+    fn levenshtein() {
+        assert_eq!(levenshtein_distance("kitten", "sitting"), 3);
+        assert_eq!(levenshtein_distance("", "abc"), 3);
+        assert_eq!(levenshtein_distance("abc", "abc"), 0);
+    }
+}
@@ -1,2 +1,9 @@
+pub mod guess;
+pub mod rate_limit;
 pub mod server;
 pub mod skribbl;
+#[cfg(test)]
+mod tests;
+pub mod traffic;
+pub mod upnp;
+pub mod words;
@@ -0,0 +1,129 @@
+use std::time::Instant;
+
+/// a token bucket: holds up to `capacity` tokens, which refill at `rate` tokens per second.
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: f64, rate: f64) -> Self {
+        TokenBucket {
+            capacity,
+            rate,
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// take the given amount of tokens, returning false if there aren't enough.
+    /// Amounts larger than the capacity take a full bucket, so they aren't refused forever.
+    pub fn try_take(&mut self, amount: f64) -> bool {
+        let amount = amount.min(self.capacity);
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = f64::min(self.capacity, self.tokens + elapsed * self.rate);
+        self.last_refill = now;
+        if self.tokens >= amount {
+            self.tokens -= amount;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// the kinds of messages that are rate limited separately
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Limited {
+    /// chat messages, counted per message
+    Chat,
+    /// lines, counted per point of the line
+    Draw(usize),
+}
+
+/// rate limits for a single session
+#[derive(Debug)]
+pub struct RateLimits {
+    chat: TokenBucket,
+    draw: TokenBucket,
+    /// every dropped message takes a token from here, once it is empty the player gets kicked
+    dropped: TokenBucket,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            chat: TokenBucket::new(5.0, 3.0),
+            draw: TokenBucket::new(400.0, 200.0),
+            dropped: TokenBucket::new(50.0, 1.0),
+        }
+    }
+}
+
+/// what to do with a message after checking the rate limits
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RateLimitResult {
+    Allow,
+    Drop,
+    Kick,
+}
+
+impl RateLimits {
+    pub fn check(&mut self, kind: Limited) -> RateLimitResult {
+        let allowed = match kind {
+            Limited::Chat => self.chat.try_take(1.0),
+            Limited::Draw(points) => self.draw.try_take(points as f64),
+        };
+        if allowed {
+            RateLimitResult::Allow
+        } else if self.dropped.try_take(1.0) {
+            RateLimitResult::Drop
+        } else {
+            RateLimitResult::Kick
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_refuse_once_empty() {
+        let mut bucket = TokenBucket::new(3.0, 0.0);
+        assert!(bucket.try_take(2.0));
+        assert!(bucket.try_take(1.0));
+        assert!(!bucket.try_take(1.0));
+    }
+
+    #[test]
+    fn amounts_above_the_capacity_take_a_full_bucket() {
+        let mut bucket = TokenBucket::new(400.0, 0.0);
+        assert!(bucket.try_take(1000.0));
+        assert!(!bucket.try_take(1.0));
+    }
+
+    #[test]
+    fn long_lines_are_allowed_with_a_full_bucket() {
+        let mut limits = RateLimits::default();
+        assert_eq!(limits.check(Limited::Draw(2000)), RateLimitResult::Allow);
+        assert_eq!(limits.check(Limited::Draw(2000)), RateLimitResult::Drop);
+    }
+
+    #[test]
+    fn players_get_kicked_once_too_much_was_dropped() {
+        let mut limits = RateLimits {
+            chat: TokenBucket::new(1.0, 0.0),
+            draw: TokenBucket::new(1.0, 0.0),
+            dropped: TokenBucket::new(2.0, 0.0),
+        };
+        assert_eq!(limits.check(Limited::Chat), RateLimitResult::Allow);
+        assert_eq!(limits.check(Limited::Chat), RateLimitResult::Drop);
+        assert_eq!(limits.check(Limited::Chat), RateLimitResult::Drop);
+        assert_eq!(limits.check(Limited::Chat), RateLimitResult::Kick);
+    }
+}
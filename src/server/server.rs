@@ -1,31 +1,128 @@
 //https://github.com/snapview/tokio-tungstenite/blob/master/examples/server.rs
 
-use super::skribbl::SkribblState;
+use super::{
+    guess::{self, GuessFeedback},
+    rate_limit::{Limited, RateLimitResult, RateLimits},
+    skribbl::{self, SkribblGame},
+    traffic::{self, Traffic, TrafficStats},
+    words::{self, Word, WordList, MAX_WORD_LENGTH},
+};
 use crate::{
     data,
-    message::{InitialState, ToClientMsg, ToServerMsg},
+    game::{Drawing, PlayerState, SkribblState},
+    message::{
+        self, DiscoveryReply, Heartbeat, InitialState, ReplayInfo, ToClientMsg, ToServerMsg,
+        DISCOVERY_PORT, DISCOVERY_REQUEST, PEN_INTERVAL,
+    },
+};
+use data::{
+    CommandMsg, Difficulty, GameEvent, Message, TurnRotation, Username, MAX_BRUSH_SIZE,
+    MAX_MESSAGE_LENGTH,
 };
-use data::{CommandMsg, Message, Username};
-use futures_timer::Delay;
 use futures_util::{SinkExt, StreamExt};
-use std::io::Read;
-use std::net::SocketAddr;
-use std::{cmp::min, collections::HashMap, path::PathBuf, time::Duration};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 use tokio::{
-    net::{TcpListener, TcpStream},
-    sync::Mutex,
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::{mpsc::error::TrySendError, oneshot, Mutex},
 };
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
 
+/// how many seconds a turn lasts, unless the server or the leader chose otherwise
 pub const ROUND_DURATION: u64 = 120;
 
+/// bounds for the length of turns, in seconds
+pub const MIN_ROUND_DURATION: u64 = 30;
+pub const MAX_ROUND_DURATION: u64 = 600;
+
+/// bounds for the canvas dimensions the leader can choose
+pub const MIN_DIMENSIONS: (usize, usize) = (20, 10);
+pub const MAX_DIMENSIONS: (usize, usize) = (500, 250);
+
+/// how often the game state is checked, e.g. for turns that ran out of time
+pub const TICK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// how often the server sends the checksum of the canvas, so clients can notice missing lines
+pub const CHECKSUM_INTERVAL: Duration = Duration::from_secs(5);
+
+/// how long writing a single message to a client may take before the client counts as dead
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// number of rejected messages after which a player gets kicked
+pub const MAX_OFFENSES: u32 = 5;
+
+/// how much longer a turn lasts once the guessers voted for more time
+pub const TIME_EXTENSION: Duration = Duration::from_secs(30);
+
+/// how long players get to vote for the best drawing at the end of a game
+pub const DRAWING_VOTE_DURATION: Duration = Duration::from_secs(30);
+
+/// points for the drawing player of the drawing that won the vote at the end of a game
+pub const BEST_DRAWING_BONUS: u32 = 100;
+
+/// the most games a series can have
+pub const MAX_SERIES_GAMES: u32 = 20;
+
+/// how many finished games the server keeps for players to look at later
+pub const MAX_REPLAYS: usize = 20;
+
+/// clients with more than `--queue-size / BACKLOG_SHARE` messages waiting for them are behind,
+/// and only get the messages they can't recover from until they caught up
+pub const BACKLOG_SHARE: usize = 4;
+
+/// how often the server checks whether a single client causes most of the traffic
+pub const TRAFFIC_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// how long `--health-check` waits for the server to answer
+pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// what clients that are too old to join are told
+pub const OUTDATED_CLIENT_NOTICE: &str =
+    "This server needs a newer version of termibbl, please update to join";
+
 type Result<T> = std::result::Result<T, ServerError>;
 
 #[derive(Debug)]
 pub enum ServerError {
-    UserNotFound(String),
     SendError(String),
     WsError(tungstenite::error::Error),
     IOError(std::io::Error),
+    /// listening on the address failed. The hint explains a likely cause, if there is one.
+    Bind(SocketAddr, std::io::Error, Option<&'static str>),
+    BlockedWords(std::io::Error),
+    WordFiles(std::io::Error),
+}
+
+impl std::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerError::SendError(err) => write!(f, "{}", err),
+            ServerError::WsError(err) => write!(f, "{}", err),
+            ServerError::IOError(err) => write!(f, "{}", err),
+            ServerError::Bind(addr, err, hint) => {
+                write!(f, "Could not listen on {}: {}", addr, err)?;
+                if let Some(hint) = hint {
+                    write!(f, "\n{}", hint)?;
+                }
+                Ok(())
+            }
+            ServerError::BlockedWords(err) => {
+                write!(f, "Could not read the blocked words file: {}", err)
+            }
+            ServerError::WordFiles(err) => write!(f, "Could not read the word files: {}", err),
+        }
+    }
 }
 
 impl<T> From<tokio::sync::mpsc::error::SendError<T>> for ServerError {
@@ -52,25 +149,82 @@ enum ServerEvent {
     UserJoined(UserSession),
     UserLeft(Username),
     Tick,
+    /// re-read the word file, e.g. after the server received SIGHUP
+    ReloadWords,
+    /// ask for a summary of the server, e.g. to answer a LAN discovery request
+    Status(oneshot::Sender<ServerStatus>),
+    /// ask for the lobby settings, to save them before the server stops
+    Snapshot(oneshot::Sender<LobbySnapshot>),
+}
+
+/// what the server tells about itself on the status page
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerStatus {
+    pub version: &'static str,
+    pub uptime_secs: u64,
+    pub players: Vec<Username>,
+    /// one of "free draw", "starting", "playing" or "voting"
+    pub game: &'static str,
+    /// what was sent to and received from every player since they joined
+    pub traffic: BTreeMap<Username, TrafficStats>,
+}
+
+/// the lobby settings and who the leader is, written to `--state-file` when the server stops
+/// and read again when it starts. Running games aren't kept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LobbySnapshot {
+    pub leader: Option<Username>,
+    pub dimensions: (usize, usize),
+    pub difficulties: Vec<Difficulty>,
+    pub language: Option<String>,
+    pub word_lengths: (usize, usize),
+    pub rotation: TurnRotation,
+    pub drawing_vote: bool,
+    pub round_duration: u64,
+    pub pen_sharing: bool,
 }
 
 #[derive(Debug)]
 struct UserSession {
     username: Username,
-    msg_send: Mutex<tokio::sync::mpsc::Sender<ToClientMsg>>,
+    msg_send: Mutex<tokio::sync::mpsc::Sender<Arc<String>>>,
     close_send: tokio::sync::mpsc::Sender<()>,
+    joined_at: Instant,
+    terminal_size: Option<(u16, u16)>,
+    /// how many invalid messages this player sent
+    offenses: u32,
+    rate_limits: RateLimits,
+    /// when the client last sent any message, to notice clients that are gone
+    last_seen: Instant,
+    /// what was sent to and received from the client, counted by its connection
+    traffic: Arc<Traffic>,
+    /// from this many queued messages on, the messages the client can recover from are dropped,
+    /// so a client on a bad connection doesn't fall further and further behind
+    backlog_limit: u64,
+    /// whether lines were dropped, so the client gets all of them once it caught up
+    needs_canvas_sync: AtomicBool,
 }
 
 impl UserSession {
     fn new(
         username: Username,
-        msg_send: tokio::sync::mpsc::Sender<ToClientMsg>,
+        msg_send: tokio::sync::mpsc::Sender<Arc<String>>,
         close_send: tokio::sync::mpsc::Sender<()>,
+        traffic: Arc<Traffic>,
+        backlog_limit: u64,
     ) -> Self {
         UserSession {
             username,
             msg_send: Mutex::new(msg_send),
             close_send,
+            joined_at: Instant::now(),
+            terminal_size: None,
+            offenses: 0,
+            rate_limits: RateLimits::default(),
+            last_seen: Instant::now(),
+            traffic,
+            backlog_limit,
+            needs_canvas_sync: AtomicBool::new(false),
         }
     }
 
@@ -80,19 +234,121 @@ impl UserSession {
     }
 
     async fn send(&self, msg: ToClientMsg) -> Result<()> {
-        self.msg_send.lock().await.send(msg.clone()).await?;
-        Ok(())
+        self.send_serialized(&SerializedMsg::new(&msg)).await
     }
+
+    /// whether so many messages are queued for the client that it falls behind the game
+    fn is_behind(&self) -> bool {
+        self.traffic.backlog() >= self.backlog_limit
+    }
+
+    /// whether the client missed lines and caught up since, so it should get all lines again
+    fn take_canvas_sync(&self) -> bool {
+        !self.is_behind() && self.needs_canvas_sync.swap(false, Ordering::Relaxed)
+    }
+
+    /// skip a message the client can recover from, remembering when it misses lines
+    fn drop_msg(&self, msg: &SerializedMsg) {
+        if msg.draws {
+            self.needs_canvas_sync.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// queue a message for this client without waiting for it, so a slow client can't hold up
+    /// everyone else. While the client is behind, messages it can recover from are dropped.
+    /// If the queue is full anyway, the client gets disconnected.
+    async fn send_serialized(&self, msg: &SerializedMsg) -> Result<()> {
+        if msg.droppable && self.is_behind() {
+            self.drop_msg(msg);
+            return Ok(());
+        }
+        match self.msg_send.lock().await.try_send(msg.json.clone()) {
+            Ok(()) => {
+                self.traffic.record_queued();
+                Ok(())
+            }
+            Err(TrySendError::Full(_)) => {
+                if msg.droppable {
+                    self.drop_msg(msg);
+                } else {
+                    eprintln!("Disconnecting {}, they can't keep up", self.username);
+                    let _ = self.close_send.clone().try_send(());
+                }
+                Ok(())
+            }
+            // writing to the client failed or stalled, and its connection is shutting down.
+            // It leaves the game once that's done, so there's nothing left to do here.
+            Err(TrySendError::Closed(_)) => Ok(()),
+        }
+    }
+}
+
+/// a message that's already serialized in an envelope, so broadcasts only serialize it once
+/// for all sessions. Every client that gets in understands envelopes.
+#[derive(Debug, Clone)]
+struct SerializedMsg {
+    json: Arc<String>,
+    droppable: bool,
+    /// whether the message draws on the canvas, so a client that misses it needs all lines again
+    draws: bool,
+}
+
+impl SerializedMsg {
+    fn new(msg: &ToClientMsg) -> Self {
+        SerializedMsg {
+            draws: matches!(msg, ToClientMsg::NewLine(..)),
+            ..SerializedMsg::from_serializable(msg, is_droppable(msg))
+        }
+    }
+
+    fn from_serializable<T: Serialize>(msg: &T, droppable: bool) -> Self {
+        SerializedMsg {
+            json: Arc::new(message::encode_in_envelope(msg)),
+            droppable,
+            draws: false,
+        }
+    }
+}
+
+/// serializes exactly like `ToClientMsg::SkribblStateChanged`, but borrows the state
+#[derive(Serialize)]
+enum SkribblStateChangedRef<'a> {
+    SkribblStateChanged(&'a SkribblState),
+}
+
+/// whether a message may be dropped when a client can't keep up.
+/// Missed lines are sent again once the client caught up, and the time is sent again every tick.
+fn is_droppable(msg: &ToClientMsg) -> bool {
+    matches!(
+        msg,
+        ToClientMsg::NewLine(..)
+            | ToClientMsg::CanvasChecksum(..)
+            | ToClientMsg::TimeChanged(_)
+            | ToClientMsg::GameStarting(_)
+            | ToClientMsg::PenMoved(_)
+    )
 }
 
 #[derive(Debug)]
 pub enum GameState {
     FreeDraw,
-    Skribbl(SkribblState),
+    /// a game was requested, and starts at the given point in time unless players are missing
+    Starting(Instant),
+    Skribbl(SkribblGame),
+    /// the game is over, and players vote for the best drawing until the given point in time
+    DrawingVote(DrawingVote),
+}
+
+#[derive(Debug)]
+pub struct DrawingVote {
+    state: SkribblGame,
+    /// which drawing every player voted for, as an index into the drawings of the game
+    votes: HashMap<Username, usize>,
+    ends_at: Instant,
 }
 
 impl GameState {
-    fn skribbl_state(&self) -> Option<&SkribblState> {
+    fn skribbl_state(&self) -> Option<&SkribblGame> {
         match self {
             GameState::Skribbl(state) => Some(state),
             _ => None,
@@ -100,46 +356,585 @@ impl GameState {
     }
 }
 
+/// games whose scores add up to one result, set up by the leader with `!series`
+#[derive(Debug)]
+struct Series {
+    games: u32,
+    played: u32,
+    totals: HashMap<Username, u32>,
+}
+
+impl Series {
+    fn new(games: u32) -> Series {
+        Series {
+            games,
+            played: 0,
+            totals: HashMap::new(),
+        }
+    }
+
+    /// the players by their total score, best first
+    fn standings(&self) -> Vec<(&Username, u32)> {
+        let mut standings: Vec<_> = self
+            .totals
+            .iter()
+            .map(|(username, score)| (username, *score))
+            .collect();
+        standings.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then(a_name.cmp(b_name)));
+        standings
+    }
+
+    fn is_over(&self) -> bool {
+        self.played >= self.games
+    }
+}
+
 #[derive(Debug)]
 struct ServerState {
     sessions: HashMap<Username, UserSession>,
+    /// the player allowed to use lobby controls, like kicking other players
+    pub leader: Option<Username>,
     pub lines: Vec<data::Line>,
     pub dimensions: (usize, usize),
     pub game_state: GameState,
-    pub words: Option<Vec<String>>,
+    pub words: Option<Vec<Word>>,
+    /// the difficulties the words of new games are picked from
+    pub difficulties: Vec<Difficulty>,
+    /// the language the words of new games are picked from, or `None` for all languages
+    pub language: Option<String>,
+    /// the least and the most characters the words of new games have
+    pub word_lengths: (usize, usize),
+    /// how the drawing player is chosen in new games
+    pub rotation: TurnRotation,
+    /// whether players vote for the best drawing when a game ends
+    pub drawing_vote: bool,
+    /// how many seconds the turns of new games last
+    pub round_duration: u64,
+    /// whether the other players see where the drawing player's pen is
+    pub pen_sharing: bool,
+    /// when the position of the drawing player's pen was last relayed
+    last_pen_at: Option<Instant>,
+    /// the latest position of the drawing player's pen that came in too fast to be relayed,
+    /// which is relayed on the next tick, so the others don't see the pen where it was before
+    pending_pen: Option<(Username, data::Coord)>,
+    /// the leader before the server restarted, who is the leader again when rejoining
+    previous_leader: Option<Username>,
+    /// when the traffic of the players was last checked, and what it was then
+    traffic_checked_at: Instant,
+    checked_traffic: HashMap<Username, TrafficStats>,
+    /// the files the words were read from, kept around so they can be reloaded
+    word_files: Vec<PathBuf>,
+    /// the file with the words that are removed from the word files, re-read on reload as well
+    blocked_words: Option<PathBuf>,
+    /// when the checksum of the canvas was last sent to the clients
+    last_checksum_at: Instant,
+    /// how long to wait for more players before starting a game
+    start_countdown: Duration,
+    /// how many players are needed to start a game, and to keep it going
+    min_players: usize,
+    heartbeat: Heartbeat,
+    /// what was drawn in the finished turns of the current game
+    drawings: Vec<Drawing>,
+    /// used for everything random in the game, seeded with `--seed` to make games reproducible
+    rng: StdRng,
+    started_at: Instant,
+    /// where to write the stats of every finished game to
+    stats_dir: Option<PathBuf>,
+    /// the series the finished games count towards, if the leader started one
+    series: Option<Series>,
+    /// the last `MAX_REPLAYS` finished games, oldest first
+    replays: VecDeque<(ReplayInfo, Vec<Drawing>)>,
+    next_replay_id: u32,
 }
 
 impl ServerState {
-    fn new(game_state: GameState, dimensions: (usize, usize), words: Option<Vec<String>>) -> Self {
+    fn new(
+        game_state: GameState,
+        dimensions: (usize, usize),
+        words: Option<Vec<Word>>,
+        word_files: Vec<PathBuf>,
+        opts: &ServerOpts,
+    ) -> Self {
+        let rng = match opts.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
         ServerState {
             sessions: HashMap::new(),
+            leader: None,
             lines: Vec::new(),
             dimensions,
             game_state,
             words,
+            difficulties: Difficulty::ALL.to_vec(),
+            language: None,
+            word_lengths: (1, MAX_WORD_LENGTH),
+            rotation: TurnRotation::InOrder,
+            drawing_vote: true,
+            round_duration: opts.round_duration,
+            pen_sharing: true,
+            last_pen_at: None,
+            pending_pen: None,
+            previous_leader: None,
+            traffic_checked_at: Instant::now(),
+            checked_traffic: HashMap::new(),
+            word_files,
+            blocked_words: opts.blocked_words.clone(),
+            last_checksum_at: Instant::now(),
+            start_countdown: opts.start_countdown,
+            min_players: opts.min_players,
+            heartbeat: opts.heartbeat,
+            drawings: Vec::new(),
+            rng,
+            started_at: Instant::now(),
+            stats_dir: opts.stats_dir.clone(),
+            series: None,
+            replays: VecDeque::new(),
+            next_replay_id: 1,
+        }
+    }
+
+    fn status(&self) -> ServerStatus {
+        let mut players: Vec<Username> = self.sessions.keys().cloned().collect();
+        players.sort();
+        ServerStatus {
+            version: env!("CARGO_PKG_VERSION"),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            players,
+            game: match self.game_state {
+                GameState::FreeDraw => "free draw",
+                GameState::Starting(_) => "starting",
+                GameState::Skribbl(_) => "playing",
+                GameState::DrawingVote(_) => "voting",
+            },
+            traffic: self
+                .sessions
+                .iter()
+                .map(|(username, session)| (username.clone(), session.traffic.stats()))
+                .collect(),
+        }
+    }
+
+    /// warn about a player who caused most of the traffic since the last check,
+    /// which is likely whatever makes the game lag for everyone
+    fn check_traffic(&mut self) {
+        let current: HashMap<Username, TrafficStats> = self
+            .sessions
+            .iter()
+            .map(|(username, session)| (username.clone(), session.traffic.stats()))
+            .collect();
+        let bytes = current.iter().map(|(username, stats)| {
+            let earlier = self.checked_traffic.get(username).copied();
+            (username, stats.since(&earlier.unwrap_or_default()).bytes())
+        });
+        if let Some((username, bytes, share)) = traffic::dominating(bytes) {
+            eprintln!(
+                "{} caused {:.0}% of the traffic since the last check ({} bytes)",
+                username,
+                share * 100.0,
+                bytes
+            );
+        }
+        self.checked_traffic = current;
+    }
+
+    fn snapshot(&self) -> LobbySnapshot {
+        LobbySnapshot {
+            leader: self.leader.clone(),
+            dimensions: self.dimensions,
+            difficulties: self.difficulties.clone(),
+            language: self.language.clone(),
+            word_lengths: self.word_lengths,
+            rotation: self.rotation,
+            drawing_vote: self.drawing_vote,
+            round_duration: self.round_duration,
+            pen_sharing: self.pen_sharing,
+        }
+    }
+
+    /// take over the settings of the lobby before the server restarted.
+    /// Settings the leader couldn't have chosen, e.g. because the file was edited, are left as they are.
+    fn restore(&mut self, snapshot: LobbySnapshot) {
+        self.previous_leader = snapshot.leader;
+        if valid_dimensions(snapshot.dimensions) {
+            self.dimensions = snapshot.dimensions;
+        } else {
+            eprintln!(
+                "Not restoring the canvas size {}x{}, it must be between {}x{} and {}x{}",
+                snapshot.dimensions.0,
+                snapshot.dimensions.1,
+                MIN_DIMENSIONS.0,
+                MIN_DIMENSIONS.1,
+                MAX_DIMENSIONS.0,
+                MAX_DIMENSIONS.1
+            );
+        }
+        let (min, max) = snapshot.word_lengths;
+        let available = self.available_words(
+            &snapshot.difficulties,
+            snapshot.language.as_deref(),
+            snapshot.word_lengths,
+        );
+        if min == 0 || min > max || available == 0 {
+            eprintln!("Not restoring the choice of words, there are no words to choose from");
+        } else {
+            self.difficulties = snapshot.difficulties;
+            self.language = snapshot.language;
+            self.word_lengths = snapshot.word_lengths;
+        }
+        self.rotation = snapshot.rotation;
+        self.drawing_vote = snapshot.drawing_vote;
+        if (MIN_ROUND_DURATION..=MAX_ROUND_DURATION).contains(&snapshot.round_duration) {
+            self.round_duration = snapshot.round_duration;
+        } else {
+            eprintln!(
+                "Not restoring turns of {}s, they last {} to {} seconds",
+                snapshot.round_duration, MIN_ROUND_DURATION, MAX_ROUND_DURATION
+            );
+        }
+        self.pen_sharing = snapshot.pen_sharing;
+    }
+
+    /// re-read the word files. Running games keep their words, new games use the new ones.
+    /// If the files can't be read or have no usable words, the old words are kept.
+    fn reload_words(&mut self) {
+        if self.word_files.is_empty() {
+            return;
+        }
+        let blocked = match words::read_blocked_words(self.blocked_words.as_deref()) {
+            Ok(blocked) => blocked,
+            Err(err) => return eprintln!("Could not reload the blocked words: {}", err),
+        };
+        match WordList::load(&self.word_files, &blocked) {
+            Ok(list) => {
+                println!("Reloaded the words:\n{}", list.stats);
+                self.words = Some(list.words);
+            }
+            Err(err) => eprintln!("Could not reload the words: {}", err),
         }
     }
 
     async fn remove_player(&mut self, username: &Username) -> Result<()> {
         self.sessions.remove(username).map(|x| x.close());
+        if self.leader.as_ref() == Some(username) {
+            self.migrate_leader().await?;
+        }
         let state = match &mut self.game_state {
             GameState::Skribbl(state) => state,
             _ => return Ok(()),
         };
-        if state.is_drawing(username) {
-            state.next_turn();
+        let was_drawing = state.view.is_drawing(username);
+        if was_drawing {
+            state.next_turn(&mut self.rng);
         }
         state.remove_user(username);
-        let state = state.clone();
-        self.broadcast(ToClientMsg::SkribblStateChanged(state))
-            .await?;
+        let players_left = state.view.player_states.len() + state.view.waiting_players.len();
+        self.broadcast_skribbl_state().await?;
+        if was_drawing {
+            self.broadcast_event(GameEvent::TurnSkipped(username.clone()))
+                .await?;
+        }
+        if players_left < self.min_players {
+            self.broadcast_system_msg("Not enough players left to keep playing".to_string())
+                .await?;
+            // there aren't enough players left for a vote on the best drawing either
+            if let GameState::Skribbl(state) =
+                std::mem::replace(&mut self.game_state, GameState::FreeDraw)
+            {
+                self.finish_game(state).await?;
+            }
+        }
         Ok(())
     }
 
-    async fn on_command_msg(&mut self, _username: &Username, msg: &CommandMsg) -> Result<()> {
+    async fn on_user_left(&mut self, username: &Username) -> Result<()> {
+        self.remove_player(username).await?;
+        if let GameState::Skribbl(_) = self.game_state {
+            self.broadcast_event(GameEvent::Left(username.clone()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// make the longest-connected player the new leader
+    async fn migrate_leader(&mut self) -> Result<()> {
+        self.leader = self
+            .sessions
+            .values()
+            .min_by_key(|session| session.joined_at)
+            .map(|session| session.username.clone());
+        if let Some(leader) = self.leader.clone() {
+            tokio::try_join!(
+                self.broadcast(ToClientMsg::LeaderChanged(leader.clone())),
+                self.broadcast_event(GameEvent::NewLeader(leader.clone())),
+            )?;
+        }
+        Ok(())
+    }
+
+    async fn on_command_msg(&mut self, username: &Username, msg: &CommandMsg) -> Result<()> {
+        if self.leader.as_ref() != Some(username) {
+            if let Some(session) = self.sessions.get(username) {
+                session
+                    .send(ToClientMsg::NewMessage(Message::SystemMsg(
+                        "Only the leader can do that".to_string(),
+                    )))
+                    .await?;
+            }
+            return Ok(());
+        }
         match msg {
             CommandMsg::KickPlayer(kicked_player) => self.remove_player(kicked_player).await?,
+            CommandMsg::SetDimensions(dimensions) => {
+                self.set_dimensions(username, *dimensions).await?
+            }
+            CommandMsg::SetDifficulties(difficulties) => {
+                self.set_difficulties(username, difficulties).await?
+            }
+            CommandMsg::SetLanguage(language) => {
+                self.set_language(username, language.clone()).await?
+            }
+            CommandMsg::SetWordLengths(lengths) => {
+                self.set_word_lengths(username, *lengths).await?
+            }
+            CommandMsg::SetTurnRotation(rotation) => {
+                self.rotation = *rotation;
+                let description = match rotation {
+                    TurnRotation::InOrder => "every player draws once per round",
+                    TurnRotation::Random => "a random player draws each turn",
+                    TurnRotation::Winner => "the first player to guess the word draws next",
+                };
+                self.broadcast_system_msg(format!("In new games, {}", description))
+                    .await?
+            }
+            CommandMsg::SetDrawingVote(enabled) => {
+                self.drawing_vote = *enabled;
+                let msg = if *enabled {
+                    "When a game ends, everyone votes for the best drawing"
+                } else {
+                    "Games end without voting for the best drawing"
+                };
+                self.broadcast_system_msg(msg.to_string()).await?
+            }
+            CommandMsg::SetRoundDuration(secs) => self.set_round_duration(username, *secs).await?,
+            CommandMsg::SetPenSharing(enabled) => {
+                self.pen_sharing = *enabled;
+                let msg = if *enabled {
+                    "Everyone sees where the drawing player's pen is"
+                } else {
+                    "Only the drawn lines are shown, not where the pen is"
+                };
+                self.broadcast(ToClientMsg::PenMoved(None)).await?;
+                self.broadcast_system_msg(msg.to_string()).await?
+            }
+            CommandMsg::ModerateCanvas { skip_turn } => {
+                self.moderate_canvas(username, *skip_turn).await?
+            }
+            CommandMsg::EndGame => self.end_game().await?,
+            CommandMsg::SetSeries(games) => self.set_series(username, *games).await?,
+        }
+        Ok(())
+    }
+
+    /// clear the canvas for everyone, and skip the turn of the drawing player if asked to
+    async fn moderate_canvas(&mut self, username: &Username, skip_turn: bool) -> Result<()> {
+        self.lines.clear();
+        let skipped = match &mut self.game_state {
+            GameState::Skribbl(state) if skip_turn => {
+                let drawing_user = state.view.drawing_user.clone();
+                state.skip_turn(&mut self.rng);
+                Some(drawing_user)
+            }
+            _ => None,
+        };
+        self.broadcast(ToClientMsg::ClearCanvas).await?;
+        if skipped.is_some() {
+            self.broadcast_skribbl_state().await?;
+        }
+        let msg = match skipped {
+            Some(drawing_user) => format!(
+                "{} cleared the canvas and skipped the turn of {}",
+                username, drawing_user
+            ),
+            None => format!("{} cleared the canvas", username),
+        };
+        self.broadcast_system_msg(msg).await
+    }
+
+    async fn set_series(&mut self, username: &Username, games: Option<u32>) -> Result<()> {
+        let msg = match games {
+            Some(games) if !(2..=MAX_SERIES_GAMES).contains(&games) => {
+                let msg = format!("A series has 2 to {} games", MAX_SERIES_GAMES);
+                return self
+                    .send_to(username, ToClientMsg::NewMessage(Message::SystemMsg(msg)))
+                    .await;
+            }
+            Some(games) => {
+                self.series = Some(Series::new(games));
+                format!(
+                    "The scores of the next {} games add up to find the winner of the series",
+                    games
+                )
+            }
+            None if self.series.take().is_some() => "The series was cancelled".to_string(),
+            None => return Ok(()),
+        };
+        self.broadcast_system_msg(msg).await
+    }
+
+    async fn set_round_duration(&mut self, username: &Username, secs: u64) -> Result<()> {
+        if !(MIN_ROUND_DURATION..=MAX_ROUND_DURATION).contains(&secs) {
+            let msg = format!(
+                "Turns last {} to {} seconds",
+                MIN_ROUND_DURATION, MAX_ROUND_DURATION
+            );
+            return self
+                .send_to(username, ToClientMsg::NewMessage(Message::SystemMsg(msg)))
+                .await;
+        }
+        self.round_duration = secs;
+        self.broadcast_system_msg(format!("Turns of new games last {}s", secs))
+            .await
+    }
+
+    async fn set_difficulties(
+        &mut self,
+        username: &Username,
+        difficulties: &[Difficulty],
+    ) -> Result<()> {
+        let available =
+            self.available_words(difficulties, self.language.as_deref(), self.word_lengths);
+        if available == 0 {
+            let msg = "There are no words with these difficulties".to_string();
+            self.send_to(username, ToClientMsg::NewMessage(Message::SystemMsg(msg)))
+                .await?;
+            return Ok(());
+        }
+
+        self.difficulties = difficulties.to_vec();
+        let names = difficulties
+            .iter()
+            .map(|difficulty| difficulty.to_string())
+            .collect::<Vec<_>>();
+        self.broadcast_system_msg(format!(
+            "New games will use {} words ({} words available)",
+            names.join(", "),
+            available
+        ))
+        .await
+    }
+
+    async fn set_language(&mut self, username: &Username, language: Option<String>) -> Result<()> {
+        let language = language.map(|language| language.to_lowercase());
+        let available =
+            self.available_words(&self.difficulties, language.as_deref(), self.word_lengths);
+        if available == 0 {
+            let msg = "There are no words in this language".to_string();
+            self.send_to(username, ToClientMsg::NewMessage(Message::SystemMsg(msg)))
+                .await?;
+            return Ok(());
         }
+
+        let msg = match &language {
+            Some(language) => format!(
+                "New games will use words in {} ({} words available)",
+                language, available
+            ),
+            None => format!(
+                "New games will use words in all languages ({} words available)",
+                available
+            ),
+        };
+        self.language = language;
+        self.broadcast_system_msg(msg).await
+    }
+
+    async fn set_word_lengths(
+        &mut self,
+        username: &Username,
+        (min, max): (usize, usize),
+    ) -> Result<()> {
+        let reason = if min == 0 || min > max {
+            Some("The shortest words can't be longer than the longest ones".to_string())
+        } else if self.available_words(&self.difficulties, self.language.as_deref(), (min, max))
+            == 0
+        {
+            Some(format!(
+                "There are no words with {} to {} characters",
+                min, max
+            ))
+        } else {
+            None
+        };
+        if let Some(msg) = reason {
+            return self
+                .send_to(username, ToClientMsg::NewMessage(Message::SystemMsg(msg)))
+                .await;
+        }
+
+        self.word_lengths = (min, max);
+        let available =
+            self.available_words(&self.difficulties, self.language.as_deref(), (min, max));
+        self.broadcast_system_msg(format!(
+            "New games will use words with {} to {} characters ({} words available)",
+            min, max, available
+        ))
+        .await
+    }
+
+    /// how many words new games could use with the given difficulties, language and lengths
+    fn available_words(
+        &self,
+        difficulties: &[Difficulty],
+        language: Option<&str>,
+        lengths: (usize, usize),
+    ) -> usize {
+        match &self.words {
+            Some(words) => words::pick_words(words, difficulties, language, lengths).len(),
+            None => 0,
+        }
+    }
+
+    async fn set_dimensions(
+        &mut self,
+        username: &Username,
+        dimensions: (usize, usize),
+    ) -> Result<()> {
+        let (width, height) = dimensions;
+        if !valid_dimensions(dimensions) {
+            let msg = format!(
+                "The canvas size must be between {}x{} and {}x{}",
+                MIN_DIMENSIONS.0, MIN_DIMENSIONS.1, MAX_DIMENSIONS.0, MAX_DIMENSIONS.1
+            );
+            self.send_to(username, ToClientMsg::NewMessage(Message::SystemMsg(msg)))
+                .await?;
+            return Ok(());
+        }
+
+        let too_small = self
+            .sessions
+            .values()
+            .filter(|session| match session.terminal_size {
+                Some((w, h)) => (w as usize) < width || (h as usize) < height,
+                None => false,
+            })
+            .map(|session| session.username.to_string())
+            .collect::<Vec<_>>();
+        if !too_small.is_empty() {
+            let msg = format!(
+                "The canvas doesn't fit the terminal of {}, they will have to scroll",
+                too_small.join(", ")
+            );
+            self.send_to(username, ToClientMsg::NewMessage(Message::SystemMsg(msg)))
+                .await?;
+        }
+
+        self.dimensions = dimensions;
+        self.lines.clear();
+        tokio::try_join!(
+            self.broadcast(ToClientMsg::DimensionsChanged(dimensions)),
+            self.broadcast_system_msg(format!("The canvas was resized to {}x{}", width, height)),
+        )?;
         Ok(())
     }
 
@@ -147,65 +942,123 @@ impl ServerState {
         let mut should_broadcast = true;
         match self.game_state {
             GameState::Skribbl(ref mut state) => {
-                let can_guess = state.can_guess(&username);
+                let can_guess = state.view.can_guess(&username);
                 let remaining_time = state.remaining_time();
-                let current_word = state.current_word().to_string();
+                let difficulty = state.view.difficulty();
+                let round_duration = state.round_duration;
+                let current_word = state.view.current_word().to_string();
                 let noone_already_solved = state
+                    .view
                     .player_states
                     .iter()
                     .all(|(_, player)| !player.has_solved);
 
-                if let Some(player_state) = state.player_states.get_mut(&username) {
-                    if can_guess && msg.text().eq_ignore_ascii_case(&current_word) {
+                let feedback = guess::check_guess(msg.text(), &current_word);
+
+                // players waiting for the next round could otherwise spoil the word in the chat
+                if state.view.is_waiting(&username) && feedback != GuessFeedback::Wrong {
+                    let msg = Message::SystemMsg(
+                        "You can start guessing when the next round begins".to_string(),
+                    );
+                    return self.send_to(&username, ToClientMsg::NewMessage(msg)).await;
+                }
+                // spectators see the drawing without guessing, so they could help the guessers.
+                // Until they join the game, they only talk among themselves.
+                if state.view.is_waiting(&username) {
+                    let spectators = state.view.waiting_players.clone();
+                    return self
+                        .broadcast_to(&spectators, ToClientMsg::NewMessage(msg))
+                        .await;
+                }
+
+                if can_guess {
+                    state.record_guess(&username);
+                }
+                if let Some(player_state) = state.view.player_states.get_mut(&username) {
+                    if can_guess && feedback == GuessFeedback::Correct {
                         should_broadcast = false;
                         if noone_already_solved {
-                            state.round_end_time -= remaining_time as u64 / 2;
+                            state.round_end_time -= Duration::from_secs(remaining_time as u64 / 2);
                         }
-                        player_state.on_solve(remaining_time);
+                        skribbl::reward_solve(
+                            player_state,
+                            remaining_time,
+                            round_duration,
+                            difficulty,
+                        );
+                        state.record_solve(username.clone());
                         let all_solved = state.did_all_solve();
+                        let reveal = GameEvent::WordRevealed {
+                            word: current_word.clone(),
+                            definition: state.current_definition().map(str::to_string),
+                            solve_times: state.view.solve_times.clone(),
+                        };
                         if all_solved {
-                            state.next_turn();
+                            self.drawings.push(Drawing::new(
+                                state.view.drawing_user.clone(),
+                                current_word.clone(),
+                                &self.lines,
+                                self.dimensions,
+                            ));
+                            state.next_turn(&mut self.rng);
                         }
-                        let state = state.clone();
                         tokio::try_join!(
-                            self.broadcast(ToClientMsg::SkribblStateChanged(state)),
-                            self.broadcast_system_msg(format!("{} guessed it!", username)),
+                            self.broadcast_skribbl_state(),
+                            self.broadcast_event(GameEvent::Guessed(username.clone())),
                         )?;
                         if all_solved {
                             self.lines.clear();
                             tokio::try_join!(
                                 self.broadcast(ToClientMsg::ClearCanvas),
-                                self.broadcast_system_msg(format!(
-                                    "The word was: \"{}\"",
-                                    current_word
-                                ))
+                                self.broadcast_event(reveal)
                             )?;
                         }
-                    } else if is_very_close_to(msg.text().to_string(), current_word.to_string()) {
+                    } else if feedback != GuessFeedback::Wrong {
+                        // near misses would spoil the word for everyone else,
+                        // so only the guesser gets to see how close they were
                         should_broadcast = false;
-                        if can_guess {
-                            self.send_to(
-                                &username,
-                                ToClientMsg::NewMessage(Message::SystemMsg(
-                                    "You're very close!".to_string(),
-                                )),
-                            )
-                            .await?;
+                        if state.view.is_drawing(&username) {
+                            // near misses are often ordinary chat, only the word itself gives it away
+                            if feedback == GuessFeedback::Correct
+                                || feedback == GuessFeedback::ContainsWord
+                            {
+                                self.on_offense(
+                                    &username,
+                                    "You can't give away the word while drawing",
+                                )
+                                .await?;
+                            } else {
+                                let msg = Message::SystemMsg(
+                                    "Your message was too close to the word, so nobody else saw it"
+                                        .to_string(),
+                                );
+                                self.send_to(&username, ToClientMsg::NewMessage(msg))
+                                    .await?;
+                            }
+                            return Ok(());
+                        }
+                        if let (true, Some(closeness)) = (can_guess, feedback.closeness()) {
+                            self.send_event_to(&username, GameEvent::CloseGuess(closeness))
+                                .await?;
                         }
                     }
                 }
             }
-            GameState::FreeDraw => {
-                if let Some(words) = &self.words {
-                    let skribbl_state = SkribblState::new(
-                        self.sessions.keys().cloned().collect::<Vec<Username>>(),
-                        words.clone(),
-                    );
-                    self.game_state = GameState::Skribbl(skribbl_state.clone());
-                    self.broadcast(ToClientMsg::SkribblStateChanged(skribbl_state))
-                        .await?;
+            GameState::FreeDraw
+                if self.words.is_some() && self.sessions.len() >= self.min_players =>
+            {
+                if self.start_countdown == Duration::from_secs(0) {
+                    self.start_game().await?;
+                } else {
+                    self.game_state = GameState::Starting(Instant::now() + self.start_countdown);
+                    self.broadcast_system_msg(format!(
+                        "The game starts in {}s",
+                        self.start_countdown.as_secs()
+                    ))
+                    .await?;
                 }
             }
+            GameState::FreeDraw | GameState::Starting(_) | GameState::DrawingVote(_) => {}
         }
 
         if should_broadcast {
@@ -215,56 +1068,578 @@ impl ServerState {
         Ok(())
     }
 
+    /// start a game with everyone that is connected
+    async fn start_game(&mut self) -> Result<()> {
+        let words = match &self.words {
+            Some(words) => words,
+            None => return Ok(()),
+        };
+        // the words might have been reloaded since the difficulties were chosen
+        let mut chosen_words = words::pick_words(
+            words,
+            &self.difficulties,
+            self.language.as_deref(),
+            self.word_lengths,
+        );
+        if chosen_words.is_empty() {
+            chosen_words = words.clone();
+        }
+        let skribbl_state = SkribblGame::new(
+            self.sessions.keys().cloned().collect::<Vec<Username>>(),
+            chosen_words,
+            self.rotation,
+            self.round_duration,
+            &mut self.rng,
+        );
+        self.game_state = GameState::Skribbl(skribbl_state);
+        self.drawings.clear();
+        self.broadcast_skribbl_state().await
+    }
+
+    /// end the running game, letting everyone vote for the best drawing first if there is a choice
+    async fn end_game(&mut self) -> Result<()> {
+        let state = match std::mem::replace(&mut self.game_state, GameState::FreeDraw) {
+            GameState::Skribbl(state) => state,
+            game_state => {
+                self.game_state = game_state;
+                return Ok(());
+            }
+        };
+        if self.drawing_vote && self.drawings.len() >= 2 {
+            self.game_state = GameState::DrawingVote(DrawingVote {
+                state,
+                votes: HashMap::new(),
+                ends_at: Instant::now() + DRAWING_VOTE_DURATION,
+            });
+            tokio::try_join!(
+                self.broadcast(ToClientMsg::DrawingVoteStarted(self.drawings.clone())),
+                self.broadcast_system_msg(format!(
+                    "Vote for the best drawing with !vote <number> within {}s",
+                    DRAWING_VOTE_DURATION.as_secs()
+                )),
+            )?;
+            Ok(())
+        } else {
+            self.finish_game(state).await
+        }
+    }
+
+    /// relay where the drawing player's pen is to everyone else. Positions that come in faster
+    /// than clients send them are held back until the next tick, but lifting the pen always gets through.
+    async fn on_pen_moved(&mut self, username: &Username, pos: Option<data::Coord>) -> Result<()> {
+        let is_drawing = match &self.game_state {
+            GameState::Skribbl(state) => state.view.is_drawing(username),
+            _ => false,
+        };
+        if !self.pen_sharing || !is_drawing {
+            self.pending_pen = None;
+            return Ok(());
+        }
+        let too_fast = self
+            .last_pen_at
+            .is_some_and(|at| at.elapsed() < PEN_INTERVAL / 2);
+        match pos {
+            Some(pos) if too_fast => {
+                self.pending_pen = Some((username.clone(), pos));
+                return Ok(());
+            }
+            _ => self.pending_pen = None,
+        }
+        self.last_pen_at = Some(Instant::now());
+        let others: Vec<Username> = self
+            .sessions
+            .keys()
+            .filter(|other| *other != username)
+            .cloned()
+            .collect();
+        self.broadcast_to(&others, ToClientMsg::PenMoved(pos)).await
+    }
+
+    /// count a players vote for the best drawing, ending the vote once everyone voted
+    async fn on_drawing_vote(&mut self, username: &Username, idx: usize) -> Result<()> {
+        let vote = match &mut self.game_state {
+            GameState::DrawingVote(vote) => vote,
+            _ => return Ok(()),
+        };
+        let reason = match self.drawings.get(idx) {
+            None => Some("There is no drawing with that number"),
+            Some(drawing) if &drawing.drawer == username => {
+                Some("You can't vote for your own drawing")
+            }
+            Some(_) => None,
+        };
+        if let Some(reason) = reason {
+            let msg = Message::SystemMsg(reason.to_string());
+            return self.send_to(username, ToClientMsg::NewMessage(msg)).await;
+        }
+        vote.votes.insert(username.clone(), idx);
+        let everyone_voted = self
+            .sessions
+            .keys()
+            .all(|username| vote.votes.contains_key(username));
+        if everyone_voted {
+            self.finish_drawing_vote().await?;
+        }
+        Ok(())
+    }
+
+    /// give the bonus to the drawings with the most votes and end the game
+    async fn finish_drawing_vote(&mut self) -> Result<()> {
+        let mut vote = match std::mem::replace(&mut self.game_state, GameState::FreeDraw) {
+            GameState::DrawingVote(vote) => vote,
+            game_state => {
+                self.game_state = game_state;
+                return Ok(());
+            }
+        };
+        let mut counts = vec![0; self.drawings.len()];
+        for idx in vote.votes.values() {
+            counts[*idx] += 1;
+        }
+        let most_votes = counts.iter().cloned().max().unwrap_or(0);
+        if most_votes > 0 {
+            for (drawing, _) in self
+                .drawings
+                .iter()
+                .zip(&counts)
+                .filter(|(_, count)| **count == most_votes)
+            {
+                if let Some(player) = vote.state.view.player_states.get_mut(&drawing.drawer) {
+                    player.score += BEST_DRAWING_BONUS;
+                }
+                self.broadcast_system_msg(format!(
+                    "{}'s drawing of \"{}\" won the vote with {} votes (+{} points)",
+                    drawing.drawer, drawing.word, most_votes, BEST_DRAWING_BONUS
+                ))
+                .await?;
+            }
+        }
+        self.finish_game(vote.state).await
+    }
+
+    /// show everyone the final scores and drawings, and go back to drawing freely
+    async fn finish_game(&mut self, state: SkribblGame) -> Result<()> {
+        if let Some(dir) = &self.stats_dir {
+            if let Err(err) = write_game_stats(dir, &state) {
+                eprintln!("Could not write the stats of the game: {}", err);
+            }
+        }
+        self.keep_replay(&state);
+        self.game_state = GameState::FreeDraw;
+        self.lines.clear();
+        tokio::try_join!(
+            self.broadcast(ToClientMsg::GameOver(
+                state.view.clone(),
+                self.drawings.clone()
+            )),
+            self.broadcast(ToClientMsg::ClearCanvas),
+        )?;
+        self.count_towards_series(&state).await
+    }
+
+    /// keep the drawings of a finished game, so players can look at them later
+    fn keep_replay(&mut self, state: &SkribblGame) {
+        if self.drawings.is_empty() {
+            return;
+        }
+        let mut players: Vec<Username> = state.view.player_states.keys().cloned().collect();
+        players.sort();
+        let info = ReplayInfo {
+            id: self.next_replay_id,
+            finished_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            players,
+            drawings: self.drawings.len(),
+        };
+        self.next_replay_id += 1;
+        self.replays.push_back((info, self.drawings.clone()));
+        if self.replays.len() > MAX_REPLAYS {
+            self.replays.pop_front();
+        }
+    }
+
+    /// add the scores of a finished game to the running series,
+    /// showing the standings and announcing the winner once the series is over
+    async fn count_towards_series(&mut self, state: &SkribblGame) -> Result<()> {
+        let series = match &mut self.series {
+            Some(series) => series,
+            None => return Ok(()),
+        };
+        series.played += 1;
+        for (username, player) in &state.view.player_states {
+            *series.totals.entry(username.clone()).or_insert(0) += player.score;
+        }
+        let standings = series.standings();
+        let mut msgs = vec![format!(
+            "Series standings after game {}/{}: {}",
+            series.played,
+            series.games,
+            standings
+                .iter()
+                .map(|(username, score)| format!("{} {}", username, score))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )];
+        if series.is_over() {
+            let best = standings.first().map(|(_, score)| *score).unwrap_or(0);
+            let winners: Vec<String> = standings
+                .iter()
+                .filter(|(_, score)| *score == best)
+                .map(|(username, _)| username.to_string())
+                .collect();
+            msgs.push(format!(
+                "{} won the series with {} points!",
+                winners.join(" and "),
+                best
+            ));
+            self.series = None;
+        }
+        for msg in msgs {
+            self.broadcast_system_msg(msg).await?;
+        }
+        Ok(())
+    }
+
+    /// warn a player about an invalid message, kicking them once they sent too many
+    async fn on_offense(&mut self, username: &Username, reason: &str) -> Result<()> {
+        let offenses = match self.sessions.get_mut(username) {
+            Some(session) => {
+                session.offenses += 1;
+                session.offenses
+            }
+            None => return Ok(()),
+        };
+        if offenses >= MAX_OFFENSES {
+            self.remove_player(username).await?;
+            self.broadcast_system_msg(format!(
+                "{} was kicked for sending invalid messages",
+                username
+            ))
+            .await?;
+        } else {
+            let msg = format!("{} (warning {}/{})", reason, offenses, MAX_OFFENSES - 1);
+            self.send_to(username, ToClientMsg::NewMessage(Message::SystemMsg(msg)))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// count a guessers vote for more time, extending the turn once the majority voted for it
+    async fn on_extend_vote(&mut self, username: &Username) -> Result<()> {
+        let state = match &mut self.game_state {
+            GameState::Skribbl(state) => state,
+            _ => return Ok(()),
+        };
+        let reason = if state.view.is_drawing(username) {
+            Some("Only the guessers can vote for more time")
+        } else if state.time_extended() {
+            Some("The time was already extended this turn")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            let msg = Message::SystemMsg(reason.to_string());
+            return self.send_to(username, ToClientMsg::NewMessage(msg)).await;
+        }
+
+        let (is_new, votes, needed) = state.vote_extend(username);
+        if votes >= needed {
+            state.extend_time(TIME_EXTENSION);
+            let remaining_time = state.remaining_time();
+            tokio::try_join!(
+                self.broadcast(ToClientMsg::TimeChanged(remaining_time)),
+                self.broadcast_system_msg(format!(
+                    "The time was extended by {}s",
+                    TIME_EXTENSION.as_secs()
+                )),
+            )?;
+        } else if is_new {
+            self.broadcast_system_msg(format!(
+                "{} wants more time ({}/{} votes, type !extend to vote)",
+                username, votes, needed
+            ))
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// check a chat message, returning the reason if it should be rejected
+    fn validate_message(
+        username: &Username,
+        msg: &data::Message,
+    ) -> std::result::Result<(), &'static str> {
+        match msg {
+            Message::UserMsg(sender, _) if sender == username => {}
+            _ => return Err("You can only send messages as yourself"),
+        }
+        let text = msg.text().trim();
+        if text.is_empty() {
+            Err("Empty messages aren't allowed")
+        } else if text.chars().count() > MAX_MESSAGE_LENGTH {
+            Err("That message is too long")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// check a line, returning the reason if it should be rejected
+    fn validate_line(line: &data::Line) -> std::result::Result<(), &'static str> {
+        let x = line.start.0.max(line.end.0);
+        let y = line.start.1.max(line.end.1);
+        // lines slightly outside of the canvas can happen when it was just resized,
+        // but no client can draw outside of the biggest canvas
+        if line.size > MAX_BRUSH_SIZE
+            || x as usize >= MAX_DIMENSIONS.0
+            || y as usize >= MAX_DIMENSIONS.1
+        {
+            return Err("That line is outside of the canvas");
+        }
+        Ok(())
+    }
+
+    /// check if a player may send another message, dropping it if they are sending too many.
+    /// Players that keep flooding the server get kicked.
+    async fn check_rate_limit(&mut self, username: &Username, kind: Limited) -> Result<bool> {
+        let result = match self.sessions.get_mut(username) {
+            Some(session) => session.rate_limits.check(kind),
+            None => return Ok(false),
+        };
+        match result {
+            RateLimitResult::Allow => return Ok(true),
+            RateLimitResult::Drop => {
+                if kind == Limited::Chat {
+                    let msg = "You're sending messages too fast".to_string();
+                    self.send_to(username, ToClientMsg::NewMessage(Message::SystemMsg(msg)))
+                        .await?;
+                }
+            }
+            RateLimitResult::Kick => {
+                self.remove_player(username).await?;
+                self.broadcast_system_msg(format!("{} was kicked for flooding", username))
+                    .await?;
+            }
+        }
+        Ok(false)
+    }
+
     async fn on_to_srv_msg(&mut self, username: Username, msg: ToServerMsg) -> Result<()> {
+        // messages of a player that was just kicked or left can still be in flight
+        match self.sessions.get_mut(&username) {
+            // any message shows the client is still there, so busy players don't depend on heartbeats
+            Some(session) => session.last_seen = Instant::now(),
+            None => return Ok(()),
+        }
+        let limited = match &msg {
+            ToServerMsg::NewMessage(_) => Some(Limited::Chat),
+            ToServerMsg::NewLine(line) => Some(Limited::Draw(line.point_count())),
+            _ => None,
+        };
+        if let Some(kind) = limited {
+            if !self.check_rate_limit(&username, kind).await? {
+                // a kicked player, or one that left in the meantime, has nobody left to tell
+                if matches!(kind, Limited::Draw(_)) && self.sessions.contains_key(&username) {
+                    self.send_to(&username, ToClientMsg::LineRejected).await?;
+                }
+                return Ok(());
+            }
+        }
+
         match msg {
             ToServerMsg::CommandMsg(msg) => {
                 self.on_command_msg(&username, &msg).await?;
             }
-            ToServerMsg::NewMessage(message) => {
-                self.on_new_message(username, message).await?;
-            }
+            ToServerMsg::NewMessage(message) => match Self::validate_message(&username, &message) {
+                Ok(()) => self.on_new_message(username, message).await?,
+                Err(reason) => self.on_offense(&username, reason).await?,
+            },
             ToServerMsg::NewLine(line) => {
-                self.lines.push(line);
-                self.broadcast(ToClientMsg::NewLine(line)).await?;
+                // lines of players that aren't drawing aren't malicious,
+                // they arrive whenever a turn ends while someone is still drawing
+                let may_draw = match &self.game_state {
+                    GameState::Skribbl(state) => state.view.is_drawing(&username),
+                    GameState::DrawingVote(_) => false,
+                    GameState::FreeDraw | GameState::Starting(_) => true,
+                };
+                match (Self::validate_line(&line), line.clipped(self.dimensions)) {
+                    (Ok(()), Some(line)) if may_draw => {
+                        if let GameState::Skribbl(state) = &mut self.game_state {
+                            state.record_line(&line);
+                        }
+                        self.lines.push(line);
+                        self.broadcast_line(&username, self.lines.len() - 1, line)
+                            .await?;
+                    }
+                    (Ok(()), _) => self.send_to(&username, ToClientMsg::LineRejected).await?,
+                    (Err(reason), _) => {
+                        self.send_to(&username, ToClientMsg::LineRejected).await?;
+                        self.on_offense(&username, reason).await?;
+                    }
+                }
             }
             ToServerMsg::ClearCanvas => {
-                self.lines.clear();
-                self.broadcast(ToClientMsg::ClearCanvas).await?;
+                let may_clear = match &self.game_state {
+                    GameState::Skribbl(state) => state.view.is_drawing(&username),
+                    GameState::DrawingVote(_) => false,
+                    GameState::FreeDraw | GameState::Starting(_) => true,
+                };
+                if may_clear {
+                    self.lines.clear();
+                    self.broadcast(ToClientMsg::ClearCanvas).await?;
+                } else {
+                    self.on_offense(&username, "Only the drawing player can clear the canvas")
+                        .await?;
+                }
+            }
+            ToServerMsg::RequestCanvasSync => {
+                if let Some(session) = self.sessions.get(&username) {
+                    session
+                        .send(ToClientMsg::CanvasSync(self.lines.clone()))
+                        .await?;
+                }
+            }
+            ToServerMsg::TerminalSize(size) => {
+                if let Some(session) = self.sessions.get_mut(&username) {
+                    session.terminal_size = Some(size);
+                }
+            }
+            ToServerMsg::PenMoved(pos) => self.on_pen_moved(&username, pos).await?,
+            ToServerMsg::VoteExtendTime => self.on_extend_vote(&username).await?,
+            ToServerMsg::VoteDrawing(idx) => self.on_drawing_vote(&username, idx).await?,
+            ToServerMsg::Heartbeat => {}
+            ToServerMsg::ListReplays => {
+                if let Some(session) = self.sessions.get(&username) {
+                    let replays = self.replays.iter().rev().map(|(info, _)| info.clone());
+                    session
+                        .send(ToClientMsg::ReplayList(replays.collect()))
+                        .await?;
+                }
+            }
+            ToServerMsg::FetchReplay(id) => {
+                let msg = match self.replays.iter().find(|(info, _)| info.id == id) {
+                    Some((_, drawings)) => ToClientMsg::Replay(id, drawings.clone()),
+                    None => ToClientMsg::NewMessage(Message::SystemMsg(format!(
+                        "There is no game #{}, see !replays",
+                        id
+                    ))),
+                };
+                if let Some(session) = self.sessions.get(&username) {
+                    session.send(msg).await?;
+                }
             }
+            // every client that gets in understands events and envelopes
+            ToServerMsg::EnableEvents => {}
+            ToServerMsg::EnableEnvelopes => {}
         }
         Ok(())
     }
 
     pub async fn on_tick(&mut self) -> Result<()> {
+        let timed_out = self
+            .sessions
+            .values()
+            .filter(|session| session.last_seen.elapsed() > self.heartbeat.timeout)
+            .map(|session| session.username.clone())
+            .collect::<Vec<_>>();
+        for username in timed_out {
+            eprintln!("Disconnecting {}, they stopped responding", username);
+            self.on_user_left(&username).await?;
+        }
+
+        if let Some((username, pos)) = self.pending_pen.take() {
+            self.last_pen_at = None;
+            self.on_pen_moved(&username, Some(pos)).await?;
+        }
+
+        // clients that fell behind missed lines, so they get all of them once they caught up
+        let caught_up = self
+            .sessions
+            .values()
+            .filter(|session| session.take_canvas_sync())
+            .map(|session| session.username.clone())
+            .collect::<Vec<_>>();
+        for username in caught_up {
+            self.send_to(&username, ToClientMsg::CanvasSync(self.lines.clone()))
+                .await?;
+        }
+
+        if self.traffic_checked_at.elapsed() >= TRAFFIC_CHECK_INTERVAL {
+            self.traffic_checked_at = Instant::now();
+            self.check_traffic();
+        }
+
+        if self.last_checksum_at.elapsed() >= CHECKSUM_INTERVAL {
+            self.last_checksum_at = Instant::now();
+            let checksum = data::canvas_checksum(&self.lines);
+            self.broadcast(ToClientMsg::CanvasChecksum(self.lines.len(), checksum))
+                .await?;
+        }
+
+        if let GameState::Starting(start_time) = self.game_state {
+            let remaining = start_time.saturating_duration_since(Instant::now());
+            if remaining > Duration::from_secs(0) {
+                let secs = remaining.as_secs_f64().ceil() as u32;
+                self.broadcast(ToClientMsg::GameStarting(Some(secs)))
+                    .await?;
+            } else if self.sessions.len() >= self.min_players {
+                self.start_game().await?;
+            } else {
+                self.game_state = GameState::FreeDraw;
+                tokio::try_join!(
+                    self.broadcast(ToClientMsg::GameStarting(None)),
+                    self.broadcast_system_msg("Not enough players to start the game".to_string()),
+                )?;
+            }
+            return Ok(());
+        }
+
+        if let GameState::DrawingVote(vote) = &self.game_state {
+            if vote.ends_at <= Instant::now() {
+                self.finish_drawing_vote().await?;
+            }
+            return Ok(());
+        }
+
         let state = match &mut self.game_state {
             GameState::Skribbl(state) => state,
             _ => return Ok(()),
         };
 
         let remaining_time = state.remaining_time();
-        let revealed_char_cnt = state.revealed_characters().len();
+        let revealed_char_cnt = state.view.revealed_characters().len();
 
         if remaining_time <= 0 {
-            let old_word = state.current_word().to_string();
-            if let Some(ref mut drawing_user) = state.player_states.get_mut(&state.drawing_user) {
+            let old_word = state.view.current_word().to_string();
+            if let Some(ref mut drawing_user) =
+                state.view.player_states.get_mut(&state.view.drawing_user)
+            {
                 drawing_user.score += 50;
             }
 
-            state.next_turn();
-            let state = self.game_state.skribbl_state().unwrap().clone();
+            let reveal = GameEvent::WordRevealed {
+                word: old_word.clone(),
+                definition: state.current_definition().map(str::to_string),
+                solve_times: state.view.solve_times.clone(),
+            };
+            self.drawings.push(Drawing::new(
+                state.view.drawing_user.clone(),
+                old_word,
+                &self.lines,
+                self.dimensions,
+            ));
+            state.next_turn(&mut self.rng);
             self.lines.clear();
             tokio::try_join!(
-                self.broadcast(ToClientMsg::SkribblStateChanged(state)),
+                self.broadcast_skribbl_state(),
                 self.broadcast(ToClientMsg::ClearCanvas),
-                self.broadcast_system_msg(format!("The word was: \"{}\"", old_word)),
+                self.broadcast_event(reveal),
             )?;
-        } else if remaining_time <= (ROUND_DURATION / 4) as u32 && revealed_char_cnt < 2
-            || remaining_time <= (ROUND_DURATION / 2) as u32 && revealed_char_cnt < 1
+        } else if remaining_time <= (state.round_duration / 4) as u32 && revealed_char_cnt < 2
+            || remaining_time <= (state.round_duration / 2) as u32 && revealed_char_cnt < 1
         {
-            state.reveal_random_char();
-            let state = state.clone();
-            self.broadcast(ToClientMsg::SkribblStateChanged(state))
-                .await?;
+            state.reveal_random_char(&mut self.rng);
+            self.broadcast_skribbl_state().await?;
         }
 
         self.broadcast(ToClientMsg::TimeChanged(remaining_time as u32))
@@ -276,22 +1651,62 @@ impl ServerState {
     pub async fn on_user_joined(&mut self, session: UserSession) -> Result<()> {
         if let GameState::Skribbl(ref mut state) = self.game_state {
             state.add_player(session.username.clone());
-            let state = state.clone();
             tokio::try_join!(
-                self.broadcast(ToClientMsg::SkribblStateChanged(state)),
-                self.broadcast_system_msg(format!("{} joined", session.username)),
+                self.broadcast_skribbl_state(),
+                self.broadcast_event(GameEvent::Joined(session.username.clone())),
             )?;
         }
 
+        if self.previous_leader.as_ref() == Some(&session.username) {
+            self.previous_leader = None;
+            self.leader = Some(session.username.clone());
+            self.broadcast(ToClientMsg::LeaderChanged(session.username.clone()))
+                .await?;
+        } else if self.leader.is_none() {
+            self.leader = Some(session.username.clone());
+        }
+
         let initial_state = InitialState {
+            leader: self.leader.clone(),
             lines: self.lines.clone(),
-            skribbl_state: self.game_state.skribbl_state().cloned(),
+            skribbl_state: self
+                .game_state
+                .skribbl_state()
+                .map(|state| state.view_for(&session.username)),
+            heartbeat: self.heartbeat,
             dimensions: self.dimensions,
+            envelopes: true,
         };
         session
             .send(ToClientMsg::InitialState(initial_state))
             .await?;
+        let username = session.username.clone();
         self.sessions.insert(session.username.clone(), session);
+
+        let is_waiting = match &self.game_state {
+            GameState::Skribbl(state) => state.view.is_waiting(&username),
+            _ => false,
+        };
+        if is_waiting {
+            let msg = Message::SystemMsg(
+                "A game is running, you'll join it when the next round begins. \
+                 Until then, only other spectators see your messages"
+                    .to_string(),
+            );
+            self.send_to(&username, ToClientMsg::NewMessage(msg))
+                .await?;
+        }
+
+        // give everyone that is still joining a chance to be part of the first round
+        if let GameState::Starting(_) = self.game_state {
+            self.game_state = GameState::Starting(Instant::now() + self.start_countdown);
+            self.broadcast_system_msg(format!(
+                "{} joined, the game starts in {}s",
+                username,
+                self.start_countdown.as_secs()
+            ))
+            .await?;
+        }
         Ok(())
     }
 
@@ -302,94 +1717,625 @@ impl ServerState {
         Ok(())
     }
 
-    /// send a ToClientMsg to a specific session
+    /// send a game event to all active sessions
+    async fn broadcast_event(&self, event: GameEvent) -> Result<()> {
+        self.broadcast(ToClientMsg::NewMessage(Message::Event(event)))
+            .await
+    }
+
+    /// send a game event to a specific session
+    async fn send_event_to(&self, user: &Username, event: GameEvent) -> Result<()> {
+        self.send_to(user, ToClientMsg::NewMessage(Message::Event(event)))
+            .await
+    }
+
+    /// send a ToClientMsg to a specific session. Players that already left are skipped.
     pub async fn send_to(&self, user: &Username, msg: ToClientMsg) -> Result<()> {
-        self.sessions
-            .get(user)
-            .ok_or(ServerError::UserNotFound(user.to_string()))?
-            .send(msg)
-            .await?;
+        if let Some(session) = self.sessions.get(user) {
+            session.send(msg).await?;
+        }
         Ok(())
     }
 
     /// broadcast a ToClientMsg to all running sessions
     async fn broadcast(&self, msg: ToClientMsg) -> Result<()> {
+        self.broadcast_serialized(&SerializedMsg::new(&msg)).await
+    }
+
+    /// send a ToClientMsg to the sessions of the given players, skipping those that left
+    async fn broadcast_to(&self, usernames: &[Username], msg: ToClientMsg) -> Result<()> {
+        let msg = SerializedMsg::new(&msg);
         futures_util::future::try_join_all(
-            self.sessions
+            usernames
                 .iter()
-                .map(|(_, session)| session.send(msg.clone())),
+                .filter_map(|username| self.sessions.get(username))
+                .map(|session| session.send_serialized(&msg)),
         )
         .await?;
         Ok(())
     }
 
-    /// run the main server, reacting to any server events
-    async fn run(&mut self, mut evt_recv: tokio::sync::mpsc::Receiver<ServerEvent>) -> Result<()> {
+    async fn broadcast_serialized(&self, msg: &SerializedMsg) -> Result<()> {
+        futures_util::future::try_join_all(
+            self.sessions
+                .values()
+                .map(|session| session.send_serialized(msg)),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// broadcast the current skribbl state, if a game is running. Only players that know the word
+    /// get to see it, everyone else gets a redacted copy, which is the same for all of them.
+    /// The full state is serialized straight from the game, instead of cloning it into a message.
+    async fn broadcast_skribbl_state(&self) -> Result<()> {
+        let state = match self.game_state.skribbl_state() {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+        let full = SerializedMsg::from_serializable(
+            &SkribblStateChangedRef::SkribblStateChanged(&state.view),
+            false,
+        );
+        let redacted = SerializedMsg::new(&ToClientMsg::SkribblStateChanged(state.view.redacted()));
+        futures_util::future::try_join_all(self.sessions.iter().map(|(username, session)| {
+            if state.view.knows_word(username) {
+                session.send_serialized(&full)
+            } else {
+                session.send_serialized(&redacted)
+            }
+        }))
+        .await?;
+        Ok(())
+    }
+
+    /// send a new line to all players, confirming it to the player that drew it
+    async fn broadcast_line(
+        &self,
+        drawn_by: &Username,
+        index: usize,
+        line: data::Line,
+    ) -> Result<()> {
+        let accepted = SerializedMsg::new(&ToClientMsg::LineAccepted(index, line));
+        let new_line = SerializedMsg::new(&ToClientMsg::NewLine(index, line));
+        futures_util::future::try_join_all(self.sessions.iter().map(|(username, session)| {
+            if username == drawn_by {
+                session.send_serialized(&accepted)
+            } else {
+                session.send_serialized(&new_line)
+            }
+        }))
+        .await?;
+        Ok(())
+    }
+
+    /// run the main server, reacting to any server events.
+    /// An event that can't be handled is logged, and the game goes on with the next one.
+    async fn run(&mut self, mut evt_recv: tokio::sync::mpsc::Receiver<ServerEvent>) {
         loop {
             if let Some(evt) = evt_recv.recv().await {
-                match evt {
+                let result = match evt {
                     ServerEvent::ToServerMsg(name, msg) => {
-                        self.on_to_srv_msg(name.into(), msg).await?
+                        self.on_to_srv_msg(name.into(), msg).await
+                    }
+                    ServerEvent::UserJoined(session) => self.on_user_joined(session).await,
+                    ServerEvent::UserLeft(username) => self.on_user_left(&username).await,
+                    ServerEvent::Tick => self.on_tick().await,
+                    ServerEvent::ReloadWords => {
+                        self.reload_words();
+                        Ok(())
+                    }
+                    ServerEvent::Status(reply) => {
+                        let _ = reply.send(self.status());
+                        Ok(())
+                    }
+                    ServerEvent::Snapshot(reply) => {
+                        let _ = reply.send(self.snapshot());
+                        Ok(())
                     }
-                    ServerEvent::UserJoined(session) => self.on_user_joined(session).await?,
-                    ServerEvent::UserLeft(username) => self.remove_player(&username).await?,
-                    ServerEvent::Tick => self.on_tick().await?,
+                };
+                if let Err(err) = result {
+                    eprintln!("Could not handle an event: {}", err);
                 }
             }
         }
     }
 }
 
+/// settings of the server that don't change while it runs
+#[derive(Debug, Clone)]
+pub struct ServerOpts {
+    /// how many messages can be queued for a client before it counts as too slow
+    pub queue_size: usize,
+    pub seed: Option<u64>,
+    /// how long to wait for more players before starting a game
+    pub start_countdown: Duration,
+    /// how many players are needed to start a game. Games with fewer players left end early.
+    pub min_players: usize,
+    /// how many seconds turns last, until the leader chooses otherwise
+    pub round_duration: u64,
+    pub heartbeat: Heartbeat,
+    /// answer discovery requests from clients in the local network
+    pub lan_discovery: bool,
+    /// serve a status page over HTTP on this port
+    pub status_port: Option<u16>,
+    /// write the stats of every finished game into this directory
+    pub stats_dir: Option<PathBuf>,
+    /// file with words to remove from the word files
+    pub blocked_words: Option<PathBuf>,
+    /// file to save the lobby settings to when the server stops, and to read them from when it starts
+    pub state_file: Option<PathBuf>,
+}
+
 pub async fn run_server(
-    addr: &str,
+    addrs: Vec<SocketAddr>,
     dimensions: (usize, usize),
-    word_file: Option<PathBuf>,
+    word_files: Vec<PathBuf>,
+    opts: ServerOpts,
 ) -> Result<()> {
-    let mut server_listener = TcpListener::bind(addr)
-        .await
-        .expect("Could not start webserver (could not bind)");
+    // on most systems, listening on [::] accepts IPv4 connections too, which takes 0.0.0.0
+    let binds_both_unspecified = addrs.iter().any(|addr| addr.ip() == Ipv6Addr::UNSPECIFIED)
+        && addrs.iter().any(|addr| addr.ip() == Ipv4Addr::UNSPECIFIED);
+    let mut listeners = Vec::new();
+    for addr in addrs {
+        let listener = match TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                let hint = if err.kind() == std::io::ErrorKind::AddrInUse && binds_both_unspecified
+                {
+                    Some(
+                        "Listening on [::] accepts IPv4 connections as well on this system, \
+                         so --bind 0.0.0.0 isn't needed",
+                    )
+                } else {
+                    None
+                };
+                return Err(ServerError::Bind(addr, err, hint));
+            }
+        };
+        println!("Listening on {}", addr);
+        listeners.push(listener);
+    }
+
+    let maybe_words = if word_files.is_empty() {
+        None
+    } else {
+        let blocked = words::read_blocked_words(opts.blocked_words.as_deref())
+            .map_err(ServerError::BlockedWords)?;
+        let list = WordList::load(&word_files, &blocked).map_err(ServerError::WordFiles)?;
+        println!("{}", list.stats);
+        Some(list.words)
+    };
 
-    let maybe_words = word_file.map(|path| read_words_file(&path).unwrap());
+    serve(
+        listeners,
+        dimensions,
+        maybe_words,
+        word_files,
+        opts,
+        shutdown_signal(),
+    )
+    .await
+}
 
-    let (srv_event_send, srv_event_recv) = tokio::sync::mpsc::channel::<ServerEvent>(1);
-    let mut server_state = ServerState::new(GameState::FreeDraw, dimensions, maybe_words);
+/// wait until the server should stop, on Ctrl-C or on SIGTERM, which is how containers get stopped
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+/// check that a server is running at `addr`, and with a `status_port`, that its game still answers.
+/// Servers listening on all interfaces are checked through the loopback interface.
+pub async fn health_check(
+    addr: SocketAddr,
+    status_port: Option<u16>,
+) -> std::result::Result<(), String> {
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    let addr = SocketAddr::new(ip, addr.port());
+    match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, TcpStream::connect(addr)).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(err)) => return Err(format!("Could not connect to {}: {}", addr, err)),
+        Err(_) => return Err(format!("{} didn't answer in time", addr)),
+    }
+    if let Some(status_port) = status_port {
+        let url = format!("http://{}/health", SocketAddr::new(ip, status_port));
+        match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, reqwest::get(&url)).await {
+            Ok(Ok(response)) if response.status().is_success() => {}
+            Ok(Ok(response)) => return Err(format!("{} answered {}", url, response.status())),
+            Ok(Err(err)) => return Err(format!("Could not get {}: {}", url, err)),
+            Err(_) => return Err(format!("{} didn't answer in time", url)),
+        }
+    }
+    Ok(())
+}
+
+/// run the server on listeners that are already bound, until `shutdown` finishes
+pub async fn serve(
+    listeners: Vec<TcpListener>,
+    dimensions: (usize, usize),
+    words: Option<Vec<Word>>,
+    word_files: Vec<PathBuf>,
+    opts: ServerOpts,
+    shutdown: impl std::future::Future<Output = Result<()>>,
+) -> Result<()> {
+    let (mut srv_event_send, srv_event_recv) = tokio::sync::mpsc::channel::<ServerEvent>(1);
+    let mut server_state =
+        ServerState::new(GameState::FreeDraw, dimensions, words, word_files, &opts);
+    if let Some(path) = &opts.state_file {
+        match read_snapshot(path) {
+            Ok(Some(snapshot)) => {
+                println!("Restored the lobby from {}", path.display());
+                server_state.restore(snapshot);
+            }
+            Ok(None) => {}
+            Err(err) => eprintln!(
+                "Could not restore the lobby from {}: {}",
+                path.display(),
+                err
+            ),
+        }
+    }
+    let queue_size = opts.queue_size;
+    let local_addr = match listeners.first() {
+        Some(listener) => listener.local_addr()?,
+        None => return Ok(()),
+    };
 
     tokio::spawn(async move {
-        server_state.run(srv_event_recv).await.unwrap();
+        server_state.run(srv_event_recv).await;
+    });
+
+    tokio::spawn(send_ticks(srv_event_send.clone()));
+    #[cfg(unix)]
+    tokio::spawn(reload_words_on_sighup(srv_event_send.clone()));
+    if opts.lan_discovery {
+        tokio::spawn(answer_discovery_requests(
+            local_addr.port(),
+            srv_event_send.clone(),
+        ));
+    }
+    if let Some(status_port) = opts.status_port {
+        let status_addr = SocketAddr::new(local_addr.ip(), status_port);
+        tokio::spawn(serve_status_page(status_addr, srv_event_send.clone()));
+    }
+
+    let accept_loops = listeners.into_iter().map(|mut listener| {
+        let srv_event_send = srv_event_send.clone();
+        async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let peer = stream.peer_addr().expect("Peer didn't have an address");
+                tokio::spawn(handle_connection(
+                    peer,
+                    stream,
+                    srv_event_send.clone(),
+                    queue_size,
+                    dimensions,
+                ));
+            }
+        }
     });
+    tokio::select! {
+        _ = futures_util::future::join_all(accept_loops) => Ok(()),
+        result = shutdown => {
+            result?;
+            println!("Shutting down");
+            if let Some(path) = &opts.state_file {
+                save_snapshot(path, &mut srv_event_send).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// read the lobby saved by a server that ran before, or `None` if there is none
+fn read_snapshot(path: &Path) -> std::io::Result<Option<LobbySnapshot>> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// write the current lobby to `path`, so the next server started with it can restore it
+async fn save_snapshot(
+    path: &Path,
+    srv_event_send: &mut tokio::sync::mpsc::Sender<ServerEvent>,
+) -> Result<()> {
+    let (snapshot_send, snapshot_recv) = oneshot::channel();
+    srv_event_send
+        .send(ServerEvent::Snapshot(snapshot_send))
+        .await?;
+    let snapshot = match snapshot_recv.await {
+        Ok(snapshot) => snapshot,
+        Err(_) => return Ok(()),
+    };
+    let json = serde_json::to_string_pretty(&snapshot).expect("Could not serialize the lobby");
+    if let Err(err) = std::fs::write(path, json) {
+        eprintln!("Could not save the lobby to {}: {}", path.display(), err);
+    }
+    Ok(())
+}
+
+/// send a tick to the server every `TICK_INTERVAL`.
+/// Ticks only trigger checks, turns end based on their deadline, so late ticks don't shorten them.
+async fn send_ticks(mut srv_event_send: tokio::sync::mpsc::Sender<ServerEvent>) -> Result<()> {
+    let mut interval = tokio::time::interval(TICK_INTERVAL);
+    loop {
+        interval.tick().await;
+        srv_event_send.send(ServerEvent::Tick).await?;
+    }
+}
+
+/// ask the server to reload the word file whenever the process receives SIGHUP
+#[cfg(unix)]
+async fn reload_words_on_sighup(
+    mut srv_event_send: tokio::sync::mpsc::Sender<ServerEvent>,
+) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangup = signal(SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        srv_event_send.send(ServerEvent::ReloadWords).await?;
+    }
+    Ok(())
+}
+
+/// answer discovery requests broadcast by clients in the local network with the port
+/// of the server and how many players are on it
+async fn answer_discovery_requests(
+    port: u16,
+    mut srv_event_send: tokio::sync::mpsc::Sender<ServerEvent>,
+) -> Result<()> {
+    let mut socket = match UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT)).await {
+        Ok(socket) => socket,
+        Err(err) => {
+            eprintln!(
+                "Could not listen for LAN discovery requests on port {}: {}",
+                DISCOVERY_PORT, err
+            );
+            return Ok(());
+        }
+    };
+    let mut buf = [0; 64];
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf).await?;
+        if &buf[..len] != DISCOVERY_REQUEST {
+            continue;
+        }
+        let players = match request_status(&mut srv_event_send).await {
+            Some(status) => status.players.len(),
+            None => return Ok(()),
+        };
+        let reply = serde_json::to_vec(&DiscoveryReply { port, players })
+            .expect("Could not serialize discovery reply");
+        // the client might be gone already, which is no reason to stop answering others
+        let _ = socket.send_to(&reply, peer).await;
+    }
+}
 
-    while let Ok((stream, _)) = server_listener.accept().await {
-        let peer = stream.peer_addr().expect("Peer didn't have an address");
-        tokio::spawn(handle_connection(peer, stream, srv_event_send.clone()));
+/// what gets written for every game when the server runs with `--stats-dir`
+#[derive(Serialize)]
+struct GameStats<'a> {
+    /// seconds since the unix epoch
+    finished_at: u64,
+    players: &'a HashMap<Username, PlayerState>,
+}
+
+/// write the final scores and stats of a game into `game-<timestamp>.json` in `dir`
+fn write_game_stats(dir: &Path, state: &SkribblGame) -> std::io::Result<()> {
+    let finished_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let stats = GameStats {
+        finished_at,
+        players: &state.view.player_states,
+    };
+    std::fs::create_dir_all(dir)?;
+    let json = serde_json::to_string_pretty(&stats).expect("Could not serialize the stats");
+    std::fs::write(dir.join(format!("game-{}.json", finished_at)), json)
+}
+
+/// ask the server for its status, returning `None` if it stopped
+async fn request_status(
+    srv_event_send: &mut tokio::sync::mpsc::Sender<ServerEvent>,
+) -> Option<ServerStatus> {
+    let (status_send, status_recv) = oneshot::channel();
+    srv_event_send
+        .send(ServerEvent::Status(status_send))
+        .await
+        .ok()?;
+    status_recv.await.ok()
+}
+
+/// serve the status of the server as JSON at `/status.json`, and as a small HTML page at `/`,
+/// so it can be shown on websites. `/health` answers whether the game is still running.
+async fn serve_status_page(
+    addr: SocketAddr,
+    srv_event_send: tokio::sync::mpsc::Sender<ServerEvent>,
+) -> Result<()> {
+    let mut listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Could not serve the status page on {}: {}", addr, err);
+            return Ok(());
+        }
+    };
+    println!("Serving the status page on http://{}", addr);
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(answer_status_request(stream, srv_event_send.clone()));
     }
     Ok(())
 }
 
+async fn answer_status_request(
+    mut stream: TcpStream,
+    mut srv_event_send: tokio::sync::mpsc::Sender<ServerEvent>,
+) -> Result<()> {
+    // only the path in the first line of the request matters
+    let mut buf = [0; 1024];
+    let len = match tokio::time::timeout(WRITE_TIMEOUT, stream.read(&mut buf)).await {
+        Ok(len) => len?,
+        Err(_) => return Ok(()),
+    };
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    let status = request_status(&mut srv_event_send).await;
+    let (code, content_type, body) = match (path, status) {
+        ("/health", Some(_)) => ("200 OK", "text/plain; charset=utf-8", "ok".to_string()),
+        ("/health", None) => (
+            "503 Service Unavailable",
+            "text/plain; charset=utf-8",
+            "stopped".to_string(),
+        ),
+        (_, None) => return Ok(()),
+        ("/status.json", Some(status)) => (
+            "200 OK",
+            "application/json",
+            serde_json::to_string(&status).expect("Could not serialize the status"),
+        ),
+        ("/", Some(status)) => ("200 OK", "text/html; charset=utf-8", status_html(&status)),
+        _ => (
+            "404 Not Found",
+            "text/plain; charset=utf-8",
+            "not found".to_string(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{}",
+        code,
+        content_type,
+        body.len(),
+        body
+    );
+    let _ = tokio::time::timeout(WRITE_TIMEOUT, stream.write_all(response.as_bytes())).await;
+    Ok(())
+}
+
+fn status_html(status: &ServerStatus) -> String {
+    let players: String = status
+        .players
+        .iter()
+        .map(|player| format!("<li>{}</li>", escape_html(&player.to_string())))
+        .collect();
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Termibbl server</title></head>\n\
+         <body><h1>Termibbl server</h1>\n\
+         <p>Version {}, up for {} minutes, {}</p>\n\
+         <h2>{} players</h2><ul>{}</ul></body></html>\n",
+        status.version,
+        status.uptime_secs / 60,
+        status.game,
+        status.players.len(),
+        players
+    )
+}
+
+/// whether the canvas can have these dimensions, which are within `MIN_DIMENSIONS` and `MAX_DIMENSIONS`
+fn valid_dimensions((width, height): (usize, usize)) -> bool {
+    (MIN_DIMENSIONS.0..=MAX_DIMENSIONS.0).contains(&width)
+        && (MIN_DIMENSIONS.1..=MAX_DIMENSIONS.1).contains(&height)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 async fn handle_connection(
     peer: SocketAddr,
     stream: TcpStream,
     mut srv_event_send: tokio::sync::mpsc::Sender<ServerEvent>,
+    queue_size: usize,
+    dimensions: (usize, usize),
 ) -> Result<()> {
-    let ws_stream = tokio_tungstenite::accept_async(stream).await?;
+    let mut protocol_version = None;
+    let read_protocol_version =
+        |request: &Request, response: Response| -> std::result::Result<Response, ErrorResponse> {
+            protocol_version = request
+                .headers()
+                .get(message::PROTOCOL_HEADER)
+                .and_then(|version| version.to_str().ok())
+                .and_then(|version| version.parse::<u32>().ok());
+            Ok(response)
+        };
+    let ws_stream = tokio_tungstenite::accept_hdr_async(stream, read_protocol_version).await?;
     println!("new WebSocket connection: {}", peer);
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
     // first, wait for the client to send his username
-    let username: Username = loop {
+    let username = loop {
         let msg = ws_receiver
             .next()
             .await
             .expect("No username message received")?;
         if let tungstenite::Message::Text(username) = msg {
-            break username.into();
+            break username;
+        }
+    };
+
+    // clients from before the version was sent can't decode most of what the server sends,
+    // but they understand an empty initial state and a chat message, which tells them why
+    if protocol_version.unwrap_or(0) < message::PROTOCOL_VERSION {
+        println!("Turning away {}, their client is outdated", peer);
+        let initial_state = InitialState {
+            leader: None,
+            lines: Vec::new(),
+            dimensions,
+            skribbl_state: None,
+            heartbeat: Heartbeat::default(),
+            envelopes: false,
+        };
+        let notice = Message::SystemMsg(OUTDATED_CLIENT_NOTICE.to_string());
+        for msg in &[
+            ToClientMsg::InitialState(initial_state),
+            ToClientMsg::NewMessage(notice),
+        ] {
+            let msg = message::encode(msg);
+            ws_sender.send(tungstenite::Message::Text(msg)).await?;
+        }
+        ws_sender.send(tungstenite::Message::Close(None)).await?;
+        return Ok(());
+    }
+
+    let username: Username = match data::sanitize_username(&username) {
+        Ok(username) => username,
+        Err(reason) => {
+            let msg = message::encode(&ToClientMsg::UsernameRejected(reason.to_string()));
+            ws_sender.send(tungstenite::Message::Text(msg)).await?;
+            ws_sender.send(tungstenite::Message::Close(None)).await?;
+            return Ok(());
         }
     };
 
-    let (session_msg_send, mut session_msg_recv) = tokio::sync::mpsc::channel(1);
+    let (session_msg_send, mut session_msg_recv) = tokio::sync::mpsc::channel(queue_size);
     let (session_close_send, mut session_close_recv) = tokio::sync::mpsc::channel(1);
 
     // then, create a session and send that session to the server's main thread
-    let session = UserSession::new(username.clone(), session_msg_send, session_close_send);
+    let traffic = Arc::new(Traffic::default());
+    let session = UserSession::new(
+        username.clone(),
+        session_msg_send,
+        session_close_send,
+        traffic.clone(),
+        (queue_size / BACKLOG_SHARE).max(1) as u64,
+    );
     srv_event_send
         .send(ServerEvent::UserJoined(session))
         .await?;
@@ -397,15 +2343,22 @@ async fn handle_connection(
     // TODO look at stream forwarding for this...
     // asynchronously read messages that the main server thread wants
     // to send to this client and forward them to the WS client
-    let send_thread = tokio::spawn(async move {
+    let send_username = username.clone();
+    let send_traffic = traffic.clone();
+    let mut send_thread = tokio::spawn(async move {
         loop {
             tokio::select! {
                 maybe_msg = session_msg_recv.recv() => match maybe_msg {
                     Some(msg) => {
-                        let msg = serde_json::to_string(&msg).expect("Could not serialize msg");
-                        let result = ws_sender.send(tungstenite::Message::Text(msg)).await;
-                        if let Err(_) = result {
-                            break result;
+                        send_traffic.record_sent(msg.len());
+                        let msg = tungstenite::Message::Text(msg.to_string());
+                        match tokio::time::timeout(WRITE_TIMEOUT, ws_sender.send(msg)).await {
+                            Ok(Ok(())) => {}
+                            Ok(Err(err)) => break Err(err),
+                            Err(_) => {
+                                eprintln!("Disconnecting {}, writing to them stalled", send_username);
+                                break Ok(());
+                            }
                         }
                     }
                     // if the msg received is None, all senders have been closed, so we can finish
@@ -423,31 +2376,34 @@ async fn handle_connection(
     });
 
     // TODO look at stream forwarding for this
-    // forward other events to the main server thread
+    // forward other events to the main server thread,
+    // until the client disconnects or writing to it failed
     loop {
-        let delay = Delay::new(Duration::from_millis(500));
-        tokio::select! {
-            // every 100ms, send a tick event to the main server thread
-            _ = delay => srv_event_send.send(ServerEvent::Tick).await?,
-
-            // Websocket messages from the client
-            msg = ws_receiver.next() => match msg {
-                Some(Ok(tungstenite::Message::Text(msg))) => match serde_json::from_str(&msg) {
-                    Ok(Some(msg)) => {
+        let msg = tokio::select! {
+            msg = ws_receiver.next() => msg,
+            _ = &mut send_thread => break,
+        };
+        match msg {
+            Some(Ok(tungstenite::Message::Text(msg))) => {
+                traffic.record_received(msg.len());
+                match message::decode(&msg) {
+                    Ok(Some(Some(msg))) => {
                         srv_event_send
                             .send(ServerEvent::ToServerMsg(username.clone(), msg))
                             .await?;
                     }
-                    Ok(None) => {
+                    Ok(Some(None)) => {
                         break;
                     }
+                    // sent by a newer client, which can do without the answer
+                    Ok(None) => {}
                     Err(err) => {
                         eprintln!("{} (msg was: {})", err, msg);
                     }
-                },
-                Some(Ok(tungstenite::Message::Close(_))) | Some(Err(_)) | None => break,
-                _ => {}
+                }
             }
+            Some(Ok(tungstenite::Message::Close(_))) | Some(Err(_)) | None => break,
+            _ => {}
         }
     }
 
@@ -456,47 +2412,29 @@ async fn handle_connection(
     Ok(())
 }
 
-pub fn read_words_file(path: &PathBuf) -> Result<Vec<String>> {
-    let mut file = std::fs::File::open(path)?;
-    let mut words = String::new();
-    file.read_to_string(&mut words)?;
-    Ok(words
-        .lines()
-        .map(|x| x.trim().to_string())
-        .filter(|x| !x.is_empty())
-        .collect::<Vec<String>>())
-}
-
-fn is_very_close_to(a: String, b: String) -> bool {
-    return levenshtein_distance(a, b) <= 1;
-}
-
-fn levenshtein_distance(a: String, b: String) -> usize {
-    let w1 = a.chars().collect::<Vec<_>>();
-    let w2 = b.chars().collect::<Vec<_>>();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let a_len = w1.len() + 1;
-    let b_len = w2.len() + 1;
+    #[tokio::test]
+    async fn health_fails_once_the_game_stopped() {
+        let (srv_event_send, srv_event_recv) = tokio::sync::mpsc::channel(1);
+        drop(srv_event_recv);
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            answer_status_request(stream, srv_event_send).await
+        });
 
-    let mut matrix = vec![vec![0]];
-
-    for i in 1..a_len {
-        matrix[0].push(i);
-    }
-    for j in 1..b_len {
-        matrix.push(vec![j]);
-    }
-
-    for (j, i) in (1..b_len).flat_map(|j| (1..a_len).map(move |i| (j, i))) {
-        let x: usize = if w1[i - 1].eq_ignore_ascii_case(&w2[j - 1]) {
-            matrix[j - 1][i - 1]
-        } else {
-            1 + min(
-                min(matrix[j][i - 1], matrix[j - 1][i]),
-                matrix[j - 1][i - 1],
-            )
-        };
-        matrix[j].push(x);
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable"));
+        assert!(response.ends_with("stopped"));
     }
-    matrix[b_len - 1][a_len - 1]
 }
@@ -1,87 +1,173 @@
-use super::server::ROUND_DURATION;
-use crate::client::Username;
-use rand::{prelude::IteratorRandom, seq::SliceRandom};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::{cmp::max, time};
-use time::{SystemTime, UNIX_EPOCH};
+use super::words::Word;
+use crate::{
+    data::{Difficulty, Line, TurnRotation, Username},
+    game::{PlayerState, SkribblState},
+};
+use rand::{seq::SliceRandom, Rng};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SkribblState {
-    current_word: String,
-    revealed_characters: Vec<usize>,
+/// how long a player that left the game can take to rejoin without losing their score
+pub const REJOIN_GRACE_PERIOD: Duration = Duration::from_secs(120);
 
-    /// the currently drawing user
-    pub drawing_user: Username,
+/// a running game: what the players get to see of it, together with what only the server knows
+#[derive(Debug, Clone)]
+pub struct SkribblGame {
+    /// the state shared with the players, see `view_for`
+    pub view: SkribblState,
 
-    /// players which didn't draw yet in the current round.
-    pub remaining_users: Vec<Username>,
+    /// how many seconds every turn lasts
+    pub round_duration: u64,
 
-    /// states of all the players
-    pub player_states: HashMap<Username, PlayerState>,
+    /// point in time at which the current turn ends.
+    /// Clients get the remaining time sent as a duration instead.
+    pub round_end_time: Instant,
 
-    pub round_end_time: u64,
+    /// when the current turn started, to tell how long players took to guess the word
+    turn_started_at: Instant,
 
-    pub remaining_words: Vec<String>,
+    /// guessers that voted to give the drawing player more time in the current turn
+    extend_votes: HashSet<Username>,
+
+    /// whether the current turn was already extended, which only happens once per turn
+    time_extended: bool,
+
+    /// the definition of the current word, revealed together with it at the end of the turn
+    current_definition: Option<String>,
+
+    /// the words that will come up next. Clients never get these, they would give away the words.
+    pub remaining_words: Vec<Word>,
+
+    /// words that were already drawn in this game, including the current one.
+    /// They only come up again once all the other words were used.
+    used_words: Vec<Word>,
+
+    /// players that recently left the game, kept so they can rejoin without losing their progress.
+    departed_players: HashMap<Username, DepartedPlayer>,
+
+    /// counts up whenever everyone had their turn and the drawing order starts over
+    round: u32,
+}
+
+#[derive(Debug, Clone)]
+struct DepartedPlayer {
+    state: PlayerState,
+    left_at: Instant,
+    /// the round the player left in
+    left_in_round: u32,
+    /// whether the player still had to draw in that round when they left
+    was_remaining: bool,
 }
 
-impl SkribblState {
-    pub fn current_word(&self) -> &str {
-        &self.current_word
+impl SkribblGame {
+    pub fn current_definition(&self) -> Option<&str> {
+        self.current_definition.as_deref()
     }
 
-    pub fn revealed_characters(&self) -> &[usize] {
-        self.revealed_characters.as_ref()
+    pub fn set_current_word(&mut self, word: Word) {
+        self.view.current_word = word.text;
+        self.current_definition = word.definition;
+        self.view.difficulty = word.difficulty;
+        self.view.revealed_characters = Vec::new();
     }
 
-    pub fn set_current_word(&mut self, word: String) {
-        self.current_word = word;
-        self.revealed_characters = Vec::new();
+    /// the positions of the characters that have to be guessed, grouped by the words of the phrase.
+    /// Spaces and punctuation like in "ice-cream" are shown to everyone.
+    fn letters_by_word(&self) -> Vec<Vec<usize>> {
+        let mut words = vec![Vec::new()];
+        for (idx, c) in self.view.current_word.chars().enumerate() {
+            if c.is_whitespace() {
+                words.push(Vec::new());
+            } else if c.is_alphanumeric() {
+                words.last_mut().unwrap().push(idx);
+            }
+        }
+        words.retain(|letters| !letters.is_empty());
+        words
     }
 
-    /// reveals a random character, as long as that doesn't reveal half of the word
-    pub fn reveal_random_char(&mut self) {
-        if self.revealed_characters.len() < self.current_word.len() / 2 {
-            let mut rng = rand::thread_rng();
-            self.revealed_characters
-                .push((0..self.current_word.len()).choose(&mut rng).unwrap());
+    /// reveals a random letter, as long as that doesn't reveal half of the letters.
+    /// The letter is taken from the word with the least of its letters revealed,
+    /// so every word of a phrase gets hints.
+    pub fn reveal_random_char(&mut self, rng: &mut impl Rng) {
+        let words = self.letters_by_word();
+        let letter_count: usize = words.iter().map(|letters| letters.len()).sum();
+        if self.view.revealed_characters.len() >= letter_count / 2 {
+            return;
+        }
+        let revealed = &self.view.revealed_characters;
+        let hidden_letters = |letters: &[usize]| {
+            letters
+                .iter()
+                .filter(|idx| !revealed.contains(idx))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        // compare how much of each word is revealed, as a fraction of its length
+        let least_revealed = words
+            .iter()
+            .filter(|letters| !hidden_letters(letters).is_empty())
+            .min_by_key(|letters| {
+                let revealed_count = letters.len() - hidden_letters(letters).len();
+                revealed_count * letter_count / letters.len()
+            });
+        if let Some(letters) = least_revealed {
+            if let Some(&idx) = hidden_letters(letters).choose(rng) {
+                self.view.revealed_characters.push(idx);
+            }
         }
     }
 
-    /// returns the placeholder chars for the current word, with the revealed characters revealed.
-    pub fn hinted_current_word(&self) -> String {
-        self.current_word
-            .chars()
-            .enumerate()
-            .map(|(idx, c)| {
-                if self.revealed_characters.contains(&(idx as usize)) {
-                    c
-                } else if c.is_whitespace() {
-                    ' '
-                } else {
-                    '?'
-                }
-            })
-            .collect()
+    /// the state as the given player may see it
+    pub fn view_for(&self, username: &Username) -> SkribblState {
+        if self.view.knows_word(username) {
+            self.view.clone()
+        } else {
+            self.view.redacted()
+        }
     }
 
     pub fn remaining_time(&self) -> u32 {
-        max(0, self.round_end_time as i64 - get_time_now() as i64) as u32
+        self.round_end_time
+            .saturating_duration_since(Instant::now())
+            .as_secs() as u32
     }
 
     pub fn did_all_solve(&self) -> bool {
-        self.player_states
+        self.view
+            .player_states
             .iter()
-            .all(|(username, player)| player.has_solved || username == &self.drawing_user)
+            .all(|(username, player)| player.has_solved || username == &self.view.drawing_user)
+    }
+
+    /// remember that a player guessed the word just now
+    pub fn record_solve(&mut self, username: Username) {
+        let secs = self.turn_started_at.elapsed().as_secs() as u32;
+        if let Some(player) = self.view.player_states.get_mut(&username) {
+            player.stats.words_guessed += 1;
+            player.stats.total_solve_secs += secs;
+        }
+        self.view.solve_times.push((username, secs));
     }
 
-    pub fn has_solved(&self, username: &Username) -> bool {
-        self.player_states.get(username).map(|x| x.has_solved) == Some(true)
+    /// count a chat message of a player that could still guess the word as a guess
+    pub fn record_guess(&mut self, username: &Username) {
+        if let Some(player) = self.view.player_states.get_mut(username) {
+            player.stats.guesses += 1;
+        }
+    }
+
+    /// count the points of a line the drawing player drew
+    pub fn record_line(&mut self, line: &Line) {
+        let view = &mut self.view;
+        if let Some(player) = view.player_states.get_mut(&view.drawing_user) {
+            player.stats.pixels_drawn += line.point_count() as u64;
+        }
     }
 
     pub fn remove_user(&mut self, username: &Username) {
-        self.player_states.remove(username);
         let left_player_idx = self
+            .view
             .remaining_users
             .iter()
             .enumerate()
@@ -89,100 +175,317 @@ impl SkribblState {
             .map(|x| x.0);
         // TODO check if idx = 0
         if let Some(idx) = left_player_idx {
-            self.remaining_users.remove(idx);
+            self.view.remaining_users.remove(idx);
+        }
+        self.view
+            .waiting_players
+            .retain(|waiting| waiting != username);
+        if let Some(state) = self.view.player_states.remove(username) {
+            self.departed_players.insert(
+                username.clone(),
+                DepartedPlayer {
+                    state,
+                    left_at: Instant::now(),
+                    left_in_round: self.round,
+                    was_remaining: left_player_idx.is_some(),
+                },
+            );
         }
     }
 
+    /// add a player to the game. Players that left less than `REJOIN_GRACE_PERIOD` ago
+    /// get their score back, and get to draw this round unless they already drew in it.
+    /// If they already guessed the word of the current turn, they can't guess it again.
+    /// New players wait for the next round.
     pub fn add_player(&mut self, username: Username) {
-        if !self.player_states.contains_key(&username) {
-            self.remaining_users.push(username.clone());
-            self.player_states.insert(username, PlayerState::default());
-        }
-    }
+        self.departed_players
+            .retain(|_, departed| departed.left_at.elapsed() < REJOIN_GRACE_PERIOD);
 
-    pub fn is_drawing(&self, username: &Username) -> bool {
-        self.drawing_user == *username
-    }
-    pub fn can_guess(&self, username: &Username) -> bool {
-        !self.is_drawing(username)
-            && !self
-                .player_states
-                .get(username)
-                .map(|x| x.has_solved)
-                .unwrap_or(false)
+        if self.view.player_states.contains_key(&username) || self.view.is_waiting(&username) {
+            return;
+        }
+        match self.departed_players.remove(&username) {
+            Some(departed) => {
+                // a round that started after they left didn't include them yet
+                if departed.was_remaining || departed.left_in_round != self.round {
+                    self.view.remaining_users.push(username.clone());
+                }
+                self.view.player_states.insert(username, departed.state);
+            }
+            None => self.view.waiting_players.push(username),
+        }
     }
 
-    pub fn next_turn(&mut self) -> &Username {
+    pub fn next_turn(&mut self, rng: &mut impl Rng) -> &Username {
         let remaining_time = self.remaining_time();
-        self.player_states
-            .get_mut(&self.drawing_user)
-            .map(|drawing_user| {
-                drawing_user.score += 50;
-                drawing_user.on_solve(remaining_time);
-            });
+        let round_duration = self.round_duration;
+        let view = &mut self.view;
+        let difficulty = view.difficulty;
+        if let Some(drawing_user) = view.player_states.get_mut(&view.drawing_user) {
+            drawing_user.score += 50;
+            reward_solve(drawing_user, remaining_time, round_duration, difficulty);
+            drawing_user.stats.words_drawn += 1;
+        }
+        self.skip_turn(rng)
+    }
 
-        let new_word = self.remaining_words.remove(0);
+    /// go on with the next turn without giving the drawing player any points for this one
+    pub fn skip_turn(&mut self, rng: &mut impl Rng) -> &Username {
+        let new_word = self.next_word(rng);
         self.set_current_word(new_word);
-        self.round_end_time = get_time_now() + ROUND_DURATION;
-        if self.remaining_users.len() == 0 {
-            self.remaining_users = self.player_states.keys().cloned().collect();
+        self.round_end_time = Instant::now() + Duration::from_secs(self.round_duration);
+        self.turn_started_at = Instant::now();
+        // without a fixed order, every turn is a good time to let new players in
+        if self.view.rotation != TurnRotation::InOrder || self.view.remaining_users.is_empty() {
+            for username in std::mem::take(&mut self.view.waiting_players) {
+                self.view
+                    .player_states
+                    .insert(username, PlayerState::default());
+            }
         }
-        self.drawing_user = self.remaining_users.remove(0);
-        self.player_states
+        self.view.drawing_user = self.next_drawing_user(rng);
+        self.view.solve_times.clear();
+        self.extend_votes.clear();
+        self.time_extended = false;
+        self.view
+            .player_states
             .iter_mut()
             .for_each(|(_, player)| player.has_solved = false);
-        &self.drawing_user
+        self.departed_players
+            .values_mut()
+            .for_each(|departed| departed.state.has_solved = false);
+        &self.view.drawing_user
     }
 
-    pub fn new(users: Vec<Username>, mut words: Vec<String>) -> Self {
-        let mut rng = rand::thread_rng();
-        words.shuffle(&mut rng);
+    pub fn time_extended(&self) -> bool {
+        self.time_extended
+    }
+
+    /// vote to extend the current turn. Returns whether the vote is new, together with
+    /// how many of the players that are still here voted and how many votes are needed.
+    pub fn vote_extend(&mut self, username: &Username) -> (bool, usize, usize) {
+        let is_new = self.extend_votes.insert(username.clone());
+        let player_states = &self.view.player_states;
+        let votes = self
+            .extend_votes
+            .iter()
+            .filter(|voter| player_states.contains_key(*voter))
+            .count();
+        // a majority of everyone but the drawing player
+        let needed = player_states.len().saturating_sub(1) / 2 + 1;
+        (is_new, votes, needed)
+    }
+
+    /// give the drawing player more time, once per turn
+    pub fn extend_time(&mut self, by: Duration) {
+        self.round_end_time += by;
+        self.time_extended = true;
+    }
+
+    /// pick who draws next, according to the turn rotation
+    fn next_drawing_user(&mut self, rng: &mut impl Rng) -> Username {
+        let view = &mut self.view;
+        // sorted, so the drawing order doesn't depend on the order of the map
+        let mut players = view.player_states.keys().cloned().collect::<Vec<_>>();
+        players.sort();
+        match view.rotation {
+            TurnRotation::Random => {
+                let others = players
+                    .iter()
+                    .filter(|player| **player != view.drawing_user)
+                    .collect::<Vec<_>>();
+                if let Some(player) = others.choose(rng) {
+                    return (*player).clone();
+                }
+            }
+            TurnRotation::Winner => {
+                let winner = view
+                    .solve_times
+                    .first()
+                    .map(|(winner, _)| winner)
+                    .filter(|winner| view.player_states.contains_key(winner));
+                if let Some(winner) = winner {
+                    return winner.clone();
+                }
+            }
+            TurnRotation::InOrder => {}
+        }
+        if view.remaining_users.is_empty() {
+            view.remaining_users = players;
+            self.round += 1;
+        }
+        view.remaining_users.remove(0)
+    }
+
+    /// take the next word to draw. Once all words were used, they are shuffled and reused,
+    /// making sure the word that was just drawn doesn't come up again right away.
+    fn next_word(&mut self, rng: &mut impl Rng) -> Word {
+        if self.remaining_words.is_empty() {
+            self.remaining_words = std::mem::take(&mut self.used_words);
+            self.remaining_words.shuffle(rng);
+            let last_idx = self.remaining_words.len() - 1;
+            if self.remaining_words[0].text == self.view.current_word {
+                self.remaining_words.swap(0, last_idx);
+            }
+        }
+        let word = self.remaining_words.remove(0);
+        self.used_words.push(word.clone());
+        word
+    }
+
+    pub fn new(
+        mut users: Vec<Username>,
+        mut words: Vec<Word>,
+        rotation: TurnRotation,
+        round_duration: u64,
+        rng: &mut impl Rng,
+    ) -> Self {
+        users.sort();
+        words.shuffle(rng);
         let current_word = words.remove(0);
-        let mut state = SkribblState {
-            current_word: current_word.clone(),
+        let view = SkribblState {
+            current_word: current_word.text.clone(),
+            difficulty: current_word.difficulty,
             revealed_characters: Vec::new(),
             drawing_user: users[0].clone(),
             remaining_users: users.iter().cloned().skip(1).collect::<Vec<_>>(),
-            player_states: HashMap::new(),
-            round_end_time: get_time_now() + ROUND_DURATION,
-            remaining_words: words,
+            rotation,
+            player_states: users
+                .into_iter()
+                .map(|user| (user, PlayerState::default()))
+                .collect(),
+            solve_times: Vec::new(),
+            waiting_players: Vec::new(),
         };
-        for user in users {
-            state.player_states.insert(user, PlayerState::default());
+        SkribblGame {
+            view,
+            round_duration,
+            round_end_time: Instant::now() + Duration::from_secs(round_duration),
+            turn_started_at: Instant::now(),
+            extend_votes: HashSet::new(),
+            time_extended: false,
+            current_definition: current_word.definition.clone(),
+            remaining_words: words,
+            used_words: vec![current_word],
+            departed_players: HashMap::new(),
+            round: 0,
         }
-        state
     }
 }
 
-pub fn get_time_now() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
+/// give a player the points for guessing the word with `remaining_time` seconds left
+pub fn reward_solve(
+    player: &mut PlayerState,
+    remaining_time: u32,
+    round_duration: u64,
+    difficulty: Difficulty,
+) {
+    player.score += calculate_score_increase(remaining_time, round_duration, difficulty);
+    player.has_solved = true;
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct PlayerState {
-    pub score: u32,
-    pub has_solved: bool,
+/// the points for guessing a word, more the sooner it was guessed relative to the length of the turn
+pub fn calculate_score_increase(
+    remaining_time: u32,
+    round_duration: u64,
+    difficulty: Difficulty,
+) -> u32 {
+    let score = 50 + (((remaining_time as f64 / round_duration as f64) * 100f64) as u32 / 2u32);
+    score * difficulty.score_percentage() / 100
 }
 
-impl Default for PlayerState {
-    fn default() -> Self {
-        PlayerState {
-            score: 0,
-            has_solved: false,
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn state_with_word(word: &str) -> SkribblGame {
+        let words = vec![Word {
+            text: word.to_string(),
+            difficulty: Difficulty::Medium,
+            language: None,
+            definition: None,
+        }];
+        let users = vec![Username::from("alice".to_string())];
+        let mut rng = StdRng::seed_from_u64(0);
+        SkribblGame::new(users, words, TurnRotation::InOrder, 120, &mut rng)
     }
-}
 
-impl PlayerState {
-    pub fn on_solve(&mut self, remaining_time: u32) {
-        self.score += calculate_score_increase(remaining_time);
-        self.has_solved = true;
+    #[test]
+    fn rejoining_players_only_guess_again_in_the_next_turn() {
+        let mut state = state_with_word("polybar");
+        let bob = Username::from("bob".to_string());
+        let player = PlayerState {
+            has_solved: true,
+            ..PlayerState::default()
+        };
+        state.view.player_states.insert(bob.clone(), player);
+
+        state.remove_user(&bob);
+        state.add_player(bob.clone());
+        assert!(!state.view.can_guess(&bob));
+
+        state.remove_user(&bob);
+        state.skip_turn(&mut StdRng::seed_from_u64(0));
+        state.add_player(bob.clone());
+        assert!(state.view.can_guess(&bob));
     }
-}
 
-pub fn calculate_score_increase(remaining_time: u32) -> u32 {
-    50 + (((remaining_time as f64 / ROUND_DURATION as f64) * 100f64) as u32 / 2u32)
+    #[test]
+    fn rejoining_players_draw_in_a_round_that_started_without_them() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let alice = Username::from("alice".to_string());
+        let bob = Username::from("bob".to_string());
+        let carol = Username::from("carol".to_string());
+        let users = vec![alice.clone(), bob.clone(), carol.clone()];
+        let words = vec![Word {
+            text: "polybar".to_string(),
+            difficulty: Difficulty::Medium,
+            language: None,
+            definition: None,
+        }];
+        let mut state = SkribblGame::new(users, words, TurnRotation::InOrder, 120, &mut rng);
+        assert!(state.view.is_drawing(&alice));
+
+        // alice already drew this round when she leaves, and comes back in the next one
+        state.skip_turn(&mut rng);
+        state.remove_user(&alice);
+        state.skip_turn(&mut rng);
+        assert!(state.view.is_drawing(&carol));
+        state.skip_turn(&mut rng);
+        assert!(state.view.is_drawing(&bob));
+        state.add_player(alice.clone());
+        assert_eq!(state.view.remaining_users, [carol.clone(), alice.clone()]);
+
+        state.skip_turn(&mut rng);
+        assert!(state.view.is_drawing(&carol));
+        state.skip_turn(&mut rng);
+        assert!(state.view.is_drawing(&alice));
+    }
+
+    #[test]
+    fn phrases_keep_their_spaces_and_punctuation() {
+        let state = state_with_word("ice cream-cone");
+        assert_eq!(state.view.hinted_current_word(), "??? ?????-????");
+    }
+
+    #[test]
+    fn hints_are_spread_over_the_words_of_a_phrase() {
+        let mut state = state_with_word("ice cream");
+        let mut rng = StdRng::seed_from_u64(0);
+        state.reveal_random_char(&mut rng);
+        state.reveal_random_char(&mut rng);
+        let hint = state.view.hinted_current_word();
+        let revealed_per_word: Vec<usize> = hint
+            .split(' ')
+            .map(|word| word.chars().filter(|c| *c != '?').count())
+            .collect();
+        assert_eq!(revealed_per_word, [1, 1]);
+
+        // no more than half of the letters get revealed
+        for _ in 0..10 {
+            state.reveal_random_char(&mut rng);
+        }
+        assert_eq!(state.view.revealed_characters().len(), 4);
+    }
 }
@@ -0,0 +1,870 @@
+use crate::{
+    data::{
+        Brush, CanvasColor, Closeness, CommandMsg, Coord, Difficulty, GameEvent, Line, Message,
+        TurnRotation, Username,
+    },
+    game::SkribblState,
+    message::{ToClientMsg, ToServerMsg},
+    server::server::{
+        run_server, LobbySnapshot, ServerError, ServerOpts, BEST_DRAWING_BONUS, MAX_OFFENSES,
+        OUTDATED_CLIENT_NOTICE, ROUND_DURATION,
+    },
+    testing::{TempFile, TestClient, TestServer},
+};
+use std::io::ErrorKind;
+
+fn line() -> Line {
+    Line {
+        start: Coord(1, 1),
+        end: Coord(5, 1),
+        brush: Brush::Paint(CanvasColor::Red),
+        size: 1,
+    }
+}
+
+async fn next_skribbl_state(client: &mut TestClient) -> SkribblState {
+    client
+        .recv_until(|msg| match msg {
+            ToClientMsg::SkribblStateChanged(state) => Some(state),
+            _ => None,
+        })
+        .await
+}
+
+/// skip chat messages until one with the given text arrives
+async fn expect_chat(client: &mut TestClient, text: &str) {
+    loop {
+        if client.recv_chat().await == text {
+            return;
+        }
+    }
+}
+
+/// connect two players and start a game, returning the drawer, the guesser and the drawers view of the game
+async fn start_game(words: &[&str]) -> (TestClient, TestClient, SkribblState) {
+    let (drawer, guesser, state, _) = start_game_on_server(words).await;
+    (drawer, guesser, state)
+}
+
+/// like `start_game`, also returning the server so more players can join later
+async fn start_game_on_server(
+    words: &[&str],
+) -> (TestClient, TestClient, SkribblState, TestServer) {
+    let server = TestServer::start(words).await;
+    let (mut alice, initial_state) = server.connect("alice").await;
+    assert!(initial_state.skribbl_state.is_none());
+    let (mut bob, _) = server.connect("bob").await;
+
+    alice.say("let's play").await;
+    let state = next_skribbl_state(&mut alice).await;
+    let bob_state = next_skribbl_state(&mut bob).await;
+    assert_eq!(state.drawing_user, bob_state.drawing_user);
+    expect_chat(&mut alice, "alice: let's play").await;
+    expect_chat(&mut bob, "alice: let's play").await;
+
+    if state.drawing_user == alice.username {
+        (alice, bob, state, server)
+    } else {
+        (bob, alice, bob_state, server)
+    }
+}
+
+#[tokio::test]
+async fn outdated_clients_are_told_to_update() {
+    use baseline::{Message, ToClientMsg};
+    use futures_util::{SinkExt, StreamExt};
+
+    let server = TestServer::start(&["polybar"]).await;
+    // connect like the first version did, without telling the server the protocol version
+    let stream = tokio::net::TcpStream::connect(server.addr).await.unwrap();
+    let url = format!("ws://{}", server.addr);
+    let (mut ws, _) = tokio_tungstenite::client_async(url.as_str(), stream)
+        .await
+        .unwrap();
+    ws.send(tungstenite::Message::Text("alice".to_string()))
+        .await
+        .unwrap();
+
+    // the first version unwrapped every message it got, so it must understand all of them
+    let mut received = Vec::new();
+    while let Some(Ok(msg)) = ws.next().await {
+        match msg {
+            tungstenite::Message::Text(msg) => {
+                received.push(serde_json::from_str::<ToClientMsg>(&msg).unwrap())
+            }
+            tungstenite::Message::Close(_) => break,
+            _ => {}
+        }
+    }
+    assert_eq!(received.len(), 2, "{:?}", received);
+    assert!(matches!(
+        &received[0],
+        ToClientMsg::InitialState(state) if state.skribbl_state.is_none()
+    ));
+    assert!(matches!(
+        &received[1],
+        ToClientMsg::NewMessage(Message::SystemMsg(notice)) if notice == OUTDATED_CLIENT_NOTICE
+    ));
+}
+
+#[tokio::test]
+async fn flooding_lines_only_kicks_the_flooding_player() {
+    let (mut drawer, mut guesser, _) = start_game(&["polybar"]).await;
+    let long_line = Line {
+        start: Coord(0, 0),
+        end: Coord(99, 0),
+        ..line()
+    };
+    for _ in 0..120 {
+        drawer.send(ToServerMsg::NewLine(long_line)).await;
+    }
+    let kicked = format!("{} was kicked for flooding", drawer.username);
+    expect_chat(&mut guesser, &kicked).await;
+
+    // the server is still there for everyone else
+    guesser.say("still here").await;
+    let said = format!("{}: still here", guesser.username);
+    expect_chat(&mut guesser, &said).await;
+}
+
+#[tokio::test]
+async fn messages_after_being_kicked_are_ignored() {
+    let server = TestServer::start(&["polybar"]).await;
+    let (mut alice, _) = server.connect("alice").await;
+    let (mut bob, _) = server.connect("bob").await;
+    let oversized = Line {
+        size: 200,
+        ..line()
+    };
+    for _ in 0..5 {
+        alice.send(ToServerMsg::NewLine(oversized)).await;
+    }
+    alice.send(ToServerMsg::RequestCanvasSync).await;
+    expect_chat(&mut bob, "alice was kicked for sending invalid messages").await;
+
+    bob.say("still here").await;
+    expect_chat(&mut bob, "bob: still here").await;
+}
+
+#[tokio::test]
+async fn drawer_lines_are_acknowledged_and_broadcast() {
+    let (mut drawer, mut guesser, _) = start_game(&["polybar"]).await;
+
+    drawer.send(ToServerMsg::NewLine(line())).await;
+    let accepted = drawer
+        .recv_until(|msg| match msg {
+            ToClientMsg::LineAccepted(index, line) => Some((index, line)),
+            _ => None,
+        })
+        .await;
+    assert_eq!(accepted, (0, line()));
+    let drawn = guesser
+        .recv_until(|msg| match msg {
+            ToClientMsg::NewLine(index, line) => Some((index, line)),
+            _ => None,
+        })
+        .await;
+    assert_eq!(drawn, (0, line()));
+
+    guesser.send(ToServerMsg::RequestCanvasSync).await;
+    let lines = guesser
+        .recv_until(|msg| match msg {
+            ToClientMsg::CanvasSync(lines) => Some(lines),
+            _ => None,
+        })
+        .await;
+    assert_eq!(lines, vec![line()]);
+}
+
+#[tokio::test]
+async fn guessers_never_see_the_word() {
+    let server = TestServer::start(&["polybar"]).await;
+    let (mut alice, _) = server.connect("alice").await;
+    let (mut bob, _) = server.connect("bob").await;
+    alice.say("let's play").await;
+    let alice_state = next_skribbl_state(&mut alice).await;
+    let bob_state = next_skribbl_state(&mut bob).await;
+    let (drawer_state, guesser_state) = if alice_state.is_drawing(&alice.username) {
+        (alice_state, bob_state)
+    } else {
+        (bob_state, alice_state)
+    };
+    assert_eq!(drawer_state.current_word(), "polybar");
+    assert_eq!(guesser_state.current_word(), "???????");
+    assert!(!serde_json::to_string(&guesser_state)
+        .unwrap()
+        .contains("polybar"));
+
+    // neither do players that join while the game is running
+    let (_carol, initial_state) = server.connect("carol").await;
+    let carols_state = initial_state.skribbl_state.unwrap();
+    assert_eq!(carols_state.current_word(), "???????");
+}
+
+#[tokio::test]
+async fn guesser_lines_are_rejected() {
+    let (_drawer, mut guesser, _) = start_game(&["polybar"]).await;
+
+    guesser.send(ToServerMsg::NewLine(line())).await;
+    let rejected = guesser
+        .recv_until(|msg| match msg {
+            ToClientMsg::LineAccepted(..) | ToClientMsg::NewLine(..) => Some(false),
+            ToClientMsg::LineRejected => Some(true),
+            _ => None,
+        })
+        .await;
+    assert!(rejected);
+}
+
+#[tokio::test]
+async fn near_misses_are_only_sent_to_the_guesser() {
+    let (mut drawer, mut guesser, _) = start_game(&["polybar"]).await;
+
+    guesser.say("polybat").await;
+    assert_eq!(guesser.recv_chat().await, "You're very close!");
+
+    // the server handles messages in order, so the drawer would have seen the near miss first
+    guesser.say("hello").await;
+    let text = format!("{}: hello", guesser.username);
+    assert_eq!(drawer.recv_chat().await, text);
+}
+
+#[tokio::test]
+async fn drawers_are_only_warned_about_near_misses() {
+    let (mut drawer, mut guesser, _) = start_game(&["polybar"]).await;
+
+    drawer.say("polybat").await;
+    expect_chat(
+        &mut drawer,
+        "Your message was too close to the word, so nobody else saw it",
+    )
+    .await;
+    drawer.say("polybar").await;
+    let warning = format!(
+        "You can't give away the word while drawing (warning 1/{})",
+        MAX_OFFENSES - 1
+    );
+    expect_chat(&mut drawer, &warning).await;
+
+    // the server handles messages in order, so the guesser would have seen them first
+    drawer.say("hello").await;
+    let text = format!("{}: hello", drawer.username);
+    assert_eq!(guesser.recv_chat().await, text);
+}
+
+#[tokio::test]
+async fn events_are_sent_structured() {
+    let (_drawer, mut guesser, _) = start_game(&["polybar"]).await;
+
+    guesser.say("polybat").await;
+    let event = guesser
+        .recv_until(|msg| match msg {
+            ToClientMsg::NewMessage(Message::Event(event)) => Some(event),
+            _ => None,
+        })
+        .await;
+    assert_eq!(event, GameEvent::CloseGuess(Closeness::VeryClose));
+}
+
+#[tokio::test]
+async fn correct_guess_ends_the_turn() {
+    let (mut drawer, mut guesser, state) = start_game(&["polybar"]).await;
+
+    guesser.say(state.current_word()).await;
+    let state = next_skribbl_state(&mut drawer).await;
+    assert!(state.player_states[&guesser.username].score > 0);
+    expect_chat(&mut drawer, &format!("{} guessed it!", guesser.username)).await;
+    let reveal = format!(
+        "The word was: \"polybar\" ({} guessed it in ",
+        guesser.username
+    );
+    while !guesser.recv_chat().await.starts_with(&reveal) {}
+}
+
+#[tokio::test]
+async fn the_leader_can_clear_the_canvas_and_skip_the_turn() {
+    let (mut drawer, mut guesser, _) = start_game(&["polybar", "xmonad"]).await;
+    let leader = if drawer.username.to_string() == "alice" {
+        &mut drawer
+    } else {
+        &mut guesser
+    };
+    leader
+        .send(ToServerMsg::CommandMsg(CommandMsg::ModerateCanvas {
+            skip_turn: true,
+        }))
+        .await;
+
+    let state = next_skribbl_state(&mut guesser).await;
+    assert!(state.is_drawing(&guesser.username));
+    assert_eq!(state.player_states[&drawer.username].score, 0);
+    let msg = format!(
+        "alice cleared the canvas and skipped the turn of {}",
+        drawer.username
+    );
+    expect_chat(&mut drawer, &msg).await;
+}
+
+#[tokio::test]
+async fn guessers_see_where_the_pen_is_until_the_leader_turns_it_off() {
+    let (mut drawer, mut guesser, _) = start_game(&["polybar"]).await;
+    drawer.send(ToServerMsg::PenMoved(Some(Coord(3, 4)))).await;
+    let pos = guesser
+        .recv_until(|msg| match msg {
+            ToClientMsg::PenMoved(pos) => Some(pos),
+            _ => None,
+        })
+        .await;
+    assert_eq!(pos, Some(Coord(3, 4)));
+
+    // positions that come in too fast still end up where the pen stopped
+    drawer.send(ToServerMsg::PenMoved(Some(Coord(4, 4)))).await;
+    drawer.send(ToServerMsg::PenMoved(Some(Coord(6, 4)))).await;
+    guesser
+        .recv_until(|msg| match msg {
+            ToClientMsg::PenMoved(Some(Coord(6, 4))) => Some(()),
+            _ => None,
+        })
+        .await;
+
+    let leader = if drawer.username.to_string() == "alice" {
+        &mut drawer
+    } else {
+        &mut guesser
+    };
+    leader
+        .send(ToServerMsg::CommandMsg(CommandMsg::SetPenSharing(false)))
+        .await;
+    expect_chat(
+        &mut guesser,
+        "Only the drawn lines are shown, not where the pen is",
+    )
+    .await;
+    drawer.send(ToServerMsg::PenMoved(Some(Coord(5, 4)))).await;
+    drawer.say("done moving").await;
+    let pos = guesser
+        .recv_until(|msg| match msg {
+            ToClientMsg::PenMoved(pos) => Some(pos),
+            ToClientMsg::NewMessage(_) => Some(None),
+            _ => None,
+        })
+        .await;
+    assert_eq!(pos, None);
+}
+
+#[tokio::test]
+async fn clients_get_every_message_in_an_envelope() {
+    let server = TestServer::start(&["polybar"]).await;
+    let (mut alice, initial_state) = server.connect("alice").await;
+    assert!(initial_state.envelopes);
+
+    alice.say("hi").await;
+    let text = alice.recv_text().await;
+    assert!(text.starts_with(r#"{"msg":"#), "{}", text);
+    assert!(matches!(
+        crate::message::decode::<ToClientMsg>(&text),
+        Ok(Some(ToClientMsg::NewMessage(_)))
+    ));
+}
+
+#[tokio::test]
+async fn a_single_player_can_practice() {
+    let opts = ServerOpts {
+        min_players: 1,
+        ..TestServer::opts()
+    };
+    let server = TestServer::start_with(&["polybar"], opts).await;
+    let (mut alice, _) = server.connect("alice").await;
+    alice.say("let's practice").await;
+    let state = next_skribbl_state(&mut alice).await;
+    assert!(state.is_drawing(&alice.username));
+}
+
+#[tokio::test]
+async fn servers_without_usable_words_do_not_start() {
+    let file = TempFile::new("no-words", "\n\n");
+    let addrs = vec!["127.0.0.1:0".parse().unwrap()];
+    let result = run_server(
+        addrs,
+        (100, 50),
+        vec![file.path.clone()],
+        TestServer::opts(),
+    )
+    .await;
+    match result {
+        Err(ServerError::WordFiles(err)) => assert_eq!(err.kind(), ErrorKind::InvalidData),
+        _ => panic!("the server started without words"),
+    }
+}
+
+#[tokio::test]
+async fn the_lobby_is_restored_after_a_restart() {
+    let snapshot = LobbySnapshot {
+        leader: Some(Username::from("bob".to_string())),
+        dimensions: (60, 30),
+        difficulties: vec![Difficulty::Easy],
+        language: None,
+        word_lengths: (1, 20),
+        rotation: TurnRotation::Random,
+        drawing_vote: false,
+        round_duration: 60,
+        pen_sharing: true,
+    };
+    let file = TempFile::new("lobby.json", &serde_json::to_string(&snapshot).unwrap());
+    let opts = ServerOpts {
+        state_file: Some(file.path.clone()),
+        ..TestServer::opts()
+    };
+    let server = TestServer::start_with(&["polybar"], opts).await;
+    let (mut alice, initial) = server.connect("alice").await;
+    assert_eq!(initial.dimensions, (60, 30));
+    assert_eq!(initial.leader, Some(alice.username.clone()));
+
+    // the leader from before the restart takes over again once they're back
+    let (bob, initial) = server.connect("bob").await;
+    assert_eq!(initial.leader, Some(bob.username.clone()));
+    let leader = alice
+        .recv_until(|msg| match msg {
+            ToClientMsg::LeaderChanged(leader) => Some(leader),
+            _ => None,
+        })
+        .await;
+    assert_eq!(leader, bob.username);
+}
+
+#[tokio::test]
+async fn settings_nobody_could_choose_are_not_restored() {
+    let snapshot = LobbySnapshot {
+        leader: None,
+        dimensions: (5000, 5000),
+        difficulties: vec![Difficulty::Medium],
+        language: None,
+        word_lengths: (5, 1),
+        rotation: TurnRotation::Random,
+        drawing_vote: false,
+        round_duration: 1,
+        pen_sharing: true,
+    };
+    let file = TempFile::new(
+        "invalid-lobby.json",
+        &serde_json::to_string(&snapshot).unwrap(),
+    );
+    let opts = ServerOpts {
+        state_file: Some(file.path.clone()),
+        min_players: 1,
+        ..TestServer::opts()
+    };
+    let server = TestServer::start_with(&["polybar"], opts).await;
+    let (mut alice, initial) = server.connect("alice").await;
+    assert_eq!(initial.dimensions, (100, 50));
+
+    // turns last as long as they did before, and there are still words to draw
+    alice.say("let's practice").await;
+    next_skribbl_state(&mut alice).await;
+    let remaining = alice
+        .recv_until(|msg| match msg {
+            ToClientMsg::TimeChanged(secs) => Some(secs),
+            _ => None,
+        })
+        .await;
+    assert!(remaining as u64 > ROUND_DURATION - 10);
+}
+
+#[tokio::test]
+async fn games_end_when_too_few_players_are_left() {
+    let (mut drawer, guesser, _) = start_game(&["polybar"]).await;
+    drop(guesser);
+    expect_chat(&mut drawer, "Not enough players left to keep playing").await;
+    drawer
+        .recv_until(|msg| match msg {
+            ToClientMsg::GameOver(..) => Some(()),
+            _ => None,
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn the_longest_connected_player_leads_once_the_leader_leaves() {
+    let server = TestServer::start(&["polybar"]).await;
+    let (alice, initial) = server.connect("alice").await;
+    assert_eq!(initial.leader, Some(alice.username.clone()));
+    let (mut bob, _) = server.connect("bob").await;
+    let (mut carol, _) = server.connect("carol").await;
+
+    drop(alice);
+    let leader = carol
+        .recv_until(|msg| match msg {
+            ToClientMsg::LeaderChanged(leader) => Some(leader),
+            _ => None,
+        })
+        .await;
+    assert_eq!(leader, bob.username);
+    let leader = bob
+        .recv_until(|msg| match msg {
+            ToClientMsg::NewMessage(Message::Event(GameEvent::NewLeader(leader))) => Some(leader),
+            _ => None,
+        })
+        .await;
+    assert_eq!(leader, bob.username);
+
+    let (_, initial) = server.connect("dave").await;
+    assert_eq!(initial.leader, Some(bob.username.clone()));
+}
+
+#[tokio::test]
+async fn the_leader_chooses_how_long_turns_last() {
+    let server = TestServer::start(&["polybar"]).await;
+    let (mut alice, _) = server.connect("alice").await;
+    let (mut bob, _) = server.connect("bob").await;
+
+    alice
+        .send(ToServerMsg::CommandMsg(CommandMsg::SetRoundDuration(5)))
+        .await;
+    expect_chat(&mut alice, "Turns last 30 to 600 seconds").await;
+    alice
+        .send(ToServerMsg::CommandMsg(CommandMsg::SetRoundDuration(60)))
+        .await;
+    expect_chat(&mut bob, "Turns of new games last 60s").await;
+
+    alice.say("let's play").await;
+    let remaining_time = bob
+        .recv_until(|msg| match msg {
+            ToClientMsg::TimeChanged(time) => Some(time),
+            _ => None,
+        })
+        .await;
+    assert!(remaining_time <= 60);
+}
+
+#[tokio::test]
+async fn guessers_can_vote_for_more_time_once_per_turn() {
+    let (mut drawer, mut guesser, _) = start_game(&["polybar"]).await;
+
+    drawer.send(ToServerMsg::VoteExtendTime).await;
+    assert_eq!(
+        drawer.recv_chat().await,
+        "Only the guessers can vote for more time"
+    );
+
+    // with only one guesser, their vote is the majority
+    guesser.send(ToServerMsg::VoteExtendTime).await;
+    let remaining_time = guesser
+        .recv_until(|msg| match msg {
+            ToClientMsg::TimeChanged(time) if time as u64 > ROUND_DURATION => Some(time),
+            _ => None,
+        })
+        .await;
+    assert!(remaining_time as u64 <= ROUND_DURATION + 30);
+    expect_chat(&mut guesser, "The time was extended by 30s").await;
+
+    guesser.send(ToServerMsg::VoteExtendTime).await;
+    expect_chat(&mut guesser, "The time was already extended this turn").await;
+}
+
+#[tokio::test]
+async fn seeded_games_are_reproducible() {
+    let words = ["polybar", "xmonad", "neovim", "alacritty", "rust"];
+    let (first_drawer, _, first) = start_game(&words).await;
+    let (second_drawer, _, second) = start_game(&words).await;
+    assert_eq!(first_drawer.username, second_drawer.username);
+    assert_eq!(first.current_word(), second.current_word());
+}
+
+#[tokio::test]
+async fn players_vote_for_the_best_drawing_when_the_game_ends() {
+    let (mut first_drawer, mut first_guesser, state) = start_game(&["polybar"]).await;
+
+    // let both players draw once
+    first_guesser.say(state.current_word()).await;
+    let state = next_skribbl_state(&mut first_guesser).await;
+    assert_eq!(state.drawing_user, first_guesser.username);
+    first_drawer.say(state.current_word()).await;
+    while next_skribbl_state(&mut first_drawer).await.drawing_user != first_drawer.username {}
+
+    let (mut alice, mut bob) = if first_drawer.username.to_string() == "alice" {
+        (first_drawer, first_guesser)
+    } else {
+        (first_guesser, first_drawer)
+    };
+    alice
+        .send(ToServerMsg::CommandMsg(CommandMsg::EndGame))
+        .await;
+    let drawings = bob
+        .recv_until(|msg| match msg {
+            ToClientMsg::DrawingVoteStarted(drawings) => Some(drawings),
+            _ => None,
+        })
+        .await;
+    assert_eq!(drawings.len(), 2);
+    let alices_drawing = drawings
+        .iter()
+        .position(|drawing| drawing.drawer == alice.username)
+        .unwrap();
+
+    bob.send(ToServerMsg::VoteDrawing(alices_drawing)).await;
+    alice.send(ToServerMsg::VoteDrawing(alices_drawing)).await;
+    expect_chat(&mut alice, "You can't vote for your own drawing").await;
+    alice
+        .send(ToServerMsg::VoteDrawing(1 - alices_drawing))
+        .await;
+
+    let final_state = bob
+        .recv_until(|msg| match msg {
+            ToClientMsg::GameOver(state, _) => Some(state),
+            _ => None,
+        })
+        .await;
+    // both drawings got one vote, so both players get the bonus
+    assert!(final_state.player_states[&alice.username].score >= BEST_DRAWING_BONUS);
+    assert!(final_state.player_states[&bob.username].score >= BEST_DRAWING_BONUS);
+}
+
+#[tokio::test]
+async fn player_stats_are_tracked_during_the_game() {
+    let (mut drawer, mut guesser, state) = start_game(&["polybar"]).await;
+    drawer.send(ToServerMsg::NewLine(line())).await;
+    drawer
+        .recv_until(|msg| match msg {
+            ToClientMsg::LineAccepted(..) => Some(()),
+            _ => None,
+        })
+        .await;
+    guesser.say("xmonad").await;
+    guesser.say(state.current_word()).await;
+    let state = loop {
+        let state = next_skribbl_state(&mut guesser).await;
+        if state.drawing_user == guesser.username {
+            break state;
+        }
+    };
+
+    let drawer_stats = &state.player_states[&drawer.username].stats;
+    assert_eq!(drawer_stats.words_drawn, 1);
+    assert_eq!(drawer_stats.pixels_drawn, line().point_count() as u64);
+    let guesser_stats = &state.player_states[&guesser.username].stats;
+    assert_eq!(guesser_stats.guesses, 2);
+    assert_eq!(guesser_stats.words_guessed, 1);
+    assert!(guesser_stats.average_solve_secs().is_some());
+}
+
+#[tokio::test]
+async fn scores_add_up_over_a_series_of_games() {
+    let (drawer, guesser, state) = start_game(&["polybar"]).await;
+    let (mut alice, mut bob) = if drawer.username.to_string() == "alice" {
+        (drawer, guesser)
+    } else {
+        (guesser, drawer)
+    };
+    alice
+        .send(ToServerMsg::CommandMsg(CommandMsg::SetSeries(Some(2))))
+        .await;
+    expect_chat(
+        &mut bob,
+        "The scores of the next 2 games add up to find the winner of the series",
+    )
+    .await;
+
+    // both players get points once the word was guessed, the drawer gets more
+    let guesser = if state.drawing_user == alice.username {
+        &mut bob
+    } else {
+        &mut alice
+    };
+    guesser.say(state.current_word()).await;
+    let state = next_skribbl_state(guesser).await;
+    let mut scores: Vec<_> = state
+        .player_states
+        .iter()
+        .map(|(username, player)| (username.to_string(), player.score))
+        .collect();
+    scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    assert!(scores[0].1 > scores[1].1);
+
+    alice
+        .send(ToServerMsg::CommandMsg(CommandMsg::EndGame))
+        .await;
+    expect_chat(
+        &mut bob,
+        &format!(
+            "Series standings after game 1/2: {} {}, {} {}",
+            scores[0].0, scores[0].1, scores[1].0, scores[1].1
+        ),
+    )
+    .await;
+
+    alice.say("again").await;
+    next_skribbl_state(&mut bob).await;
+    alice
+        .send(ToServerMsg::CommandMsg(CommandMsg::EndGame))
+        .await;
+    expect_chat(
+        &mut bob,
+        &format!(
+            "{} won the series with {} points!",
+            scores[0].0, scores[0].1
+        ),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn finished_games_can_be_looked_at_later() {
+    let (mut drawer, mut guesser, state) = start_game(&["polybar"]).await;
+    guesser.say(state.current_word()).await;
+    next_skribbl_state(&mut guesser).await;
+    let leader = if drawer.username.to_string() == "alice" {
+        &mut drawer
+    } else {
+        &mut guesser
+    };
+    leader
+        .send(ToServerMsg::CommandMsg(CommandMsg::EndGame))
+        .await;
+    leader
+        .recv_until(|msg| match msg {
+            ToClientMsg::GameOver(..) => Some(()),
+            _ => None,
+        })
+        .await;
+
+    leader.send(ToServerMsg::ListReplays).await;
+    let replays = leader
+        .recv_until(|msg| match msg {
+            ToClientMsg::ReplayList(replays) => Some(replays),
+            _ => None,
+        })
+        .await;
+    assert_eq!(replays.len(), 1);
+    assert_eq!(replays[0].drawings, 1);
+    assert_eq!(replays[0].players.len(), 2);
+
+    leader.send(ToServerMsg::FetchReplay(replays[0].id)).await;
+    let drawings = leader
+        .recv_until(|msg| match msg {
+            ToClientMsg::Replay(_, drawings) => Some(drawings),
+            _ => None,
+        })
+        .await;
+    assert_eq!(drawings[0].word, "polybar");
+
+    leader.send(ToServerMsg::FetchReplay(99)).await;
+    expect_chat(leader, "There is no game #99, see !replays").await;
+}
+
+#[tokio::test]
+async fn spectators_only_chat_with_each_other() {
+    let (mut drawer, mut guesser, _, server) = start_game_on_server(&["polybar"]).await;
+    let (mut carol, _) = server.connect("carol").await;
+    let (mut dave, _) = server.connect("dave").await;
+
+    dave.say("it's a status bar").await;
+    expect_chat(&mut carol, "dave: it's a status bar").await;
+    expect_chat(&mut dave, "dave: it's a status bar").await;
+
+    // the players only get what was said after it
+    guesser.say("a tree?").await;
+    for player in [&mut drawer, &mut guesser].iter_mut() {
+        loop {
+            let msg = player.recv_chat().await;
+            assert_ne!(msg, "dave: it's a status bar");
+            if msg.ends_with("a tree?") {
+                break;
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn late_joiners_wait_for_the_next_round() {
+    let (mut drawer, mut guesser, state, server) = start_game_on_server(&["polybar"]).await;
+    let (mut carol, initial_state) = server.connect("carol").await;
+    let carols_state = initial_state.skribbl_state.unwrap();
+    assert!(carols_state.is_waiting(&carol.username));
+    assert!(!carols_state.player_states.contains_key(&carol.username));
+    expect_chat(
+        &mut carol,
+        "A game is running, you'll join it when the next round begins. \
+         Until then, only other spectators see your messages",
+    )
+    .await;
+
+    carol.say("polybar").await;
+    expect_chat(
+        &mut carol,
+        "You can start guessing when the next round begins",
+    )
+    .await;
+
+    // once both players drew, the round is over and carol gets to play
+    guesser.say(state.current_word()).await;
+    let state = loop {
+        let state = next_skribbl_state(&mut guesser).await;
+        if state.is_drawing(&guesser.username) {
+            break state;
+        }
+    };
+    assert!(state.is_waiting(&carol.username));
+    drawer.say(state.current_word()).await;
+    let state = loop {
+        let state = next_skribbl_state(&mut carol).await;
+        if !state.is_waiting(&carol.username) {
+            break state;
+        }
+    };
+    assert!(state.player_states.contains_key(&carol.username));
+}
+
+/// the messages as the first version of termibbl understood them
+#[allow(dead_code)]
+mod baseline {
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Deserialize)]
+    pub enum ToClientMsg {
+        NewMessage(Message),
+        NewLine(Line),
+        InitialState(InitialState),
+        SkribblStateChanged(SkribblState),
+        GameOver(SkribblState),
+        ClearCanvas,
+        TimeChanged(u32),
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct InitialState {
+        pub lines: Vec<Line>,
+        pub dimensions: (usize, usize),
+        pub skribbl_state: Option<SkribblState>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct SkribblState {
+        current_word: String,
+        revealed_characters: Vec<usize>,
+        pub drawing_user: String,
+        pub remaining_users: Vec<String>,
+        pub player_states: HashMap<String, PlayerState>,
+        pub round_end_time: u64,
+        pub remaining_words: Vec<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct PlayerState {
+        pub score: u32,
+        pub has_solved: bool,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub enum Message {
+        SystemMsg(String),
+        UserMsg(String, String),
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Line {
+        pub start: (u16, u16),
+        pub end: (u16, u16),
+        pub color: String,
+    }
+}
@@ -0,0 +1,136 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// the share of all traffic above which a single client counts as dominating it
+pub const DOMINANT_SHARE: f64 = 0.8;
+
+/// a client only counts as dominating the traffic once it caused at least this many bytes
+pub const DOMINANT_MIN_BYTES: u64 = 100 * 1024;
+
+/// how much was sent to and received from a single client, counted by its connection
+#[derive(Debug, Default)]
+pub struct Traffic {
+    /// messages handed to the connection, which are sent once the ones before them are
+    messages_queued: AtomicU64,
+    messages_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+impl Traffic {
+    pub fn record_queued(&self) {
+        self.messages_queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// how many messages are queued, but weren't sent yet
+    pub fn backlog(&self) -> u64 {
+        let sent = self.messages_sent.load(Ordering::Relaxed);
+        // a message can be sent before it was counted as queued
+        self.messages_queued
+            .load(Ordering::Relaxed)
+            .saturating_sub(sent)
+    }
+
+    pub fn record_sent(&self, bytes: usize) {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_received(&self, bytes: usize) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> TrafficStats {
+        TrafficStats {
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// the counts of a `Traffic` at one point in time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TrafficStats {
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub messages_received: u64,
+    pub bytes_received: u64,
+}
+
+impl TrafficStats {
+    /// what was counted since the counts were `earlier`
+    pub fn since(&self, earlier: &TrafficStats) -> TrafficStats {
+        TrafficStats {
+            messages_sent: self.messages_sent.saturating_sub(earlier.messages_sent),
+            bytes_sent: self.bytes_sent.saturating_sub(earlier.bytes_sent),
+            messages_received: self
+                .messages_received
+                .saturating_sub(earlier.messages_received),
+            bytes_received: self.bytes_received.saturating_sub(earlier.bytes_received),
+        }
+    }
+
+    /// bytes in both directions
+    pub fn bytes(&self) -> u64 {
+        self.bytes_sent + self.bytes_received
+    }
+}
+
+/// the client that caused more than `DOMINANT_SHARE` of all bytes, given the bytes of every client,
+/// together with its bytes and its share of all of them
+pub fn dominating<K>(bytes: impl IntoIterator<Item = (K, u64)>) -> Option<(K, u64, f64)> {
+    let mut total = 0;
+    let mut top: Option<(K, u64)> = None;
+    for (client, client_bytes) in bytes {
+        total += client_bytes;
+        let is_top = match &top {
+            Some((_, top_bytes)) => client_bytes > *top_bytes,
+            None => true,
+        };
+        if is_top {
+            top = Some((client, client_bytes));
+        }
+    }
+    let (client, client_bytes) = top?;
+    let share = client_bytes as f64 / total as f64;
+    if client_bytes >= DOMINANT_MIN_BYTES && share > DOMINANT_SHARE {
+        Some((client, client_bytes, share))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_clients_with_most_of_a_lot_of_traffic_dominate() {
+        let mb = 1024 * 1024;
+        assert_eq!(
+            dominating(vec![("alice", 9 * mb), ("bob", mb / 2), ("carol", mb / 2)]),
+            Some(("alice", 9 * mb, 0.9))
+        );
+        // a drawing player with a single guesser causes most of the traffic, but not that much
+        assert_eq!(dominating(vec![("alice", 2 * mb), ("bob", mb)]), None);
+        assert_eq!(dominating(vec![("alice", 1024)]), None);
+        assert_eq!(dominating(Vec::<(&str, u64)>::new()), None);
+    }
+
+    #[test]
+    fn the_backlog_is_what_was_queued_but_not_sent_yet() {
+        let traffic = Traffic::default();
+        traffic.record_queued();
+        traffic.record_queued();
+        traffic.record_sent(10);
+        assert_eq!(traffic.backlog(), 1);
+        traffic.record_sent(10);
+        traffic.record_sent(10);
+        assert_eq!(traffic.backlog(), 0);
+    }
+}
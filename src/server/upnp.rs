@@ -0,0 +1,235 @@
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+use tokio::net::UdpSocket;
+
+/// multicast address routers listen on for SSDP searches
+const SSDP_ADDR: (Ipv4Addr, u16) = (Ipv4Addr::new(239, 255, 255, 250), 1900);
+
+/// how long to wait for the router to answer the search
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// the services of an internet gateway device that can map ports.
+/// Routers with a DSL modem tend to offer the PPP one.
+const SERVICE_TYPES: [&str; 2] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+
+type Result<T> = std::result::Result<T, UpnpError>;
+
+#[derive(Debug)]
+pub enum UpnpError {
+    /// no router in the network answered, e.g. because UPnP is turned off
+    NoGateway,
+    IOError(std::io::Error),
+    HttpError(reqwest::Error),
+    /// the router answered with something that doesn't look like an internet gateway
+    InvalidGateway(String),
+    /// the router refused to map the port, for the given reason
+    Rejected(String),
+}
+
+impl Display for UpnpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpnpError::NoGateway => write!(f, "no router with UPnP enabled answered"),
+            UpnpError::IOError(err) => write!(f, "{}", err),
+            UpnpError::HttpError(err) => write!(f, "{}", err),
+            UpnpError::InvalidGateway(reason) => {
+                write!(f, "unexpected answer of the router: {}", reason)
+            }
+            UpnpError::Rejected(reason) => write!(f, "the router refused: {}", reason),
+        }
+    }
+}
+
+impl From<std::io::Error> for UpnpError {
+    fn from(err: std::io::Error) -> Self {
+        UpnpError::IOError(err)
+    }
+}
+
+impl From<reqwest::Error> for UpnpError {
+    fn from(err: reqwest::Error) -> Self {
+        UpnpError::HttpError(err)
+    }
+}
+
+/// a port the router forwards to this machine
+#[derive(Debug)]
+pub struct PortMapping {
+    /// the address players outside of the local network can connect to
+    pub external: SocketAddr,
+    service: &'static str,
+    control_url: reqwest::Url,
+}
+
+impl PortMapping {
+    /// ask the router to stop forwarding the port
+    pub async fn remove(&self) -> Result<()> {
+        let args = format!(
+            "<NewRemoteHost></NewRemoteHost>\
+             <NewExternalPort>{port}</NewExternalPort>\
+             <NewProtocol>TCP</NewProtocol>",
+            port = self.external.port(),
+        );
+        soap_request(&self.control_url, self.service, "DeletePortMapping", &args).await?;
+        Ok(())
+    }
+}
+
+/// ask the router to forward TCP connections on `port` to this machine.
+/// The mapping has no lease time, so it stays until `PortMapping::remove` is called
+/// or the router restarts.
+pub async fn map_port(port: u16) -> Result<PortMapping> {
+    let (location, gateway) = find_gateway().await?;
+    let description = reqwest::get(&location).await?.text().await?;
+    let (service, control_url) = find_control_url(&description)
+        .ok_or_else(|| UpnpError::InvalidGateway("no port mapping service".to_string()))?;
+    let control_url = reqwest::Url::parse(&location)
+        .and_then(|base| base.join(control_url))
+        .map_err(|err| UpnpError::InvalidGateway(err.to_string()))?;
+
+    // asked first, so nothing is left forwarded if the router doesn't tell
+    let response = soap_request(&control_url, service, "GetExternalIPAddress", "").await?;
+    let external_ip = tag_text(&response, "NewExternalIPAddress")
+        .and_then(|ip| ip.trim().parse::<IpAddr>().ok())
+        .ok_or_else(|| UpnpError::InvalidGateway("no external IP address".to_string()))?;
+
+    let local_ip = local_ip_towards(gateway)?;
+    let args = format!(
+        "<NewRemoteHost></NewRemoteHost>\
+         <NewExternalPort>{port}</NewExternalPort>\
+         <NewProtocol>TCP</NewProtocol>\
+         <NewInternalPort>{port}</NewInternalPort>\
+         <NewInternalClient>{ip}</NewInternalClient>\
+         <NewEnabled>1</NewEnabled>\
+         <NewPortMappingDescription>Termibbl</NewPortMappingDescription>\
+         <NewLeaseDuration>0</NewLeaseDuration>",
+        port = port,
+        ip = local_ip,
+    );
+    soap_request(&control_url, service, "AddPortMapping", &args).await?;
+    Ok(PortMapping {
+        external: SocketAddr::new(external_ip, port),
+        service,
+        control_url,
+    })
+}
+
+/// search the local network for an internet gateway device, returning the URL of its
+/// description and its address
+async fn find_gateway() -> Result<(String, IpAddr)> {
+    let mut socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let search = "M-SEARCH * HTTP/1.1\r\n\
+                  HOST: 239.255.255.250:1900\r\n\
+                  MAN: \"ssdp:discover\"\r\n\
+                  MX: 2\r\n\
+                  ST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\r\n";
+    socket.send_to(search.as_bytes(), SSDP_ADDR).await?;
+
+    let mut buf = [0; 2048];
+    let (len, peer) = tokio::time::timeout(SEARCH_TIMEOUT, socket.recv_from(&mut buf))
+        .await
+        .map_err(|_| UpnpError::NoGateway)??;
+    let answer = String::from_utf8_lossy(&buf[..len]);
+    let location = answer
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if name.eq_ignore_ascii_case("location") => {
+                    Some(value.trim().to_string())
+                }
+                _ => None,
+            }
+        })
+        .next()
+        .ok_or_else(|| UpnpError::InvalidGateway("no location in the search answer".to_string()))?;
+    Ok((location, peer.ip()))
+}
+
+/// find a port mapping service in the description of a gateway, returning its type and control URL
+fn find_control_url(description: &str) -> Option<(&'static str, &str)> {
+    SERVICE_TYPES.iter().find_map(|service| {
+        let start = description.find(&format!("<serviceType>{}</serviceType>", service))?;
+        let control_url = tag_text(&description[start..], "controlURL")?;
+        Some((*service, control_url.trim()))
+    })
+}
+
+/// the address of this machine in the network of the gateway, which the router should forward to
+fn local_ip_towards(gateway: IpAddr) -> Result<IpAddr> {
+    // connecting a UDP socket doesn't send anything, it only picks the route
+    let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect((gateway, SSDP_ADDR.1))?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// call an action of a gateway service, returning the body of its answer
+async fn soap_request(
+    control_url: &reqwest::Url,
+    service: &str,
+    action: &str,
+    args: &str,
+) -> Result<String> {
+    let body = format!(
+        "<?xml version=\"1.0\"?>\
+         <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+         s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+         <s:Body><u:{action} xmlns:u=\"{service}\">{args}</u:{action}></s:Body>\
+         </s:Envelope>",
+        action = action,
+        service = service,
+        args = args,
+    );
+    let response = reqwest::Client::new()
+        .post(control_url.clone())
+        .header("Content-Type", "text/xml; charset=\"utf-8\"")
+        .header("SOAPAction", format!("\"{}#{}\"", service, action))
+        .body(body)
+        .send()
+        .await?;
+    let status = response.status();
+    let text = response.text().await?;
+    if status.is_success() {
+        Ok(text)
+    } else {
+        let reason = tag_text(&text, "errorDescription").unwrap_or_else(|| status.as_str());
+        Err(UpnpError::Rejected(reason.to_string()))
+    }
+}
+
+/// the text between the first `<tag>` and the following `</tag>`
+fn tag_text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let len = xml[start..].find(&format!("</{}>", tag))?;
+    Some(&xml[start..start + len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_control_url_of_the_port_mapping_service() {
+        let description = "<root><device><serviceList>\
+            <service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>\
+            <controlURL>/l3f</controlURL></service>\
+            <service><serviceType>urn:schemas-upnp-org:service:WANPPPConnection:1</serviceType>\
+            <controlURL> /upnp/control/WANPPPConn1 </controlURL></service>\
+            </serviceList></device></root>";
+        assert_eq!(
+            find_control_url(description),
+            Some((
+                "urn:schemas-upnp-org:service:WANPPPConnection:1",
+                "/upnp/control/WANPPPConn1"
+            ))
+        );
+        assert_eq!(find_control_url("<root></root>"), None);
+    }
+}
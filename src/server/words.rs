@@ -0,0 +1,322 @@
+use crate::data::Difficulty;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// longest word that still fits into the hint shown above the canvas
+pub const MAX_WORD_LENGTH: usize = 30;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Word {
+    pub text: String,
+    pub difficulty: Difficulty,
+    /// language code of the word pack, like "en" or "de". Untagged words fit any language.
+    pub language: Option<String>,
+    /// a short explanation of the word, shown to everyone once it was revealed
+    #[serde(default)]
+    pub definition: Option<String>,
+}
+
+/// what happened while loading the word files, to be reported at startup
+#[derive(Debug, Default)]
+pub struct WordStats {
+    pub files: Vec<(PathBuf, usize)>,
+    pub duplicates: usize,
+    /// how many words were skipped because they are in the blocked words file
+    pub blocked: usize,
+    pub too_long: Vec<String>,
+    /// words that appear in more than one file, with the file they were first seen in
+    pub shared: Vec<(String, PathBuf, PathBuf)>,
+    pub difficulties: HashMap<Difficulty, usize>,
+    pub languages: HashMap<String, usize>,
+}
+
+impl WordStats {
+    fn per_difficulty(&self, difficulty: Difficulty) -> usize {
+        self.difficulties.get(&difficulty).cloned().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WordList {
+    pub words: Vec<Word>,
+    pub stats: WordStats,
+}
+
+impl WordList {
+    /// read and clean up the given word files. Words are trimmed and lowercased,
+    /// duplicates, words longer than `MAX_WORD_LENGTH` and the `blocked` words are dropped.
+    ///
+    /// A line like `[easy]`, `[medium]` or `[hard]` sets the difficulty of the words below it,
+    /// words before any such line are medium. A line like `[language: de]` sets their language.
+    /// Words can be written as `word|hint|definition` to come with a definition.
+    /// Fails if no words are left, since no game could be played with them.
+    pub fn load(paths: &[PathBuf], blocked: &HashSet<String>) -> std::io::Result<WordList> {
+        let mut list = WordList::default();
+        let mut seen_in: HashMap<String, PathBuf> = HashMap::new();
+        for path in paths {
+            let mut seen_in_file = HashSet::new();
+            let mut count = 0;
+            for word in read_words_file(path)? {
+                let Word {
+                    text: word,
+                    difficulty,
+                    language,
+                    definition,
+                } = word;
+                let word = word.to_lowercase();
+                if blocked.contains(&word) {
+                    list.stats.blocked += 1;
+                    continue;
+                }
+                if word.chars().count() > MAX_WORD_LENGTH {
+                    list.stats.too_long.push(word);
+                    continue;
+                }
+                if !seen_in_file.insert(word.clone()) {
+                    list.stats.duplicates += 1;
+                    continue;
+                }
+                match seen_in.get(&word) {
+                    Some(first) => {
+                        list.stats.duplicates += 1;
+                        list.stats.shared.push((word, first.clone(), path.clone()));
+                    }
+                    None => {
+                        seen_in.insert(word.clone(), path.clone());
+                        *list.stats.difficulties.entry(difficulty).or_insert(0) += 1;
+                        if let Some(language) = &language {
+                            *list.stats.languages.entry(language.clone()).or_insert(0) += 1;
+                        }
+                        list.words.push(Word {
+                            text: word,
+                            difficulty,
+                            language,
+                            definition,
+                        });
+                        count += 1;
+                    }
+                }
+            }
+            list.stats.files.push((path.clone(), count));
+        }
+        if list.words.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "no usable words in the word files ({} blocked, {} too long)",
+                    list.stats.blocked,
+                    list.stats.too_long.len()
+                ),
+            ));
+        }
+        Ok(list)
+    }
+}
+
+/// read the words that should never come up from a file with one word per line, lowercased
+/// so they match words of any case. Without a file, no words are blocked.
+pub fn read_blocked_words(path: Option<&Path>) -> std::io::Result<HashSet<String>> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(HashSet::new()),
+    };
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .map(|line| line.trim().to_lowercase())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+/// the words with one of the given difficulties, in the given language or untagged,
+/// with at least `lengths.0` and at most `lengths.1` characters
+pub fn pick_words(
+    words: &[Word],
+    difficulties: &[Difficulty],
+    language: Option<&str>,
+    lengths: (usize, usize),
+) -> Vec<Word> {
+    words
+        .iter()
+        .filter(|word| difficulties.contains(&word.difficulty))
+        .filter(|word| {
+            let length = word.text.chars().count();
+            lengths.0 <= length && length <= lengths.1
+        })
+        .filter(|word| match (language, &word.language) {
+            (Some(language), Some(word_language)) => language == word_language,
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+impl Display for WordStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (path, count) in &self.files {
+            writeln!(f, "{}: {} words", path.display(), count)?;
+        }
+        for word in &self.too_long {
+            writeln!(
+                f,
+                "warning: skipping \"{}\", it's longer than {} characters",
+                word, MAX_WORD_LENGTH
+            )?;
+        }
+        for (word, first, second) in &self.shared {
+            writeln!(
+                f,
+                "warning: \"{}\" is in both {} and {}",
+                word,
+                first.display(),
+                second.display()
+            )?;
+        }
+        if !self.languages.is_empty() {
+            let mut languages = self
+                .languages
+                .iter()
+                .map(|(language, count)| format!("{} ({})", language, count))
+                .collect::<Vec<_>>();
+            languages.sort();
+            writeln!(f, "languages: {}", languages.join(", "))?;
+        }
+        if self.blocked > 0 {
+            writeln!(f, "skipped {} blocked words", self.blocked)?;
+        }
+        write!(
+            f,
+            "{} words in total ({} easy, {} medium, {} hard), skipped {} duplicates and {} words that are too long",
+            self.files.iter().map(|(_, count)| count).sum::<usize>(),
+            self.per_difficulty(Difficulty::Easy),
+            self.per_difficulty(Difficulty::Medium),
+            self.per_difficulty(Difficulty::Hard),
+            self.duplicates,
+            self.too_long.len()
+        )
+    }
+}
+
+/// read the words on the non-empty lines of a word file, with the difficulty and language
+/// of their section
+fn read_words_file(path: &Path) -> std::io::Result<Vec<Word>> {
+    let mut file = std::fs::File::open(path)?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    let mut difficulty = Difficulty::Medium;
+    let mut language = None;
+    let mut words = Vec::new();
+    for line in content.lines().map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        if line.starts_with('[') && line.ends_with(']') {
+            let section = &line[1..line.len() - 1];
+            if section.starts_with("language:") {
+                let code = section
+                    .trim_start_matches("language:")
+                    .trim()
+                    .to_lowercase();
+                language = Some(code).filter(|code| !code.is_empty());
+                continue;
+            } else if let Ok(section) = section.parse() {
+                difficulty = section;
+                continue;
+            }
+        }
+        // the hint column isn't used yet, but skipping it keeps the definition in its place
+        let mut columns = line.split('|').map(|column| column.trim());
+        let text = columns.next().unwrap_or_default().to_string();
+        if text.is_empty() {
+            continue;
+        }
+        let definition = columns
+            .nth(1)
+            .filter(|definition| !definition.is_empty())
+            .map(|definition| definition.to_string());
+        words.push(Word {
+            text,
+            difficulty,
+            language: language.clone(),
+            definition,
+        });
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempFile;
+
+    #[test]
+    fn reads_definitions() {
+        let file = TempFile::new(
+            "words",
+            "Polybar\nxmonad | window manager | a tiling window manager\n",
+        );
+        let list = WordList::load(std::slice::from_ref(&file.path), &HashSet::new());
+
+        let words = list.unwrap().words;
+        assert_eq!(words[0].text, "polybar");
+        assert_eq!(words[0].definition, None);
+        assert_eq!(words[1].text, "xmonad");
+        assert_eq!(
+            words[1].definition.as_deref(),
+            Some("a tiling window manager")
+        );
+    }
+
+    #[test]
+    fn skips_blocked_words() {
+        let file = TempFile::new("blocked", "Polybar\nxmonad\nDwm\n");
+        let blocked = ["XMonad", "dwm"].iter().map(|word| word.to_lowercase());
+        let list = WordList::load(std::slice::from_ref(&file.path), &blocked.collect());
+
+        let list = list.unwrap();
+        let words: Vec<_> = list.words.iter().map(|word| word.text.as_str()).collect();
+        assert_eq!(words, ["polybar"]);
+        assert_eq!(list.stats.blocked, 2);
+    }
+
+    #[test]
+    fn fails_when_every_word_is_blocked() {
+        let file = TempFile::new("all-blocked", "Polybar\nxmonad\n");
+        let blocked = ["polybar", "xmonad"].iter().map(|word| word.to_string());
+        let list = WordList::load(std::slice::from_ref(&file.path), &blocked.collect());
+
+        let err = list.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("2 blocked"));
+    }
+
+    #[test]
+    fn fails_without_usable_words() {
+        let file = TempFile::new(
+            "too-long",
+            &format!("\n{}\n", "x".repeat(MAX_WORD_LENGTH + 1)),
+        );
+        let list = WordList::load(std::slice::from_ref(&file.path), &HashSet::new());
+
+        let err = list.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("1 too long"));
+    }
+
+    #[test]
+    fn picks_words_by_length() {
+        let words: Vec<Word> = ["dwm", "polybar", "ice cream"]
+            .iter()
+            .map(|text| Word {
+                text: text.to_string(),
+                difficulty: Difficulty::Medium,
+                language: None,
+                definition: None,
+            })
+            .collect();
+        let picked = pick_words(&words, &Difficulty::ALL, None, (4, 8));
+        assert_eq!(picked.len(), 1);
+        assert_eq!(picked[0].text, "polybar");
+    }
+}
@@ -0,0 +1,204 @@
+//! helpers for tests that run a real server and talk to it like a client would
+
+use crate::{
+    client::{
+        app::{App, ServerSession},
+        config::Config,
+    },
+    data::{Difficulty, Username},
+    message::{self, Heartbeat, InitialState, ToClientMsg, ToServerMsg},
+    server::{
+        server::{serve, ServerOpts, ROUND_DURATION},
+        words::Word,
+    },
+};
+use futures_util::{SinkExt, StreamExt};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::WebSocketStream;
+use tui::buffer::Buffer;
+
+/// how long to wait for a message before failing the test
+const RECV_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+}
+
+impl TestServer {
+    /// start a server on a free port, playing with the given words.
+    /// The seed is fixed, so every test game plays out the same way, and games start right away.
+    pub async fn start(words: &[&str]) -> TestServer {
+        TestServer::start_with(words, TestServer::opts()).await
+    }
+
+    /// the options `start` uses
+    pub fn opts() -> ServerOpts {
+        ServerOpts {
+            queue_size: 256,
+            seed: Some(0),
+            start_countdown: Duration::from_secs(0),
+            min_players: 2,
+            round_duration: ROUND_DURATION,
+            heartbeat: Heartbeat::default(),
+            lan_discovery: false,
+            status_port: None,
+            stats_dir: None,
+            blocked_words: None,
+            state_file: None,
+        }
+    }
+
+    /// like `start`, with different options
+    pub async fn start_with(words: &[&str], opts: ServerOpts) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let words = words
+            .iter()
+            .map(|word| Word {
+                text: word.to_string(),
+                difficulty: Difficulty::Medium,
+                language: None,
+                definition: None,
+            })
+            .collect();
+        tokio::spawn(serve(
+            vec![listener],
+            (100, 50),
+            Some(words),
+            Vec::new(),
+            opts,
+            std::future::pending(),
+        ));
+        TestServer { addr }
+    }
+
+    /// connect a new client, returning it together with the initial state it got
+    pub async fn connect(&self, username: &str) -> (TestClient, InitialState) {
+        let stream = TcpStream::connect(self.addr).await.unwrap();
+        let url = format!("ws://{}", self.addr);
+        let request = message::connect_request(&url).unwrap();
+        let (ws, _) = tokio_tungstenite::client_async(request, stream)
+            .await
+            .unwrap();
+        let mut client = TestClient {
+            username: Username::from(username.to_string()),
+            ws,
+        };
+        client
+            .ws
+            .send(tungstenite::Message::Text(username.to_string()))
+            .await
+            .unwrap();
+        let initial_state = client
+            .recv_until(|msg| match msg {
+                ToClientMsg::InitialState(state) => Some(state),
+                _ => None,
+            })
+            .await;
+        (client, initial_state)
+    }
+}
+
+/// a scripted client, speaking the same protocol as the real one
+pub struct TestClient {
+    pub username: Username,
+    ws: WebSocketStream<TcpStream>,
+}
+
+impl TestClient {
+    pub async fn send(&mut self, msg: ToServerMsg) {
+        let msg = message::encode(&msg);
+        self.ws.send(tungstenite::Message::Text(msg)).await.unwrap();
+    }
+
+    /// send a chat message as this client
+    pub async fn say(&mut self, text: &str) {
+        let msg = crate::data::Message::UserMsg(self.username.clone(), text.to_string());
+        self.send(ToServerMsg::NewMessage(msg)).await;
+    }
+
+    /// receive the next message as it was sent, failing the test if none arrives in time
+    pub async fn recv_text(&mut self) -> String {
+        loop {
+            let msg = tokio::time::timeout(RECV_TIMEOUT, self.ws.next())
+                .await
+                .unwrap_or_else(|_| panic!("{} didn't receive a message in time", self.username))
+                .expect("connection closed")
+                .unwrap();
+            if let tungstenite::Message::Text(msg) = msg {
+                return msg;
+            }
+        }
+    }
+
+    /// receive the next message, failing the test if none arrives in time
+    pub async fn recv(&mut self) -> ToClientMsg {
+        loop {
+            if let Some(msg) = message::decode(&self.recv_text().await).unwrap() {
+                return msg;
+            }
+        }
+    }
+
+    /// skip messages until `f` returns something for one of them
+    pub async fn recv_until<T>(&mut self, mut f: impl FnMut(ToClientMsg) -> Option<T>) -> T {
+        loop {
+            if let Some(result) = f(self.recv().await) {
+                return result;
+            }
+        }
+    }
+
+    /// skip messages until a chat message arrives, returning its text
+    pub async fn recv_chat(&mut self) -> String {
+        self.recv_until(|msg| match msg {
+            ToClientMsg::NewMessage(msg) => Some(msg.to_string()),
+            _ => None,
+        })
+        .await
+    }
+}
+
+/// a file in the temp directory that is removed again once it's dropped, even if the test failed
+pub struct TempFile {
+    pub path: PathBuf,
+}
+
+impl TempFile {
+    /// write `contents` to a file called `name`, unique to this test run.
+    /// Tests running at the same time need to use different names.
+    pub fn new(name: &str, contents: &str) -> TempFile {
+        let path = std::env::temp_dir().join(format!("termibbl-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        TempFile { path }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// an app in the given state, as if it had just joined a game, rendered for a terminal of `terminal_size`
+pub fn offline_app(username: &str, initial_state: InitialState, terminal_size: (u16, u16)) -> App {
+    let session = ServerSession::offline(Username::from(username.to_string()));
+    let mut app = App::new(session, initial_state);
+    // don't depend on the config file of whoever runs the tests
+    app.config = Config::default();
+    app.canvas.terminal_size = terminal_size;
+    app
+}
+
+/// the text of every row of a rendered buffer, ignoring colors
+pub fn buffer_lines(buffer: &Buffer) -> Vec<String> {
+    let area = buffer.area();
+    (0..area.height)
+        .map(|y| {
+            (0..area.width)
+                .map(|x| buffer.get(x, y).symbol.as_str())
+                .collect::<String>()
+        })
+        .collect()
+}